@@ -0,0 +1,19 @@
+#![no_main]
+use gif_me_hd::decoder::lzw;
+use libfuzzer_sys::fuzz_target;
+
+// The first byte is the minimum code size, the rest is the code stream
+fuzz_target!(|data: &[u8]| {
+    let Some((&minimum_code_size, compressed)) = data.split_first() else {
+        return;
+    };
+    let decompressed = lzw::decompress(compressed.to_vec(), minimum_code_size);
+    let mut codes = 0;
+    let traced = lzw::decompress_traced(
+        compressed.to_vec(),
+        minimum_code_size,
+        usize::MAX,
+        &mut |_| codes += 1,
+    );
+    assert_eq!(decompressed, traced.map(|traced| traced.index_stream));
+});
@@ -0,0 +1,7 @@
+#![no_main]
+use gif_me_hd_fuzz::{exercise_decoder, ArbitraryGif};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|gif: ArbitraryGif| {
+    exercise_decoder(&gif.to_bytes());
+});
@@ -0,0 +1,27 @@
+#![no_main]
+use gif_me_hd::decoder::fuzzing;
+use libfuzzer_sys::fuzz_target;
+
+// The first byte picks the parser, the rest is its input
+fuzz_target!(|data: &[u8]| {
+    let Some((&parser, bytes)) = data.split_first() else {
+        return;
+    };
+    match parser % 6 {
+        0 => drop(fuzzing::parse_header(bytes)),
+        1 => drop(fuzzing::parse_logical_screen(bytes)),
+        2 => drop(fuzzing::parse_extension(bytes)),
+        3 => drop(fuzzing::parse_image_descriptor(bytes)),
+        4 => drop(fuzzing::parse_data_block(bytes)),
+        _ => {
+            // Both walk the same sub-blocks, so they must agree. Their errors
+            // differ (kind and position), only whether they failed is compared.
+            let parsed = fuzzing::parse_data_block(bytes);
+            let skipped = fuzzing::skip_data_block(bytes);
+            match (parsed, skipped) {
+                (Ok((rest, block)), Ok(skipped)) => assert_eq!((rest, block.len()), skipped),
+                (parsed, skipped) => assert_eq!(parsed.is_ok(), skipped.is_ok()),
+            }
+        }
+    }
+});
@@ -0,0 +1,262 @@
+//! Structure-aware input generation for the fuzz targets.
+//!
+//! Random bytes almost never get past the header, so `ArbitraryGif` builds
+//! sequences of blocks that are valid most of the time (with real LZW data)
+//! and only occasionally broken, which gets the fuzzer deep into the decoder.
+
+use arbitrary::Arbitrary;
+use gif_me_hd::decoder::{self, DecodeLimits, GifFile};
+use gif_me_hd::encoder::lzw::compress;
+use gif_me_hd::render::{self, RenderOptions};
+use gif_me_hd::validate;
+
+const EXTENSION_INTRODUCER: u8 = 0x21;
+const IMAGE_SEPARATOR: u8 = 0x2C;
+const TRAILER: u8 = 0x3B;
+
+// Canvas and frame sizes are kept small so that inputs stay fast to decode
+#[derive(Debug, Arbitrary)]
+pub struct ArbitraryGif {
+    // One in four files gets the `Raw` blocks, corruptions, missing trailer and
+    // garbage below, the rest are kept valid.
+    pub damage: u8,
+    pub gif87a: bool,
+    pub canvas_width: u8,
+    pub canvas_height: u8,
+    // Color resolution and sort flag bits of the packed field
+    pub packed_field: u8,
+    pub global_color_table: Option<ArbitraryColorTable>,
+    pub background_color_index: u8,
+    pub pixel_aspect_ratio: u8,
+    pub blocks: Vec<ArbitraryBlock>,
+    pub trailer: bool,
+    // Written after everything else
+    pub garbage: Vec<u8>,
+}
+
+#[derive(Debug, Arbitrary)]
+pub struct ArbitraryColorTable {
+    // Only the low 3 bits are used
+    pub size: u8,
+    pub colors: Vec<u8>,
+}
+
+#[derive(Debug, Arbitrary)]
+pub enum ArbitraryBlock {
+    GraphicsControl {
+        packed_field: u8,
+        delay_timer: u16,
+        transparent_color_index: u8,
+    },
+    PlainText {
+        header: [u8; 12],
+        text: Vec<u8>,
+    },
+    Application {
+        identifier: [u8; 11],
+        data: Vec<u8>,
+    },
+    Comment(Vec<u8>),
+    Image(ArbitraryImage),
+    // Anything at all where a block should be
+    Raw(Vec<u8>),
+}
+
+#[derive(Debug, Arbitrary)]
+pub struct ArbitraryImage {
+    pub left: u8,
+    pub top: u8,
+    pub width: u8,
+    pub height: u8,
+    // Interlace, sort and reserved bits of the packed field
+    pub packed_field: u8,
+    pub local_color_table: Option<ArbitraryColorTable>,
+    // Only values 2 to 8 are used, unless `raw_minimum_code_size` is set
+    pub minimum_code_size: u8,
+    pub raw_minimum_code_size: Option<u8>,
+    // Repeated to fill the frame
+    pub indices: Vec<u8>,
+    // (position, new value) of a byte to overwrite in the compressed data
+    pub corruption: Option<(u16, u8)>,
+    // Length of the sub-blocks, 0 means 255
+    pub sub_block_length: u8,
+}
+
+fn write_sub_blocks(out: &mut Vec<u8>, data: &[u8], sub_block_length: u8) {
+    let sub_block_length = if sub_block_length == 0 {
+        255
+    } else {
+        sub_block_length as usize
+    };
+    for chunk in data.chunks(sub_block_length) {
+        out.push(chunk.len() as u8);
+        out.extend_from_slice(chunk);
+    }
+    out.push(0x00);
+}
+
+impl ArbitraryColorTable {
+    fn size(&self) -> u8 {
+        self.size & 0b111
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        let len = 3 << (self.size() + 1);
+        out.extend((0..len).map(|i| self.colors.get(i).copied().unwrap_or(i as u8)));
+    }
+}
+
+impl ArbitraryImage {
+    fn write(&self, out: &mut Vec<u8>, damaged: bool) {
+        out.push(IMAGE_SEPARATOR);
+        for value in [self.left, self.top, self.width, self.height] {
+            out.extend((value as u16).to_le_bytes());
+        }
+        let packed_field = match &self.local_color_table {
+            Some(lct) => 0x80 | lct.size(),
+            None => 0,
+        } | (self.packed_field & 0b0111_1000);
+        out.push(packed_field);
+        if let Some(lct) = &self.local_color_table {
+            lct.write(out);
+        }
+
+        let minimum_code_size = 2 + self.minimum_code_size % 7;
+        let max_index = ((1u16 << minimum_code_size) - 1) as u8;
+        let pixels = self.width as usize * self.height as usize;
+        let indices: Vec<u8> = match self.indices.is_empty() {
+            true => vec![0; pixels],
+            false => (0..pixels)
+                .map(|i| self.indices[i % self.indices.len()] & max_index)
+                .collect(),
+        };
+        let mut compressed = compress(&indices, minimum_code_size);
+        let mut code_size = minimum_code_size;
+        if damaged {
+            if let Some((position, value)) = self.corruption {
+                let len = compressed.len();
+                compressed[position as usize % len] = value;
+            }
+            code_size = self.raw_minimum_code_size.unwrap_or(minimum_code_size);
+        }
+        out.push(code_size);
+        write_sub_blocks(out, &compressed, self.sub_block_length);
+    }
+}
+
+impl ArbitraryBlock {
+    fn write(&self, out: &mut Vec<u8>, damaged: bool) {
+        match self {
+            ArbitraryBlock::GraphicsControl {
+                packed_field,
+                delay_timer,
+                transparent_color_index,
+            } => {
+                out.extend([EXTENSION_INTRODUCER, 0xF9, 0x04, *packed_field]);
+                out.extend(delay_timer.to_le_bytes());
+                out.extend([*transparent_color_index, 0x00]);
+            }
+            ArbitraryBlock::PlainText { header, text } => {
+                out.extend([EXTENSION_INTRODUCER, 0x01, 0x0C]);
+                out.extend(header);
+                write_sub_blocks(out, text, 0);
+            }
+            ArbitraryBlock::Application { identifier, data } => {
+                out.extend([EXTENSION_INTRODUCER, 0xFF, 0x0B]);
+                out.extend(identifier);
+                write_sub_blocks(out, data, 0);
+            }
+            ArbitraryBlock::Comment(text) => {
+                out.extend([EXTENSION_INTRODUCER, 0xFE]);
+                write_sub_blocks(out, text, 0);
+            }
+            ArbitraryBlock::Image(image) => image.write(out, damaged),
+            ArbitraryBlock::Raw(bytes) if damaged => out.extend(bytes),
+            ArbitraryBlock::Raw(_) => {}
+        }
+    }
+}
+
+impl ArbitraryGif {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend(if self.gif87a { b"GIF87a" } else { b"GIF89a" });
+        out.extend((self.canvas_width as u16).to_le_bytes());
+        out.extend((self.canvas_height as u16).to_le_bytes());
+        let packed_field = match &self.global_color_table {
+            Some(gct) => 0x80 | gct.size(),
+            None => 0,
+        } | (self.packed_field & 0b0111_1000);
+        out.extend([
+            packed_field,
+            self.background_color_index,
+            self.pixel_aspect_ratio,
+        ]);
+        if let Some(gct) = &self.global_color_table {
+            gct.write(&mut out);
+        }
+        let damaged = self.damage.is_multiple_of(4);
+        for block in &self.blocks {
+            block.write(&mut out, damaged);
+        }
+        if !self
+            .blocks
+            .iter()
+            .any(|b| matches!(b, ArbitraryBlock::Image(_)))
+        {
+            // Files need at least one image
+            out.extend([
+                IMAGE_SEPARATOR,
+                0,
+                0,
+                0,
+                0,
+                1,
+                0,
+                1,
+                0,
+                0,
+                0x02,
+                0x02,
+                0x44,
+                0x01,
+                0x00,
+            ]);
+        }
+        if self.trailer || !damaged {
+            out.push(TRAILER);
+        }
+        if damaged {
+            out.extend(&self.garbage);
+        }
+        out
+    }
+}
+
+// Canvases bigger than this aren't rendered, to keep each run fast
+const MAX_RENDERED_PIXELS: usize = 1 << 16;
+
+/// Runs `bytes` through every part of the crate that reads untrusted input.
+/// None of them may panic, whatever the input.
+pub fn exercise_decoder(bytes: &[u8]) {
+    let _ = gif_me_hd::sniff(bytes);
+    let _ = gif_me_hd::probe(bytes);
+    let _ = decoder::dump(bytes).to_string();
+    let _ = decoder::compressed_frames(bytes);
+    let _ = GifFile::stats(bytes, &DecodeLimits::default());
+    let _ = validate::validate_bytes(bytes);
+
+    let gif_file = match GifFile::new(bytes) {
+        Ok(gif_file) => gif_file,
+        Err(_) => return,
+    };
+    let lsd = &gif_file.logical_screen_descriptor;
+    if lsd.canvas_width as usize * lsd.canvas_height as usize <= MAX_RENDERED_PIXELS {
+        let _ = render::render(&gif_file, RenderOptions::default());
+    }
+    // Whatever was decoded must be encodable, and decodable again
+    let encoded = gif_file.to_bytes();
+    if let Err(err) = GifFile::new(&encoded) {
+        panic!("Unable to decode re-encoded file: {}", err);
+    }
+}
@@ -0,0 +1,577 @@
+use super::parser::{
+    parse_extension, parse_global_color_table, parse_header, parse_image_descriptor,
+    parse_local_color_table, parse_logical_screen_descriptor, parse_sub_blocks,
+};
+use super::{Extension, GifHeader, ImageDescriptor, LogicalScreenDescriptor, ParseError, Pixel};
+use nom::bytes::complete::take;
+use nom::number::complete::le_u8;
+use nom::IResult;
+use std::fmt;
+
+const EXTENSION_INTRODUCER: u8 = 0x21;
+const IMAGE_SEPARATOR: u8 = 0x2C;
+const TRAILER: u8 = 0x3B;
+
+const PLAIN_TEXT_LABEL: u8 = 0x01;
+const GRAPHICS_CONTROL_LABEL: u8 = 0xF9;
+const COMMENT_LABEL: u8 = 0xFE;
+const APPLICATION_LABEL: u8 = 0xFF;
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Block {
+    Header(GifHeader),
+    LogicalScreenDescriptor(LogicalScreenDescriptor),
+    GlobalColorTable(Vec<Pixel>),
+    Extension {
+        label: u8,
+        extension: Extension,
+        sub_blocks: usize,
+        data_length: usize,
+    },
+    // An extension with a known label that doesn't parse, with every one of
+    // its sub-blocks as they are in the file
+    MalformedExtension {
+        label: u8,
+        sub_blocks: Vec<Vec<u8>>,
+    },
+    ImageDescriptor(ImageDescriptor),
+    LocalColorTable(Vec<Pixel>),
+    ImageData {
+        lzw_minimum_code_size: u8,
+        sub_blocks: usize,
+        compressed_length: usize,
+    },
+    Trailer,
+    // Anything after the trailer
+    TrailingData,
+}
+
+impl Block {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Block::Header(_) => "Header",
+            Block::LogicalScreenDescriptor(_) => "Logical Screen Descriptor",
+            Block::GlobalColorTable(_) => "Global Color Table",
+            Block::Extension { label, .. } | Block::MalformedExtension { label, .. } => {
+                match *label {
+                    GRAPHICS_CONTROL_LABEL => "Graphics Control Extension",
+                    COMMENT_LABEL => "Comment Extension",
+                    APPLICATION_LABEL => "Application Extension",
+                    PLAIN_TEXT_LABEL => "Plain Text Extension",
+                    _ => "Unknown Extension",
+                }
+            }
+            Block::ImageDescriptor(_) => "Image Descriptor",
+            Block::LocalColorTable(_) => "Local Color Table",
+            Block::ImageData { .. } => "Image Data",
+            Block::Trailer => "Trailer",
+            Block::TrailingData => "Trailing Data",
+        }
+    }
+}
+
+/// A block of a GIF file and where it is in the file.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DumpedBlock {
+    pub offset: usize,
+    // Including the introducer/label bytes and all sub-blocks
+    pub length: usize,
+    pub block: Block,
+}
+
+/// Every block of a GIF file in order. Dumping stops at the first block that
+/// can't be parsed, `error` then says what went wrong and `error_offset` where.
+#[derive(Debug, PartialEq)]
+pub struct GifDump {
+    pub blocks: Vec<DumpedBlock>,
+    pub error: Option<ParseError>,
+    pub error_offset: usize,
+}
+
+// (number of sub-blocks, total data length) of a run of sub-blocks, not
+// counting the block terminator.
+fn measure_data_block(mut bytes: &[u8]) -> IResult<&[u8], (usize, usize)> {
+    let mut sub_blocks = 0;
+    let mut total = 0;
+    loop {
+        let (rest, subblock_length) = le_u8(bytes)?;
+        let (rest, _) = take(subblock_length)(rest)?;
+        bytes = rest;
+        if subblock_length == 0 {
+            return Ok((bytes, (sub_blocks, total)));
+        }
+        sub_blocks += 1;
+        total += subblock_length as usize;
+    }
+}
+
+// Known extensions that don't parse are still walked over sub-block by
+// sub-block, a dump is most useful for broken files after all.
+fn dump_extension(bytes: &[u8]) -> IResult<&[u8], Block> {
+    let (rest, label) = le_u8(&bytes[1..])?;
+    let (rest, block) = match parse_extension(bytes) {
+        Ok((_, extension)) => {
+            let (rest, (sub_blocks, data_length)) = measure_data_block(rest)?;
+            let block = Block::Extension {
+                label,
+                extension,
+                sub_blocks,
+                data_length,
+            };
+            (rest, block)
+        }
+        Err(_) => {
+            let (rest, sub_blocks) = parse_sub_blocks(rest)?;
+            (rest, Block::MalformedExtension { label, sub_blocks })
+        }
+    };
+    Ok((rest, block))
+}
+
+fn dump_image_data(bytes: &[u8]) -> IResult<&[u8], Block> {
+    let (bytes, lzw_minimum_code_size) = le_u8(bytes)?;
+    let (bytes, (sub_blocks, compressed_length)) = measure_data_block(bytes)?;
+    Ok((
+        bytes,
+        Block::ImageData {
+            lzw_minimum_code_size,
+            sub_blocks,
+            compressed_length,
+        },
+    ))
+}
+
+struct Dumper<'a> {
+    bytes: &'a [u8],
+    rest: &'a [u8],
+    blocks: Vec<DumpedBlock>,
+}
+
+impl<'a> Dumper<'a> {
+    fn offset(&self) -> usize {
+        self.bytes.len() - self.rest.len()
+    }
+
+    // Records `block` as everything between the current position and `rest`
+    fn advance(&mut self, rest: &'a [u8], block: Block) {
+        let offset = self.offset();
+        self.rest = rest;
+        self.blocks.push(DumpedBlock {
+            offset,
+            length: self.offset() - offset,
+            block,
+        });
+    }
+
+    fn dump_image(&mut self) -> Result<(), ParseError> {
+        let (rest, descriptor) = parse_image_descriptor(self.rest)
+            .map_err(|_| ParseError::InvalidBlock("Image Descriptor"))?;
+        let (after_lct, lct) = parse_local_color_table(rest, &descriptor)
+            .map_err(|_| ParseError::InvalidBlock("Local Color Table"))?;
+        self.advance(rest, Block::ImageDescriptor(descriptor));
+        if let Some(lct) = lct {
+            self.advance(after_lct, Block::LocalColorTable(lct));
+        }
+        let (rest, block) =
+            dump_image_data(self.rest).map_err(|_| ParseError::InvalidBlock("Image Data"))?;
+        self.advance(rest, block);
+        Ok(())
+    }
+
+    fn dump(&mut self) -> Result<(), ParseError> {
+        let (rest, header) =
+            parse_header(self.rest).map_err(|_| ParseError::invalid_header(self.bytes))?;
+        self.advance(rest, Block::Header(header));
+        let (rest, lsd) = parse_logical_screen_descriptor(self.rest)
+            .map_err(|_| ParseError::InvalidBlock("Logical Screen Descriptor"))?;
+        let (after_gct, gct) = parse_global_color_table(rest, &lsd)
+            .map_err(|_| ParseError::InvalidBlock("Global Color Table"))?;
+        self.advance(rest, Block::LogicalScreenDescriptor(lsd));
+        if let Some(gct) = gct {
+            self.advance(after_gct, Block::GlobalColorTable(gct));
+        }
+
+        loop {
+            match self.rest.first() {
+                Some(&EXTENSION_INTRODUCER) => {
+                    let (rest, block) = dump_extension(self.rest)
+                        .map_err(|_| ParseError::InvalidBlock("Extension"))?;
+                    self.advance(rest, block);
+                }
+                Some(&IMAGE_SEPARATOR) => self.dump_image()?,
+                Some(&TRAILER) => {
+                    self.advance(&self.rest[1..], Block::Trailer);
+                    if !self.rest.is_empty() {
+                        self.advance(&[], Block::TrailingData);
+                    }
+                    return Ok(());
+                }
+                Some(_) => return Err(ParseError::InvalidBlock("Block")),
+                None => return Err(ParseError::InvalidBlock("Trailer")),
+            }
+        }
+    }
+}
+
+/// Walks over every block of a GIF file, recording its offset and length,
+/// without decompressing any image data.
+pub fn dump(bytes: &[u8]) -> GifDump {
+    let mut dumper = Dumper {
+        bytes,
+        rest: bytes,
+        blocks: Vec::new(),
+    };
+    let error = dumper.dump().err();
+    GifDump {
+        error_offset: dumper.offset(),
+        blocks: dumper.blocks,
+        error,
+    }
+}
+
+fn yes_no(flag: bool) -> &'static str {
+    if flag {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+fn hex_color(pixel: &Pixel) -> String {
+    format!("#{:02X}{:02X}{:02X}", pixel.red, pixel.green, pixel.blue)
+}
+
+fn fmt_color_table(f: &mut fmt::Formatter, color_table: &[Pixel]) -> fmt::Result {
+    const COLORS_PER_LINE: usize = 8;
+    write!(f, "{} colors", color_table.len())?;
+    for (line, colors) in color_table.chunks(COLORS_PER_LINE).enumerate() {
+        write!(f, "\n    {:3}:", line * COLORS_PER_LINE)?;
+        for color in colors {
+            write!(f, " {}", hex_color(color))?;
+        }
+    }
+    Ok(())
+}
+
+fn fmt_extension(f: &mut fmt::Formatter, extension: &Extension) -> fmt::Result {
+    match extension {
+        Extension::GraphicsControlExtension {
+            reserved,
+            disposal_method,
+            user_input_flag,
+            transparent_color_flag,
+            delay_timer,
+            transparent_color_index,
+        } => write!(
+            f,
+            "\n    disposal method: {:?}, user input: {}, delay: {}, transparent index: {}, reserved: {}",
+            disposal_method,
+            yes_no(*user_input_flag),
+            delay_timer,
+            if *transparent_color_flag {
+                transparent_color_index.to_string()
+            } else {
+                "none".into()
+            },
+            reserved
+        ),
+        Extension::Application {
+            identifier,
+            authentication_code,
+            ..
+        } => write!(
+            f,
+            "\n    identifier: {:?}{:?}",
+            String::from_utf8_lossy(identifier),
+            String::from_utf8_lossy(authentication_code)
+        ),
+        Extension::Comment { text } => {
+            write!(f, "\n    text: {:?}", String::from_utf8_lossy(text))
+        }
+        Extension::PlainText {
+            text_grid_left,
+            text_grid_top,
+            text_grid_width,
+            text_grid_height,
+            cell_width,
+            cell_height,
+            text_foreground_color_index,
+            text_background_color_index,
+            text,
+        } => write!(
+            f,
+            "\n    grid: {},{} {}x{}, cell: {}x{}, foreground index: {}, background index: {}\n    text: {:?}",
+            text_grid_left,
+            text_grid_top,
+            text_grid_width,
+            text_grid_height,
+            cell_width,
+            cell_height,
+            text_foreground_color_index,
+            text_background_color_index,
+            String::from_utf8_lossy(text)
+        ),
+        // The label and sub-blocks are all there is to say
+        Extension::Unknown { .. } => Ok(()),
+    }
+}
+
+impl fmt::Display for DumpedBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#08x} {:>6} bytes  {}",
+            self.offset,
+            self.length,
+            self.block.name()
+        )?;
+        match &self.block {
+            Block::Header(header) => write!(f, ": {:?}", header),
+            Block::LogicalScreenDescriptor(lsd) => write!(
+                f,
+                "\n    canvas: {}x{}, global color table: {}, color resolution: {}, sorted: {}, \
+                 table size: {}, background index: {}, pixel aspect ratio: {}",
+                lsd.canvas_width,
+                lsd.canvas_height,
+                yes_no(lsd.global_color_table_flag),
+                lsd.color_resolution,
+                yes_no(lsd.sort_flag),
+                lsd.global_color_table_size,
+                lsd.background_color_index,
+                lsd.pixel_aspect_ratio
+            ),
+            Block::GlobalColorTable(color_table) | Block::LocalColorTable(color_table) => {
+                write!(f, ": ")?;
+                fmt_color_table(f, color_table)
+            }
+            Block::Extension {
+                label,
+                extension,
+                sub_blocks,
+                data_length,
+            } => {
+                write!(
+                    f,
+                    " ({:#04x}): {} sub-blocks, {} data bytes",
+                    label, sub_blocks, data_length
+                )?;
+                fmt_extension(f, extension)
+            }
+            Block::MalformedExtension { label, sub_blocks } => {
+                write!(
+                    f,
+                    " ({:#04x}): malformed, {} sub-blocks",
+                    label,
+                    sub_blocks.len()
+                )?;
+                for sub_block in sub_blocks {
+                    write!(f, "\n    {:3} bytes:", sub_block.len())?;
+                    for byte in sub_block {
+                        write!(f, " {:02X}", byte)?;
+                    }
+                }
+                Ok(())
+            }
+            Block::ImageDescriptor(descriptor) => write!(
+                f,
+                "\n    position: {},{}, size: {}x{}, local color table: {}, interlaced: {}, \
+                 sorted: {}, table size: {}, reserved: {}",
+                descriptor.left,
+                descriptor.top,
+                descriptor.width,
+                descriptor.height,
+                yes_no(descriptor.local_color_table_flag),
+                yes_no(descriptor.interlace_flag),
+                yes_no(descriptor.sort_flag),
+                descriptor.local_color_table_size,
+                descriptor.reserved
+            ),
+            Block::ImageData {
+                lzw_minimum_code_size,
+                sub_blocks,
+                compressed_length,
+            } => write!(
+                f,
+                ": LZW minimum code size {}, {} sub-blocks, {} compressed bytes",
+                lzw_minimum_code_size, sub_blocks, compressed_length
+            ),
+            Block::Trailer | Block::TrailingData => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for GifDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for block in &self.blocks {
+            writeln!(f, "{}", block)?;
+        }
+        if let Some(error) = &self.error {
+            writeln!(f, "{:#08x} {}", self.error_offset, error)?;
+        }
+        Ok(())
+    }
+}
+
+// What `GifDump::to_json` writes, the error as its message
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct JsonDump<'a> {
+    blocks: &'a [DumpedBlock],
+    error: Option<JsonError>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct JsonError {
+    offset: usize,
+    message: String,
+}
+
+#[cfg(feature = "serde")]
+impl GifDump {
+    /// The dump as a JSON object, with the same blocks as the text output.
+    pub fn to_json(&self) -> String {
+        let json_dump = JsonDump {
+            blocks: &self.blocks,
+            error: self.error.as_ref().map(|error| JsonError {
+                offset: self.error_offset,
+                message: error.to_string(),
+            }),
+        };
+        // Nothing in there can fail to serialize
+        serde_json::to_string(&json_dump).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::fixtures::{GCE, HEADER, IMAGE, LOGICAL_SCREEN, TRAILER_BLOCK};
+
+    const PLAIN_TEXT: &[u8] = &[
+        0x21, 0x01, 0x0C, 0, 0, 0, 0, 0x0A, 0, 0x0A, 0, 8, 8, 1, 0, 0x02, b'h', b'i', 0x00,
+    ];
+
+    fn summary(gif_dump: &GifDump) -> Vec<(usize, usize, &'static str)> {
+        gif_dump
+            .blocks
+            .iter()
+            .map(|block| (block.offset, block.length, block.block.name()))
+            .collect()
+    }
+
+    #[test]
+    fn dump_blocks() {
+        let data = [
+            HEADER,
+            LOGICAL_SCREEN,
+            GCE,
+            PLAIN_TEXT,
+            IMAGE,
+            TRAILER_BLOCK,
+        ]
+        .concat();
+        let gif_dump = dump(&data);
+        assert_eq!(gif_dump.error, None);
+        assert_eq!(
+            summary(&gif_dump),
+            vec![
+                (0, 6, "Header"),
+                (6, 7, "Logical Screen Descriptor"),
+                (13, 12, "Global Color Table"),
+                (25, 8, "Graphics Control Extension"),
+                (33, 19, "Plain Text Extension"),
+                (52, 10, "Image Descriptor"),
+                (62, 25, "Image Data"),
+                (87, 1, "Trailer"),
+            ]
+        );
+        assert_eq!(
+            gif_dump.blocks[4].block,
+            Block::Extension {
+                label: 0x01,
+                extension: Extension::PlainText {
+                    text_grid_left: 0,
+                    text_grid_top: 0,
+                    text_grid_width: 10,
+                    text_grid_height: 10,
+                    cell_width: 8,
+                    cell_height: 8,
+                    text_foreground_color_index: 1,
+                    text_background_color_index: 0,
+                    text: "hi".into(),
+                },
+                sub_blocks: 2,
+                data_length: 14,
+            }
+        );
+        assert_eq!(
+            gif_dump.blocks[6].block,
+            Block::ImageData {
+                lzw_minimum_code_size: 2,
+                sub_blocks: 1,
+                compressed_length: 22,
+            }
+        );
+    }
+
+    #[test]
+    fn dump_truncated_file() {
+        let data = [HEADER, LOGICAL_SCREEN, &IMAGE[..20]].concat();
+        let gif_dump = dump(&data);
+        assert_eq!(gif_dump.blocks.len(), 4);
+        assert_eq!(gif_dump.error, Some(ParseError::InvalidBlock("Image Data")));
+        assert_eq!(gif_dump.error_offset, 35);
+    }
+
+    #[test]
+    fn dump_malformed_extension() {
+        // Graphics Control Extension with a 5 byte block instead of 4
+        const BROKEN_GCE: &[u8] = &[0x21, 0xF9, 0x05, 0x01, 0x0A, 0x00, 0x00, 0x00, 0x00];
+        let data = [HEADER, LOGICAL_SCREEN, BROKEN_GCE, IMAGE, TRAILER_BLOCK].concat();
+        let gif_dump = dump(&data);
+        assert_eq!(gif_dump.error, None);
+        assert_eq!(gif_dump.blocks.len(), 7);
+        assert_eq!(
+            gif_dump.blocks[3],
+            DumpedBlock {
+                offset: 25,
+                length: 9,
+                block: Block::MalformedExtension {
+                    label: 0xF9,
+                    sub_blocks: vec![vec![0x01, 0x0A, 0x00, 0x00, 0x00]],
+                },
+            }
+        );
+        assert!(gif_dump.to_string().contains(
+            "Graphics Control Extension (0xf9): malformed, 1 sub-blocks\n      5 bytes: 01 0A 00 00 00"
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn dump_json() {
+        let data = [HEADER, LOGICAL_SCREEN, GCE, IMAGE, TRAILER_BLOCK].concat();
+        let json: serde_json::Value = serde_json::from_str(&dump(&data).to_json()).unwrap();
+        assert_eq!(json["blocks"].as_array().unwrap().len(), 7);
+        assert_eq!(
+            json["blocks"][0],
+            serde_json::json!({"offset": 0, "length": 6, "block": {"Header": "GIF89a"}})
+        );
+        assert_eq!(
+            json["blocks"][3]["block"]["Extension"]["extension"]["GraphicsControlExtension"]
+                ["delay_timer"],
+            10
+        );
+        assert_eq!(json["error"], serde_json::Value::Null);
+
+        let data = [HEADER, LOGICAL_SCREEN, &IMAGE[..20]].concat();
+        let json: serde_json::Value = serde_json::from_str(&dump(&data).to_json()).unwrap();
+        assert_eq!(
+            json["error"],
+            serde_json::json!({"offset": 35, "message": "Unable to parse Image Data!"})
+        );
+    }
+}
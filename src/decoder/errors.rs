@@ -1,16 +1,116 @@
+use super::lzw::DecompressError;
+use super::{sniff, DetectedFormat, LimitKind};
 use std::fmt;
-pub enum ParseError{
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
     // Name of the given GIF magic
     InvalidGifMagic(String),
+    // The file is something else entirely (PNG, HTML error page, ...)
+    NotAGif {
+        detected: DetectedFormat,
+    },
+    // Name of the block that could not be parsed
+    InvalidBlock(&'static str),
+    Decompress(DecompressError),
+    // One of the `DecodeLimits` was hit
+    LimitExceeded {
+        limit: LimitKind,
+        max: u64,
+        actual: u64,
+    },
+    // Name of the file
+    UnableToLoadFile(String),
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "An Error Occurred")
+        use ParseError::*;
+        match self {
+            InvalidGifMagic(magic) => write!(f, "Invalid GIF magic {:?}!", magic),
+            NotAGif { detected } => write!(f, "Not a GIF file, detected {}!", detected),
+            InvalidBlock(block) => write!(f, "Unable to parse {}!", block),
+            Decompress(err) => write!(f, "Unable to decompress image data: {:?}", err),
+            LimitExceeded { limit, max, actual } => write!(
+                f,
+                "Decode limit exceeded for {:?}! Max is {} and found {}!",
+                limit, max, actual
+            ),
+            UnableToLoadFile(filename) => write!(f, "Unable to load file {}!", filename),
+        }
+    }
+}
+
+impl ParseError {
+    // Error for a file that doesn't start with a GIF87a or GIF89a header
+    pub(super) fn invalid_header(bytes: &[u8]) -> ParseError {
+        match sniff(bytes) {
+            DetectedFormat::Unknown | DetectedFormat::Gif if bytes.starts_with(b"GIF") => {
+                ParseError::InvalidGifMagic(
+                    String::from_utf8_lossy(&bytes[..bytes.len().min(6)]).into(),
+                )
+            }
+            detected => ParseError::NotAGif { detected },
+        }
+    }
+}
+
+impl From<DecompressError> for ParseError {
+    fn from(err: DecompressError) -> Self {
+        ParseError::Decompress(err)
     }
 }
-impl fmt::Debug for ParseError {
+
+/// Problems in a file that the decoder was able to work around.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DecodeWarning {
+    // The Logical Screen Descriptor declares a width or height of 0
+    ZeroSizedCanvas,
+    // Index of the frame whose Image Descriptor goes past the declared canvas
+    FrameOutsideCanvas(usize),
+    // The canvas was changed because of the `CanvasMode`
+    CanvasResized {
+        width: u16,
+        height: u16,
+    },
+    // The header says GIF87a, but extensions (or other GIF89a features) are used
+    Gif89aFeaturesInGif87a,
+    // Index of the frame with leftover image data after its End Of Information Code
+    DataAfterEndOfInformation(usize),
+    // More indices than width * height, the extra ones were dropped
+    PixelStreamTooLong {
+        frame: usize,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for DecodeWarning {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "An Error Occurred")
+        use DecodeWarning::*;
+        match self {
+            ZeroSizedCanvas => write!(f, "Logical Screen has a width or height of 0"),
+            FrameOutsideCanvas(frame) => write!(f, "Frame {} extends past the canvas", frame),
+            CanvasResized { width, height } => {
+                write!(f, "Canvas was resized to {}x{}", width, height)
+            }
+            Gif89aFeaturesInGif87a => write!(f, "GIF87a file uses GIF89a features"),
+            DataAfterEndOfInformation(frame) => {
+                write!(
+                    f,
+                    "Frame {} has data after the End Of Information Code",
+                    frame
+                )
+            }
+            PixelStreamTooLong {
+                frame,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Frame {} has {} indices for {} pixels, the extra ones were dropped",
+                frame, actual, expected
+            ),
+        }
     }
 }
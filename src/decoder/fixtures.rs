@@ -0,0 +1,40 @@
+// Small GIF files and blocks shared by the tests
+
+// 10x10 image with a 4 color Global Color Table, two frames
+pub(crate) const SAMPLE_GIF: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x0A, 0x00, 0x0A, 0x00, 0x91, 0x00, 0x00, 0xFF, 0xFF, 0xFF,
+    0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00, 0x00, 0x21, 0xF9, 0x04, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x2C, 0x00, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x0A, 0x00, 0x00, 0x02, 0x16, 0x8C, 0x2D, 0x99,
+    0x87, 0x2A, 0x1C, 0xDC, 0x33, 0xA0, 0x02, 0x75, 0xEC, 0x95, 0xFA, 0xA8, 0xDE, 0x60, 0x8C, 0x04,
+    0x91, 0x4C, 0x01, 0x00, 0x2C, 0x00, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x0A, 0x00, 0x00, 0x02, 0x16,
+    0x8C, 0x2D, 0x99, 0x87, 0x2A, 0x1C, 0xDC, 0x33, 0xA0, 0x02, 0x75, 0xEC, 0x95, 0xFA, 0xA8, 0xDE,
+    0x60, 0x8C, 0x04, 0x91, 0x4C, 0x01, 0x00, 0x3B,
+];
+
+// The blocks of SAMPLE_GIF, to put together differently
+pub(crate) const HEADER: &[u8] = b"GIF89a";
+// 10x10 canvas with a 4 color Global Color Table
+pub(crate) const LOGICAL_SCREEN: &[u8] = &[
+    0x0A, 0x00, 0x0A, 0x00, 0x91, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF,
+    0x00, 0x00, 0x00,
+];
+// Delay of 0.1s with transparency
+pub(crate) const GCE: &[u8] = &[0x21, 0xF9, 0x04, 0x01, 0x0A, 0x00, 0x00, 0x00];
+// 10x10 frame using the Global Color Table
+pub(crate) const IMAGE: &[u8] = &[
+    0x2C, 0x00, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x0A, 0x00, 0x00, 0x02, 0x16, 0x8C, 0x2D, 0x99, 0x87,
+    0x2A, 0x1C, 0xDC, 0x33, 0xA0, 0x02, 0x75, 0xEC, 0x95, 0xFA, 0xA8, 0xDE, 0x60, 0x8C, 0x04, 0x91,
+    0x4C, 0x01, 0x00,
+];
+pub(crate) const TRAILER_BLOCK: &[u8] = &[0x3B];
+
+// Small xorshift generator, so that random test data is reproducible
+pub(crate) fn xorshift(seed: u32) -> impl FnMut() -> u32 {
+    let mut state = seed;
+    move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    }
+}
@@ -0,0 +1,135 @@
+use super::{ImageDescriptor, LogicalScreenDescriptor, ParseError};
+
+/// Resource limits that are checked while decoding, so that untrusted files
+/// (e.g. a tiny file declaring a 65535x65535 canvas) can not make the decoder
+/// allocate an unbounded amount of memory. `None` disables that check.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DecodeLimits {
+    // canvas_width * canvas_height of the Logical Screen Descriptor
+    pub max_canvas_pixels: Option<u64>,
+    pub max_frame_count: Option<usize>,
+    // Sum of the decompressed index streams of every frame
+    pub max_total_decoded_bytes: Option<u64>,
+    // width * height of every Image Descriptor, and length of its index stream
+    pub max_frame_pixels: Option<u64>,
+    // Decompressed bytes per compressed byte of a single frame
+    pub max_compression_ratio: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LimitKind {
+    CanvasPixels,
+    FrameCount,
+    TotalDecodedBytes,
+    FramePixels,
+    CompressionRatio,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            // 8192x8192
+            max_canvas_pixels: Some(1 << 26),
+            max_frame_count: Some(10_000),
+            max_total_decoded_bytes: Some(1 << 30),
+            max_frame_pixels: Some(1 << 26),
+            // A 12 bit code can never expand to more than 4096 bytes,
+            // so valid files have a ratio well below this anyway.
+            max_compression_ratio: None,
+        }
+    }
+}
+
+fn check(limit: LimitKind, max: Option<u64>, actual: u64) -> Result<(), ParseError> {
+    match max {
+        Some(max) if actual > max => Err(ParseError::LimitExceeded { limit, max, actual }),
+        _ => Ok(()),
+    }
+}
+
+impl DecodeLimits {
+    /// No limits at all, only use this for trusted input.
+    pub const fn none() -> Self {
+        DecodeLimits {
+            max_canvas_pixels: None,
+            max_frame_count: None,
+            max_total_decoded_bytes: None,
+            max_frame_pixels: None,
+            max_compression_ratio: None,
+        }
+    }
+
+    pub(super) fn check_canvas(&self, lsd: &LogicalScreenDescriptor) -> Result<(), ParseError> {
+        let pixels = lsd.canvas_width as u64 * lsd.canvas_height as u64;
+        check(LimitKind::CanvasPixels, self.max_canvas_pixels, pixels)
+    }
+
+    pub(super) fn check_frame_count(&self, frame_count: usize) -> Result<(), ParseError> {
+        check(
+            LimitKind::FrameCount,
+            self.max_frame_count.map(|x| x as u64),
+            frame_count as u64,
+        )
+    }
+
+    pub(super) fn check_frame(&self, image_descriptor: &ImageDescriptor) -> Result<(), ParseError> {
+        let pixels = image_descriptor.width as u64 * image_descriptor.height as u64;
+        check(LimitKind::FramePixels, self.max_frame_pixels, pixels)
+    }
+
+    // The most bytes the LZW decoder is allowed to output for a single frame,
+    // along with the limit that is responsible for it.
+    pub(super) fn max_decoded_len(
+        &self,
+        compressed_len: usize,
+        total_decoded: u64,
+    ) -> (u64, LimitKind) {
+        let frame = self
+            .max_frame_pixels
+            .map(|max| (max, LimitKind::FramePixels));
+        let total = self.max_total_decoded_bytes.map(|max| {
+            (
+                max.saturating_sub(total_decoded),
+                LimitKind::TotalDecodedBytes,
+            )
+        });
+        let ratio = self.max_compression_ratio.map(|max| {
+            (
+                max.saturating_mul(compressed_len as u64),
+                LimitKind::CompressionRatio,
+            )
+        });
+        [frame, total, ratio]
+            .into_iter()
+            .flatten()
+            .reduce(|a, b| if a.0 <= b.0 { a } else { b })
+            .unwrap_or((u64::MAX, LimitKind::FramePixels))
+    }
+
+    // Turns an exceeded output length from the LZW decoder into the matching error
+    pub(super) fn exceeded(
+        &self,
+        (max, limit): (u64, LimitKind),
+        compressed_len: usize,
+        total_decoded: u64,
+        decoded_len: u64,
+    ) -> ParseError {
+        match limit {
+            LimitKind::CompressionRatio => ParseError::LimitExceeded {
+                limit,
+                max: self.max_compression_ratio.unwrap_or(0),
+                actual: decoded_len / (compressed_len.max(1) as u64),
+            },
+            LimitKind::FramePixels => ParseError::LimitExceeded {
+                limit,
+                max,
+                actual: decoded_len,
+            },
+            _ => ParseError::LimitExceeded {
+                limit,
+                max: self.max_total_decoded_bytes.unwrap_or(0),
+                actual: total_decoded + decoded_len,
+            },
+        }
+    }
+}
@@ -13,7 +13,7 @@ impl fmt::Display for CodeParseError {
         use CodeParseError::{CodeTooBig, MinCodeSizeInvalid};
         match self {
             CodeTooBig(value, min_code_size) => {
-                let max = (2 as u16).pow(*min_code_size as u32);
+                let max = 2_u16.pow(*min_code_size as u32);
                 write!(
                     f,
                     "LZW Code too big! Max is {} and entered is {}!",
@@ -27,10 +27,6 @@ impl fmt::Display for CodeParseError {
                     min_code_size
                 )
             }
-            _ => {
-                // Should never reach here
-                unimplemented!("Unknown CodeParseError occurred!");
-            }
         }
     }
 }
@@ -40,7 +36,7 @@ impl fmt::Debug for CodeParseError {
         write!(f, "{{ file: {}, line: {} }}", file!(), line!())?;
         match self {
             CodeTooBig(value, min_code_size) => {
-                let max = (2 as u16).pow(*min_code_size as u32);
+                let max = 2_u16.pow(*min_code_size as u32);
                 write!(
                     f,
                     "LZW Code too big! Max is {} and entered is {}!",
@@ -54,10 +50,6 @@ impl fmt::Debug for CodeParseError {
                     min_code_size
                 )
             }
-            _ => {
-                // Should never reach here
-                unimplemented!("Unknown CodeParseError occurred!");
-            }
         }
     }
 }
@@ -65,4 +57,9 @@ impl fmt::Debug for CodeParseError {
 #[derive(Debug, PartialEq)]
 pub enum DecompressError {
     KeyDoesNotExist,
+    InvalidCode(CodeParseError),
+    // The data ran out before an End Of Information Code was read
+    UnexpectedEndOfStream,
+    // Number of indices decoded when the limit was hit
+    OutputLimitExceeded(usize),
 }
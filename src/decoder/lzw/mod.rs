@@ -1,27 +1,32 @@
 mod errors;
+mod trace;
 mod types;
 use bitter::{BitReader, LittleEndianReader};
-use errors::*;
+pub use errors::*;
+pub use trace::*;
 use types::*;
 use types::{Code, SpecialCode};
-fn create_inverse_code_table(minimum_code_size: u8) -> InvCodeTable {
+
+// Codes are at most 12 bits, so the table can never hold more than 4096 entries.
+const MAX_CODE_SIZE: u32 = 12;
+const MAX_TABLE_SIZE: usize = 1 << MAX_CODE_SIZE;
+
+fn create_inverse_code_table(minimum_code_size: u8) -> Result<InvCodeTable, CodeParseError> {
     use InvCode::*;
     use SpecialCode::*;
+    if !(2..=8).contains(&minimum_code_size) {
+        return Err(CodeParseError::MinCodeSizeInvalid(minimum_code_size));
+    }
     let mut ret = InvCodeTable::new();
-    for i in 0..((2 as u32).pow(minimum_code_size.into())) {
-        // TO-DO: Return Error here instead?
-        ret.push(CodeList(vec![Code::from(
-            i as u16,
-            minimum_code_size.into(),
-        )
-        .unwrap()]));
+    for i in 0..(2_u32.pow(minimum_code_size.into())) {
+        ret.push(CodeList(vec![Code::from(i as u16, minimum_code_size)?]));
     }
     ret.push(ControlCode(ClearCodeInv));
     ret.push(ControlCode(EoiCodeInv));
-    ret
+    Ok(ret)
 }
 
-fn lift_code_to_u8(codes: &Vec<Code>) -> Vec<&u8> {
+fn lift_code_to_u8(codes: &[Code]) -> Vec<&u8> {
     codes
         .iter()
         .map(|x| match x {
@@ -42,141 +47,199 @@ pub fn decompress(
     compressed_data: Vec<u8>,
     minimum_code_size: u8,
 ) -> Result<Vec<u8>, DecompressError> {
-    let mut inv_code_table = create_inverse_code_table(minimum_code_size);
-    let mut cur_code_size: u32 = (minimum_code_size as u32) + 1;
+    decompress_with_limit(compressed_data, minimum_code_size, usize::MAX)
+        .map(|decompressed| decompressed.index_stream)
+}
 
-    // Helper function to get a specific code from the code inv table
-    // TO-DO Maybe error-handling here...
-    let get_code = |k, t: &InvCodeTable| match t.get(k as usize) {
-        Some(val) => Some(val.clone()),
-        None => None,
-    };
+#[derive(Debug, PartialEq)]
+pub struct Decompressed {
+    pub index_stream: Vec<u8>,
+    // Whole bytes left over after the End Of Information Code
+    pub trailing_bytes: usize,
+    // Indices past `max_len` that `decompress_truncated` didn't keep
+    pub dropped_indices: usize,
+}
 
-    let mut index_stream: Vec<u8> = Vec::new();
-    let mut code_stream = LittleEndianReader::new(&compressed_data);
-    let code_key = code_stream.read_bits(cur_code_size).unwrap();
-    let code = get_code(code_key, &inv_code_table);
+// Same as `decompress` but gives up once more than `max_len` indices
+// have been decoded, so that a malicious stream can't grow `index_stream` forever.
+pub fn decompress_with_limit(
+    compressed_data: Vec<u8>,
+    minimum_code_size: u8,
+    max_len: usize,
+) -> Result<Decompressed, DecompressError> {
+    decompress_traced(compressed_data, minimum_code_size, max_len, &mut |_| {})
+}
+
+// Same as `decompress_with_limit` but only keeps the first `keep` indices,
+// the rest are decoded to be counted (and checked against `max_len`).
+pub fn decompress_truncated(
+    compressed_data: Vec<u8>,
+    minimum_code_size: u8,
+    keep: usize,
+    max_len: usize,
+) -> Result<Decompressed, DecompressError> {
+    decode(
+        compressed_data,
+        minimum_code_size,
+        keep,
+        max_len,
+        &mut |_| {},
+    )
+}
+
+// Same as `decompress_with_limit` but calls `trace` with every code read from the
+// stream, including the one it failed on, which is handy to debug broken encoders.
+pub fn decompress_traced(
+    compressed_data: Vec<u8>,
+    minimum_code_size: u8,
+    max_len: usize,
+    trace: &mut dyn FnMut(&TracedCode),
+) -> Result<Decompressed, DecompressError> {
+    decode(compressed_data, minimum_code_size, max_len, max_len, trace)
+}
 
-    // Should always start with Clear Code Inventory
-    assert_eq!(code, Some(InvCode::ControlCode(SpecialCode::ClearCodeInv)));
+fn decode(
+    compressed_data: Vec<u8>,
+    minimum_code_size: u8,
+    keep: usize,
+    max_len: usize,
+    trace: &mut dyn FnMut(&TracedCode),
+) -> Result<Decompressed, DecompressError> {
+    let initial_code_table =
+        create_inverse_code_table(minimum_code_size).map_err(DecompressError::InvalidCode)?;
+    let initial_code_size: u32 = (minimum_code_size as u32) + 1;
 
-    let code_key = code_stream.read_bits(cur_code_size).unwrap();
-    let code = get_code(code_key, &inv_code_table).unwrap();
-    let mut prev_code_key = code_key;
+    let mut inv_code_table = initial_code_table.clone();
+    let mut cur_code_size = initial_code_size;
 
-    match code {
-        InvCode::CodeList(lst) => {
-            // This should always be an entry of size 1, for the first case.
-            // TO-DO: Return Error here instead?
-            assert_eq!(lst.len(), 1);
-            index_stream.push(match lst[0] {
-                Code::Entry(val) => val,
-                // TO-DO: Return Error here instead?
-                _ => panic!("First index should be an Entry!"),
-            });
-        }
-        // TO-DO: Return Error here instead?
-        _ => panic!("First value should be a Code List!"),
-    }
+    let mut index_stream: Vec<u8> = Vec::new();
+    let mut code_stream = LittleEndianReader::new(&compressed_data);
+    let mut bits_read: usize = 0;
+    // Code read just before the current one, `None` right after a Clear Code
+    // (the stream should start with one, but we treat the start as one anyway).
+    let mut prev_code_key: Option<usize> = None;
+    let mut dropped_indices: usize = 0;
 
     loop {
-        let code_key = code_stream.read_bits(cur_code_size).unwrap();
-        let code = get_code(code_key, &inv_code_table);
-        let k = match &code {
-            Some(val) => match val {
-                InvCode::CodeList(lst) => {
-                    let lst: Vec<&u8> = lift_code_to_u8(&lst);
-                    let k = lst[0];
-                    index_stream.extend(lst);
-                    *k
-                }
-                InvCode::ControlCode(special_code) => match special_code {
-                    SpecialCode::ClearCodeInv => {
-                        inv_code_table.clear();
-                        inv_code_table.extend(create_inverse_code_table(minimum_code_size));
-                        // I don't know why I need to re-declare it but I do...
-                        let get_code = |k| match inv_code_table.get(k as usize) {
-                            Some(val) => Some(val.clone()),
-                            None => None,
-                        };
-                        cur_code_size = (minimum_code_size as u32) + 1;
-
-                        // This code is also repeated from just before the for loop
-                        // (Since it is going back to the beginning from after resetting
-                        // the inverse code table). Can be lifted/extracted?
-                        let code_key = code_stream.read_bits(cur_code_size).unwrap();
-                        let code = get_code(code_key).unwrap();
-                        prev_code_key = code_key;
-
-                        match code {
-                            InvCode::CodeList(lst) => {
-                                // This should always be an entry of size 1, for the first case.
-                                // TO-DO: Return Error here instead?
-                                assert_eq!(lst.len(), 1);
-                                index_stream.push(match lst[0] {
-                                    Code::Entry(val) => val,
-                                    // TO-DO: Return Error here instead?
-                                    _ => panic!("First index should be an Entry!"),
-                                });
-                                continue;
-                            }
-                            // TO-DO: Return Error here instead?
-                            _ => panic!("First value should be a Code List!"),
-                        }
-                    }
-                    SpecialCode::EoiCodeInv => {
-                        break;
-                    }
-                },
-            },
-            // Code not in inv_code_table
-            None => {
-                // Should be always safe to unwrap
-                // Since it was previously checked
-                match get_code(prev_code_key, &inv_code_table).unwrap() {
+        if index_stream.len() + dropped_indices > max_len {
+            return Err(DecompressError::OutputLimitExceeded(
+                index_stream.len() + dropped_indices,
+            ));
+        }
+        let code_key = code_stream
+            .read_bits(cur_code_size)
+            .ok_or(DecompressError::UnexpectedEndOfStream)? as usize;
+        let mut traced = TracedCode {
+            bit_offset: bits_read,
+            code: code_key as u16,
+            code_size: cur_code_size as u8,
+            kind: CodeKind::Entry,
+            table_size: inv_code_table.len(),
+            indices_written: index_stream.len(),
+        };
+        bits_read += cur_code_size as usize;
+
+        // The first value of the new table entry (if one gets added)
+        let k = match (inv_code_table.get(code_key), prev_code_key) {
+            (Some(InvCode::ControlCode(SpecialCode::ClearCodeInv)), _) => {
+                trace(&TracedCode {
+                    kind: CodeKind::Clear,
+                    ..traced
+                });
+                inv_code_table.clone_from(&initial_code_table);
+                cur_code_size = initial_code_size;
+                prev_code_key = None;
+                continue;
+            }
+            (Some(InvCode::ControlCode(SpecialCode::EoiCodeInv)), _) => {
+                trace(&TracedCode {
+                    kind: CodeKind::EndOfInformation,
+                    ..traced
+                });
+                break;
+            }
+            (Some(InvCode::CodeList(lst)), _) => {
+                let lst: Vec<&u8> = lift_code_to_u8(lst);
+                let k = *lst[0];
+                index_stream.extend(lst);
+                k
+            }
+            // Code not in inv_code_table yet, it must be the one that is about to be
+            // added (the KwKwK case) which is the previous code plus its own first value.
+            (None, Some(prev_code_key)) if code_key == inv_code_table.len() => {
+                traced.kind = CodeKind::KwKwK;
+                match &inv_code_table[prev_code_key] {
                     InvCode::CodeList(lst) => {
-                        // TO-DO: Return Error here instead?
-                        assert!(lst.len() >= 1);
-                        // lst should not contain a special code
-                        let lst: Vec<&u8> = lift_code_to_u8(&lst);
-                        let k = lst[0];
+                        let lst: Vec<&u8> = lift_code_to_u8(lst);
+                        let k = *lst[0];
                         index_stream.extend(lst);
-                        index_stream.push(*k);
-                        *k
+                        index_stream.push(k);
+                        k
+                    }
+                    InvCode::ControlCode(_) => {
+                        trace(&TracedCode {
+                            kind: CodeKind::Invalid,
+                            ..traced
+                        });
+                        return Err(DecompressError::KeyDoesNotExist);
                     }
-                    // TO-DO: Return Error here instead?
-                    _ => panic!("prev_code should not be a special code!"),
                 }
             }
+            (None, _) => {
+                trace(&TracedCode {
+                    kind: CodeKind::Invalid,
+                    ..traced
+                });
+                return Err(DecompressError::KeyDoesNotExist);
+            }
         };
-        {
-            // Should be always safe to unwrap
-            // Since it was previously checked
-            match get_code(prev_code_key, &inv_code_table).unwrap() {
-                InvCode::CodeList(lst) => {
-                    // TO-DO: Return Error here instead?
-                    inv_code_table.push(InvCode::CodeList(
-                        vec![lst, vec![Code::from(k as u16, minimum_code_size).unwrap()]].concat(),
-                    ));
+        trace(&TracedCode {
+            indices_written: index_stream.len(),
+            ..traced
+        });
+        if index_stream.len() > keep {
+            dropped_indices += index_stream.len() - keep;
+            index_stream.truncate(keep);
+        }
+
+        // Once the table is full, no new entries are added and the code size stays
+        // at 12 bits until the encoder decides to send a Clear Code ("deferred clear").
+        if let Some(prev_code_key) = prev_code_key {
+            if inv_code_table.len() < MAX_TABLE_SIZE {
+                match &inv_code_table[prev_code_key] {
+                    InvCode::CodeList(lst) => {
+                        let entry = [lst.as_slice(), &[Code::Entry(k)]].concat();
+                        inv_code_table.push(InvCode::CodeList(entry));
+                    }
+                    InvCode::ControlCode(_) => {
+                        panic!("Previous Code Should not be a Control Code!")
+                    }
                 }
-                InvCode::ControlCode(_) => panic!("Previouc Code Should not be a Control Code!"),
             }
         }
 
-        const MAX_CODE_SIZE: u32 = 12;
-        if inv_code_table.len() == (2 as usize).pow(cur_code_size) && cur_code_size < MAX_CODE_SIZE
-        {
+        if inv_code_table.len() == (1 << cur_code_size) && cur_code_size < MAX_CODE_SIZE {
             cur_code_size += 1;
         }
-        prev_code_key = code_key;
+        prev_code_key = Some(code_key);
     }
 
-    Ok(index_stream)
+    if index_stream.len() + dropped_indices > max_len {
+        return Err(DecompressError::OutputLimitExceeded(
+            index_stream.len() + dropped_indices,
+        ));
+    }
+    Ok(Decompressed {
+        index_stream,
+        trailing_bytes: (compressed_data.len() * 8 - bits_read) / 8,
+        dropped_indices,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::decoder::fixtures::xorshift;
 
     #[test]
     fn valid_code() {
@@ -215,9 +278,258 @@ mod tests {
             1, 1, 2, 2, 2, 0, 0, 0, 0, 1, 1, 1, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 1, 1,
             1, 1, 1, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1,
         ];
-        // TO-DO: Test with different min_code_size
-        // and also make sure ClearCodeInv coverage
-        // is done (it's not right now)
         assert_eq!(decompress(compressed_data, 2), Ok(decompressed_data));
     }
+
+    #[test]
+    fn decompress_output_limit() {
+        let compressed_data: Vec<u8> = vec![
+            140, 45, 153, 135, 42, 28, 220, 51, 160, 2, 117, 236, 149, 250, 168, 222, 96, 140, 4,
+            145, 76, 1,
+        ];
+        assert_eq!(
+            decompress_with_limit(compressed_data.clone(), 2, 100).map(|x| x.index_stream),
+            decompress(compressed_data.clone(), 2)
+        );
+        assert!(matches!(
+            decompress_with_limit(compressed_data, 2, 50),
+            Err(DecompressError::OutputLimitExceeded(len)) if len > 50
+        ));
+    }
+
+    // Minimal LZW encoder used to generate test streams.
+    // `clear_after` emits a Clear Code after that many codes, otherwise the table
+    // is left full (deferred clear) once it reaches 4096 entries.
+    fn compress(indices: &[u8], minimum_code_size: u8, clear_after: Option<usize>) -> Vec<u8> {
+        use std::collections::HashMap;
+        let clear_code = 1u32 << minimum_code_size;
+        let eoi_code = clear_code + 1;
+
+        let mut bytes = Vec::new();
+        let (mut acc, mut acc_len) = (0u32, 0u32);
+        let mut write = |code: u32, size: u32| {
+            acc |= code << acc_len;
+            acc_len += size;
+            while acc_len >= 8 {
+                bytes.push(acc as u8);
+                acc >>= 8;
+                acc_len -= 8;
+            }
+        };
+
+        // (prefix code, next index) -> code
+        let mut table: HashMap<(u32, u8), u32> = HashMap::new();
+        let mut next_code = eoi_code + 1;
+        let mut code_size = minimum_code_size as u32 + 1;
+        let mut codes_since_clear = 0;
+        write(clear_code, code_size);
+
+        let mut cur: Option<u32> = None;
+        for &k in indices {
+            let prefix = match cur {
+                Some(prefix) => prefix,
+                None => {
+                    cur = Some(k as u32);
+                    continue;
+                }
+            };
+            if let Some(&code) = table.get(&(prefix, k)) {
+                cur = Some(code);
+                continue;
+            }
+            write(prefix, code_size);
+            codes_since_clear += 1;
+            if next_code < 4096 {
+                table.insert((prefix, k), next_code);
+                if next_code == 1 << code_size {
+                    code_size += 1;
+                }
+                next_code += 1;
+            }
+            if Some(codes_since_clear) == clear_after {
+                write(clear_code, code_size);
+                table.clear();
+                next_code = eoi_code + 1;
+                code_size = minimum_code_size as u32 + 1;
+                codes_since_clear = 0;
+            }
+            cur = Some(k as u32);
+        }
+        if let Some(prefix) = cur {
+            write(prefix, code_size);
+            if next_code < 4096 && next_code == 1 << code_size {
+                code_size += 1;
+            }
+        }
+        write(eoi_code, code_size);
+        write(0, 7);
+        bytes
+    }
+
+    // Reproducible test data, values repeat in runs so that the table actually fills up
+    fn generate_indices(len: usize, minimum_code_size: u8, seed: u32) -> Vec<u8> {
+        let mut next = xorshift(seed);
+        let max = 1u32 << minimum_code_size;
+        let mut ret = Vec::with_capacity(len);
+        while ret.len() < len {
+            let value = (next() % max) as u8;
+            let run = (next() % 8) as usize + 1;
+            ret.extend(std::iter::repeat_n(value, run.min(len - ret.len())));
+        }
+        ret
+    }
+
+    #[test]
+    fn decompress_every_code_size() {
+        for minimum_code_size in 2..=8 {
+            let indices = generate_indices(20_000, minimum_code_size, 0x1234_5678);
+            let compressed = compress(&indices, minimum_code_size, None);
+            assert_eq!(
+                decompress(compressed, minimum_code_size),
+                Ok(indices),
+                "minimum code size {}",
+                minimum_code_size
+            );
+        }
+    }
+
+    #[test]
+    fn decompress_full_table_deferred_clear() {
+        // Long enough that the table stays full for thousands of codes
+        for minimum_code_size in [2, 5, 8] {
+            let indices = generate_indices(200_000, minimum_code_size, 0xDEAD_BEEF);
+            let compressed = compress(&indices, minimum_code_size, None);
+            assert_eq!(decompress(compressed, minimum_code_size), Ok(indices));
+        }
+        // Table filled, held full, then cleared and filled again
+        let indices = generate_indices(200_000, 4, 42);
+        let compressed = compress(&indices, 4, Some(6000));
+        assert_eq!(decompress(compressed, 4), Ok(indices));
+    }
+
+    #[test]
+    fn decompress_multiple_clear_codes() {
+        for minimum_code_size in 2..=8 {
+            for clear_after in [1, 2, 7, 100, 511, 4000] {
+                let indices = generate_indices(10_000, minimum_code_size, clear_after as u32);
+                let compressed = compress(&indices, minimum_code_size, Some(clear_after));
+                assert_eq!(
+                    decompress(compressed, minimum_code_size),
+                    Ok(indices),
+                    "minimum code size {}, clear after {}",
+                    minimum_code_size,
+                    clear_after
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decompress_trailing_bytes() {
+        let indices = generate_indices(1000, 3, 7);
+        let mut compressed = compress(&indices, 3, None);
+        assert_eq!(
+            decompress_with_limit(compressed.clone(), 3, usize::MAX),
+            Ok(Decompressed {
+                index_stream: indices.clone(),
+                trailing_bytes: 0,
+                dropped_indices: 0
+            })
+        );
+        compressed.extend([0xAB, 0xCD]);
+        assert_eq!(
+            decompress_with_limit(compressed, 3, usize::MAX),
+            Ok(Decompressed {
+                index_stream: indices,
+                trailing_bytes: 2,
+                dropped_indices: 0
+            })
+        );
+    }
+
+    #[test]
+    fn decompress_truncated_stream() {
+        let indices = generate_indices(5000, 8, 3);
+        let mut compressed = compress(&indices, 8, Some(1000));
+        compressed.push(0xAB);
+        assert_eq!(
+            decompress_truncated(compressed.clone(), 8, 1234, usize::MAX),
+            Ok(Decompressed {
+                index_stream: indices[..1234].to_vec(),
+                trailing_bytes: 1,
+                dropped_indices: 5000 - 1234
+            })
+        );
+        assert!(matches!(
+            decompress_truncated(compressed, 8, 1234, 3000),
+            Err(DecompressError::OutputLimitExceeded(len)) if len > 3000
+        ));
+    }
+
+    #[test]
+    fn decompress_traced_codes() {
+        let indices = vec![1; 10];
+        let mut traced = Vec::new();
+        let decompressed =
+            decompress_traced(compress(&indices, 2, None), 2, usize::MAX, &mut |code| {
+                traced.push(*code)
+            });
+        assert_eq!(decompressed.map(|d| d.index_stream), Ok(indices));
+        let kinds: Vec<CodeKind> = traced.iter().map(|code| code.kind).collect();
+        use CodeKind::*;
+        // 1, 11, 111 and 1111
+        assert_eq!(
+            kinds,
+            vec![Clear, Entry, KwKwK, KwKwK, KwKwK, EndOfInformation]
+        );
+        assert_eq!(
+            traced[3],
+            TracedCode {
+                bit_offset: 9,
+                code: 7,
+                code_size: 3,
+                kind: KwKwK,
+                table_size: 7,
+                indices_written: 6,
+            }
+        );
+        assert_eq!(traced.last().map(|code| code.indices_written), Some(10));
+
+        // Clear Code followed by a code that isn't in the table yet
+        let mut traced = Vec::new();
+        let decompressed =
+            decompress_traced(vec![0x3C], 2, usize::MAX, &mut |code| traced.push(*code));
+        assert_eq!(decompressed, Err(DecompressError::KeyDoesNotExist));
+        assert_eq!(
+            traced.last().map(|code| (code.code, code.kind)),
+            Some((7, Invalid))
+        );
+    }
+
+    #[test]
+    fn decompress_invalid_stream() {
+        let indices = generate_indices(1000, 3, 7);
+        let compressed = compress(&indices, 3, None);
+        assert_eq!(
+            decompress(compressed[..compressed.len() / 2].to_vec(), 3),
+            Err(DecompressError::UnexpectedEndOfStream)
+        );
+        // Clear Code followed by a code that can't exist yet
+        assert_eq!(
+            decompress(vec![0b0011_1100, 0x00], 2),
+            Err(DecompressError::KeyDoesNotExist)
+        );
+        assert_eq!(
+            decompress(vec![0x00], 9),
+            Err(DecompressError::InvalidCode(
+                CodeParseError::MinCodeSizeInvalid(9)
+            ))
+        );
+        assert_eq!(
+            decompress(vec![0x00], 0xFF),
+            Err(DecompressError::InvalidCode(
+                CodeParseError::MinCodeSizeInvalid(0xFF)
+            ))
+        );
+    }
 }
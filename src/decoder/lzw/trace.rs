@@ -0,0 +1,42 @@
+use std::fmt;
+
+/// What a code read from the stream turned out to be.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CodeKind {
+    Clear,
+    EndOfInformation,
+    // Already in the code table
+    Entry,
+    // The code that is about to be added to the table
+    KwKwK,
+    // Neither in the table nor the next one, decoding stops here
+    Invalid,
+}
+
+impl fmt::Display for CodeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            CodeKind::Clear => "clear",
+            CodeKind::EndOfInformation => "eoi",
+            CodeKind::Entry => "entry",
+            CodeKind::KwKwK => "kwkwk",
+            CodeKind::Invalid => "invalid",
+        };
+        f.pad(name)
+    }
+}
+
+/// A single code read by the decoder, see `decompress_traced`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TracedCode {
+    // Position of the code in the (de-sub-blocked) compressed data
+    pub bit_offset: usize,
+    pub code: u16,
+    // Bit width the code was read with
+    pub code_size: u8,
+    pub kind: CodeKind,
+    // Size of the code table when the code was read
+    pub table_size: usize,
+    // Length of the index stream after the code was decoded
+    pub indices_written: usize,
+}
@@ -19,10 +19,10 @@ pub enum Code {
 impl Code {
     pub fn from(value: u16, minimum_code_size: u8) -> Result<Self, CodeParseError> {
         use SpecialCode::*;
-        if minimum_code_size < 2 || minimum_code_size > 8 {
+        if !(2..=8).contains(&minimum_code_size) {
             return Err(CodeParseError::MinCodeSizeInvalid(minimum_code_size));
         }
-        let clear_code = (2 as u16).pow(minimum_code_size.into());
+        let clear_code = 2_u16.pow(minimum_code_size.into());
         let eoi_code = clear_code + 1;
         match value {
             x if x == clear_code => Ok(Code::ControlCode(ClearCodeInv)),
@@ -1,17 +1,72 @@
 use std::{fs::File, io::Read};
-mod lzw;
+mod dump;
+mod errors;
+#[cfg(test)]
+pub(crate) mod fixtures;
+mod limits;
+pub mod lzw;
+mod options;
 mod parser;
+mod probe;
+mod sniff;
+mod stats;
 mod types;
-pub use parser::*;
+pub use dump::*;
+pub use errors::*;
+pub use limits::*;
+pub use lzw::DecompressError;
+pub use options::*;
+pub use parser::compressed_frames;
+pub use probe::*;
+pub use sniff::*;
+pub use stats::*;
 pub use types::*;
 
-pub fn load(filename: &str) -> Result<GifFile, &'static str> {
-    match File::open(filename) {
-        Ok(mut f) => {
-            let mut buffer = Vec::new();
-            f.read_to_end(&mut buffer).expect("Unable to read file");
-            GifFile::new(&buffer)
-        }
-        Err(_) => Err("Unable to load file"),
+/// The nom sub-parsers, exposed for the fuzz targets in `fuzz/`. Not a stable API.
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing {
+    use super::parser;
+    use super::{Extension, GifHeader, ImageDescriptor, LogicalScreenDescriptor, Pixel};
+    use nom::IResult;
+
+    pub fn parse_header(bytes: &[u8]) -> IResult<&[u8], GifHeader> {
+        parser::parse_header(bytes)
+    }
+
+    pub fn parse_logical_screen(
+        bytes: &[u8],
+    ) -> IResult<&[u8], (LogicalScreenDescriptor, Option<Vec<Pixel>>)> {
+        let (bytes, lsd) = parser::parse_logical_screen_descriptor(bytes)?;
+        let (bytes, gct) = parser::parse_global_color_table(bytes, &lsd)?;
+        Ok((bytes, (lsd, gct)))
+    }
+
+    pub fn parse_extension(bytes: &[u8]) -> IResult<&[u8], Extension> {
+        parser::parse_extension(bytes)
+    }
+
+    pub fn parse_image_descriptor(
+        bytes: &[u8],
+    ) -> IResult<&[u8], (ImageDescriptor, Option<Vec<Pixel>>)> {
+        let (bytes, image_descriptor) = parser::parse_image_descriptor(bytes)?;
+        let (bytes, lct) = parser::parse_local_color_table(bytes, &image_descriptor)?;
+        Ok((bytes, (image_descriptor, lct)))
+    }
+
+    pub fn parse_data_block(bytes: &[u8]) -> IResult<&[u8], Vec<u8>> {
+        parser::parse_data_block(bytes)
+    }
+
+    pub fn skip_data_block(bytes: &[u8]) -> IResult<&[u8], usize> {
+        parser::skip_data_block(bytes)
+    }
+}
+
+pub fn load(filename: &str) -> Result<GifFile, ParseError> {
+    let mut buffer = Vec::new();
+    match File::open(filename).and_then(|mut f| f.read_to_end(&mut buffer)) {
+        Ok(_) => GifFile::new(&buffer),
+        Err(_) => Err(ParseError::UnableToLoadFile(filename.into())),
     }
 }
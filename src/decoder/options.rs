@@ -0,0 +1,50 @@
+use super::{DecodeLimits, ImageDescriptor};
+
+/// How to deal with frames that do not fit inside the Logical Screen,
+/// including files that declare a 0x0 canvas.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum CanvasMode {
+    // Keep the declared canvas, anything outside of it is cut off
+    #[default]
+    Clip,
+    // Make the canvas big enough to fit every frame
+    GrowToFit,
+    // If the declared canvas is empty, use the size of the first frame instead
+    InferFromFirstFrame,
+}
+
+fn frame_extent(image_descriptor: &ImageDescriptor) -> (usize, usize) {
+    (
+        image_descriptor.left as usize + image_descriptor.width as usize,
+        image_descriptor.top as usize + image_descriptor.height as usize,
+    )
+}
+
+impl CanvasMode {
+    /// Size of the canvas the frames should be drawn on.
+    pub fn canvas_size<'a>(
+        &self,
+        canvas_width: u16,
+        canvas_height: u16,
+        mut image_descriptors: impl Iterator<Item = &'a ImageDescriptor>,
+    ) -> (usize, usize) {
+        let declared = (canvas_width as usize, canvas_height as usize);
+        match self {
+            CanvasMode::Clip => declared,
+            CanvasMode::GrowToFit => image_descriptors.fold(declared, |(width, height), id| {
+                let (right, bottom) = frame_extent(id);
+                (width.max(right), height.max(bottom))
+            }),
+            CanvasMode::InferFromFirstFrame if canvas_width == 0 || canvas_height == 0 => {
+                image_descriptors.next().map_or(declared, frame_extent)
+            }
+            CanvasMode::InferFromFirstFrame => declared,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct DecodeOptions {
+    pub limits: DecodeLimits,
+    pub canvas_mode: CanvasMode,
+}
@@ -2,20 +2,28 @@ use crate::decoder::DisposalMethod;
 use crate::decoder::ImageDescriptor;
 
 use super::lzw;
+use super::lzw::{DecompressError, Decompressed, TracedCode};
+use super::CanvasMode;
+use super::CompressedFrame;
+use super::DecodeLimits;
+use super::DecodeOptions;
+use super::DecodeWarning;
 use super::Extension;
 use super::GifFile;
 use super::GifFrame;
 use super::GifHeader;
 use super::GlobalColorTable;
+use super::LimitKind;
 use super::LocalColorTable;
 use super::LogicalScreenDescriptor;
+use super::ParseError;
 use super::Pixel;
 use nom::bits;
 use nom::bytes::complete::tag;
 use nom::combinator::eof;
 use nom::combinator::fail;
-use nom::multi::fold_many1;
-use nom::multi::{count, many0, many1};
+use nom::multi::fold_many0;
+use nom::multi::{count, many0};
 use nom::number::complete::{le_u16, le_u8};
 use nom::sequence::preceded;
 use nom::{
@@ -39,12 +47,13 @@ fn take_pixel(bytes: &[u8]) -> IResult<&[u8], Pixel> {
     Ok((bytes, Pixel { red, green, blue }))
 }
 
-fn parse_header(bytes: &[u8]) -> IResult<&[u8], GifHeader> {
-    let (bytes, magic) = map_res(take(6usize), str::from_utf8)(bytes)?;
-    Ok((bytes, GifHeader::from(magic).unwrap()))
+pub(super) fn parse_header(bytes: &[u8]) -> IResult<&[u8], GifHeader> {
+    map_res(map_res(take(6usize), str::from_utf8), GifHeader::from)(bytes)
 }
 
-fn parse_logical_screen_descriptor(bytes: &[u8]) -> IResult<&[u8], LogicalScreenDescriptor> {
+pub(super) fn parse_logical_screen_descriptor(
+    bytes: &[u8],
+) -> IResult<&[u8], LogicalScreenDescriptor> {
     struct PackedField {
         global_color_table_flag: bool,
         color_resolution: u16,
@@ -88,7 +97,7 @@ fn parse_logical_screen_descriptor(bytes: &[u8]) -> IResult<&[u8], LogicalScreen
         },
     ))
 }
-fn parse_global_color_table<'a>(
+pub(super) fn parse_global_color_table<'a>(
     bytes: &'a [u8],
     lsd: &LogicalScreenDescriptor,
 ) -> IResult<&'a [u8], Option<GlobalColorTable>> {
@@ -106,101 +115,121 @@ fn parse_global_color_table<'a>(
     Ok((bytes, Some(ret)))
 }
 
-fn parse_extensions(bytes: &[u8]) -> IResult<&[u8], Vec<Extension>> {
-    fn parse_extension(bytes: &[u8]) -> IResult<&[u8], Extension> {
-        struct PackedField {
-            reserved: u8,
-            disposal_method: u8,
-            user_input_flag: bool,
-            transparent_color_flag: bool,
-        }
+pub(super) fn parse_extension(bytes: &[u8]) -> IResult<&[u8], Extension> {
+    struct PackedField {
+        reserved: u8,
+        disposal_method: u8,
+        user_input_flag: bool,
+        transparent_color_flag: bool,
+    }
+
+    fn parse_packed_field(bits: BitInput) -> IResult<BitInput, PackedField> {
+        let (bits, reserved) = bits::complete::take(3usize)(bits)?;
+        let (bits, disposal_method) = bits::complete::take(3usize)(bits)?;
+        let (bits, user_input_flag) = take_bit(bits)?;
+        let (bits, transparent_color_flag) = take_bit(bits)?;
+        Ok((
+            bits,
+            PackedField {
+                reserved,
+                disposal_method,
+                user_input_flag,
+                transparent_color_flag,
+            },
+        ))
+    }
+
+    const BLOCK_TERMINATOR: &[u8] = &[0x00];
+    const INTRODUCER: &[u8] = &[0x21];
+    let (bytes, ext_type) = preceded(tag(INTRODUCER), le_u8)(bytes)?;
+    match ext_type {
+        0xF9 => {
+            // Should always be 4 according to the specificatioins.
+            // IDK why they put it there then.
+            const GCE_BLOCK_SIZE: &[u8] = &[0x04];
+            let (bytes, _) = tag(GCE_BLOCK_SIZE)(bytes)?;
+            let (bytes, packed_field) = nom::bits::bits(parse_packed_field)(bytes)?;
+            let (bytes, delay_timer) = le_u16(bytes)?;
+            let (bytes, transparent_color_index) = le_u8(bytes)?;
+            let (bytes, _) = tag(BLOCK_TERMINATOR)(bytes)?;
 
-        fn parse_packed_field(bits: BitInput) -> IResult<BitInput, PackedField> {
-            let (bits, reserved) = bits::complete::take(3usize)(bits)?;
-            let (bits, disposal_method) = bits::complete::take(3usize)(bits)?;
-            let (bits, user_input_flag) = take_bit(bits)?;
-            let (bits, transparent_color_flag) = take_bit(bits)?;
             Ok((
-                bits,
-                PackedField {
-                    reserved,
-                    disposal_method,
-                    user_input_flag,
-                    transparent_color_flag,
+                bytes,
+                Extension::GraphicsControlExtension {
+                    reserved: packed_field.reserved,
+                    disposal_method: DisposalMethod::from(packed_field.disposal_method),
+                    user_input_flag: packed_field.user_input_flag,
+                    transparent_color_flag: packed_field.transparent_color_flag,
+                    delay_timer,
+                    transparent_color_index,
                 },
             ))
         }
+        0x01 => {
+            const PLAIN_TEXT_BLOCK_SIZE: &[u8] = &[0x0C];
+            let (bytes, _) = tag(PLAIN_TEXT_BLOCK_SIZE)(bytes)?;
+            let (bytes, text_grid_left) = le_u16(bytes)?;
+            let (bytes, text_grid_top) = le_u16(bytes)?;
+            let (bytes, text_grid_width) = le_u16(bytes)?;
+            let (bytes, text_grid_height) = le_u16(bytes)?;
+            let (bytes, cell_width) = le_u8(bytes)?;
+            let (bytes, cell_height) = le_u8(bytes)?;
+            let (bytes, text_foreground_color_index) = le_u8(bytes)?;
+            let (bytes, text_background_color_index) = le_u8(bytes)?;
+            let (bytes, text) = parse_data_block(bytes)?;
+            Ok((
+                bytes,
+                Extension::PlainText {
+                    text_grid_left,
+                    text_grid_top,
+                    text_grid_width,
+                    text_grid_height,
+                    cell_width,
+                    cell_height,
+                    text_foreground_color_index,
+                    text_background_color_index,
+                    text,
+                },
+            ))
+        }
+        0xFF => {
+            const APPLICATION_BLOCK_SIZE: &[u8] = &[11];
+            let (bytes, _) = tag(APPLICATION_BLOCK_SIZE)(bytes)?;
+            let (bytes, identifier) = take(8usize)(bytes)?;
+            let (bytes, authentication_code) = take(3usize)(bytes)?;
 
-        const BLOCK_TERMINATOR: &[u8] = &[0x00];
-        const INTRODUCER: &[u8] = &[0x21];
-        let (bytes, ext_type) = preceded(tag(INTRODUCER), le_u8)(bytes)?;
-        match ext_type {
-            0xF9 => {
-                // Should always be 4 according to the specificatioins.
-                // IDK why they put it there then.
-                const GCE_BLOCK_SIZE: &[u8] = &[0x04];
-                let (bytes, _) = tag(GCE_BLOCK_SIZE)(bytes)?;
-                let (bytes, packed_field) = nom::bits::bits(parse_packed_field)(bytes)?;
-                let (bytes, delay_timer) = le_u16(bytes)?;
-                let (bytes, transparent_color_index) = le_u8(bytes)?;
-                let (bytes, _) = tag(BLOCK_TERMINATOR)(bytes)?;
-
-                Ok((
-                    bytes,
-                    Extension::GraphicsControlExtension {
-                        reserved: packed_field.reserved,
-                        disposal_method: match packed_field.disposal_method {
-                            0 => DisposalMethod::NoDisposal,
-                            1 => DisposalMethod::DoNotDispose,
-                            2 => DisposalMethod::RestoreToBackground,
-                            3 => DisposalMethod::RestoreToPrevious,
-                            _ => {
-                                panic!("Invalid Disposal Method {}!", packed_field.disposal_method)
-                            }
-                        },
-                        user_input_flag: packed_field.user_input_flag,
-                        transparent_color_flag: packed_field.transparent_color_flag,
-                        delay_timer,
-                        transparent_color_index,
-                    },
-                ))
-            }
-            0x01 => {
-                unimplemented!("PlainText Extension not supported!");
-                #[allow(unreachable_code)]
-                Ok((bytes, Extension::PlainText { text: "".into() }))
-            }
-            0xFF => {
-                const NETSCAPE_EXTENSION_LENGTH: u8 = 11;
-                let (bytes, block_size) = le_u8(bytes)?;
-                assert_eq!(block_size, NETSCAPE_EXTENSION_LENGTH);
-                let (bytes, combined) = take(block_size)(bytes)?;
-
-                // For some reason, there are usually extra bytes after this
-                // which I'm not sure what is used for...
-                let (bytes, extra) = parse_data_block(bytes)?;
-                Ok((
-                    bytes,
-                    Extension::Application {
-                        identifier: str::from_utf8(&combined[..8]).unwrap().into(),
-                        authentication_code: str::from_utf8(&combined[8..]).unwrap().into(),
-                        data: extra,
-                    },
-                ))
-            }
-            0xFE => {
-                unimplemented!("Comment Extension not supported!");
-                #[allow(unreachable_code)]
-                Ok((bytes, Extension::Comment { text: "".into() }))
-            }
-            _ => panic!("Unsupported Extension {:#x}!", ext_type),
+            // For some reason, there are usually extra bytes after this
+            // which I'm not sure what is used for...
+            let (bytes, extra) = parse_sub_blocks(bytes)?;
+            Ok((
+                bytes,
+                Extension::Application {
+                    // `take` gave exactly that many bytes
+                    identifier: identifier.try_into().unwrap(),
+                    authentication_code: authentication_code.try_into().unwrap(),
+                    data: extra,
+                },
+            ))
+        }
+        0xFE => {
+            // Supposed to be 7-bit ASCII, but anything goes in practice
+            let (bytes, text) = parse_data_block(bytes)?;
+            Ok((bytes, Extension::Comment { text }))
+        }
+        // Every extension is made of sub-blocks, so unknown ones can still be walked over
+        label => {
+            let (bytes, data) = parse_sub_blocks(bytes)?;
+            Ok((bytes, Extension::Unknown { label, data }))
         }
     }
+}
+
+fn parse_extensions(bytes: &[u8]) -> IResult<&[u8], Vec<Extension>> {
     let (bytes, extensions) = many0(parse_extension)(bytes)?;
     Ok((bytes, extensions))
 }
 
-fn parse_image_descriptor(bytes: &[u8]) -> IResult<&[u8], ImageDescriptor> {
+pub(super) fn parse_image_descriptor(bytes: &[u8]) -> IResult<&[u8], ImageDescriptor> {
     struct PackedField {
         local_color_table_flag: bool,
         interlace_flag: bool,
@@ -250,7 +279,7 @@ fn parse_image_descriptor(bytes: &[u8]) -> IResult<&[u8], ImageDescriptor> {
     ))
 }
 
-fn parse_local_color_table<'a>(
+pub(super) fn parse_local_color_table<'a>(
     bytes: &'a [u8],
     image_descriptor: &ImageDescriptor,
 ) -> IResult<&'a [u8], Option<LocalColorTable>> {
@@ -269,77 +298,325 @@ fn parse_local_color_table<'a>(
 }
 
 // This is a data block used for both Image Data
-fn parse_data_block(bytes: &[u8]) -> IResult<&[u8], Vec<u8>> {
+pub(super) fn parse_data_block(bytes: &[u8]) -> IResult<&[u8], Vec<u8>> {
+    map(parse_counted_data_block, |(block, _)| block)(bytes)
+}
+
+fn parse_data_subblock(bytes: &[u8]) -> IResult<&[u8], &[u8]> {
+    let (bytes, subblock_length) = le_u8(bytes)?;
+    if subblock_length == 0 {
+        return fail::<_, &[u8], _>(bytes);
+    }
+    let (bytes, subblock) = take(subblock_length)(bytes)?;
+    Ok((bytes, subblock))
+}
+
+// Same as `parse_data_block` but also returns how many sub-blocks there were
+fn parse_counted_data_block(bytes: &[u8]) -> IResult<&[u8], (Vec<u8>, usize)> {
     // We try get the entire block out first because we weant
     // the decompression code to be somewhere else and not here.
-    fn parse_data_subblock(bytes: &[u8]) -> IResult<&[u8], &[u8]> {
-        let (bytes, subblock_length) = le_u8(bytes)?;
-        if subblock_length == 0 {
-            return fail::<_, &[u8], _>(bytes);
-        }
-        let (bytes, subblock) = take(subblock_length)(bytes)?;
-        Ok((bytes, subblock))
-    }
-    let (bytes, block) = fold_many1(parse_data_subblock, Vec::new, |mut acc: Vec<_>, item| {
-        acc.extend_from_slice(item);
-        acc
-    })(bytes)?;
+    let (bytes, block) = fold_many0(
+        parse_data_subblock,
+        || (Vec::new(), 0),
+        |(mut acc, count): (Vec<_>, usize), item| {
+            acc.extend_from_slice(item);
+            (acc, count + 1)
+        },
+    )(bytes)?;
     // Take in the final 0
     const BLOCK_TERMINATOR: &[u8] = &[0x00];
     let (bytes, _) = tag(BLOCK_TERMINATOR)(bytes)?;
     Ok((bytes, block))
 }
 
-fn parse_image_data(bytes: &[u8]) -> IResult<&[u8], Vec<u8>> {
+// Same as `parse_data_block` but keeps every sub-block apart, for data where
+// the boundaries may mean something
+pub(super) fn parse_sub_blocks(bytes: &[u8]) -> IResult<&[u8], Vec<Vec<u8>>> {
+    const BLOCK_TERMINATOR: &[u8] = &[0x00];
+    let (bytes, sub_blocks) = many0(map(parse_data_subblock, <[u8]>::to_vec))(bytes)?;
+    let (bytes, _) = tag(BLOCK_TERMINATOR)(bytes)?;
+    Ok((bytes, sub_blocks))
+}
+
+// Same as `parse_data_block` but only walks over the sub-blocks using their
+// lengths, without copying anything out. Returns the total number of data bytes.
+pub(super) fn skip_data_block(mut bytes: &[u8]) -> IResult<&[u8], usize> {
+    let mut total = 0;
+    loop {
+        let (rest, subblock_length) = le_u8(bytes)?;
+        let (rest, _) = take(subblock_length)(rest)?;
+        bytes = rest;
+        if subblock_length == 0 {
+            return Ok((bytes, total));
+        }
+        total += subblock_length as usize;
+    }
+}
+
+fn parse_image_data(bytes: &[u8]) -> IResult<&[u8], (u8, Vec<u8>, usize)> {
     let (bytes, lzw_minimum_code_size) = le_u8(bytes)?;
-    let (bytes, compressed_data) = parse_data_block(bytes)?;
+    let (bytes, (compressed_data, sub_blocks)) = parse_counted_data_block(bytes)?;
+    Ok((bytes, (lzw_minimum_code_size, compressed_data, sub_blocks)))
+}
 
-    Ok((
-        bytes,
-        // Using unwrap here is probably a bad idea, so maybe decompression
-        // should be done at a different stage and not in a parser combinator?
-        lzw::decompress(compressed_data, lzw_minimum_code_size).unwrap(),
-    ))
+// Names the limit that made the LZW decoder give up, if that's what happened
+fn decompress_error(
+    err: DecompressError,
+    limits: &DecodeLimits,
+    max_decoded_len: (u64, LimitKind),
+    compressed_len: usize,
+    total_decoded: u64,
+) -> ParseError {
+    match err {
+        DecompressError::OutputLimitExceeded(len) => {
+            limits.exceeded(max_decoded_len, compressed_len, total_decoded, len as u64)
+        }
+        err => ParseError::Decompress(err),
+    }
+}
+
+// A frame whose image data is still LZW compressed. Decompression is done
+// outside of the parser combinators so that errors and `DecodeLimits` can be
+// handled properly.
+struct RawFrame {
+    image_descriptor: ImageDescriptor,
+    local_color_table: Option<LocalColorTable>,
+    lzw_minimum_code_size: u8,
+    compressed_data: Vec<u8>,
+    sub_blocks: usize,
+    extensions: Vec<Extension>,
+}
+
+impl RawFrame {
+    // Also returns the problems found in the image data of frame number `frame`
+    fn decompress(
+        self,
+        frame: usize,
+        limits: &DecodeLimits,
+        total_decoded: u64,
+    ) -> Result<(GifFrame, Vec<DecodeWarning>), ParseError> {
+        let compressed_len = self.compressed_data.len();
+        let pixels = self.image_descriptor.width as usize * self.image_descriptor.height as usize;
+        let max_decoded_len = limits.max_decoded_len(compressed_len, total_decoded);
+        let max_len = usize::try_from(max_decoded_len.0).unwrap_or(usize::MAX);
+        // A stream longer than the frame is a common mistake that browsers
+        // ignore, the extra indices are dropped instead of kept around.
+        let decompressed = lzw::decompress_truncated(
+            self.compressed_data,
+            self.lzw_minimum_code_size,
+            pixels,
+            max_len,
+        )
+        .map_err(|err| {
+            decompress_error(err, limits, max_decoded_len, compressed_len, total_decoded)
+        })?;
+
+        let mut warnings = Vec::new();
+        if decompressed.trailing_bytes > 0 {
+            warnings.push(DecodeWarning::DataAfterEndOfInformation(frame));
+        }
+        if decompressed.dropped_indices > 0 {
+            warnings.push(DecodeWarning::PixelStreamTooLong {
+                frame,
+                expected: pixels,
+                actual: pixels + decompressed.dropped_indices,
+            });
+        }
+        Ok((
+            GifFrame {
+                image_descriptor: self.image_descriptor,
+                local_color_table: self.local_color_table,
+                frame_indices: decompressed.index_stream,
+                extensions: self.extensions,
+            },
+            warnings,
+        ))
+    }
 }
 
-fn parse_frame(bytes: &[u8]) -> IResult<&[u8], GifFrame> {
-    let (bytes, extensions) = parse_extensions(bytes).unwrap();
+fn parse_frame(bytes: &[u8]) -> IResult<&[u8], RawFrame> {
+    let (bytes, extensions) = parse_extensions(bytes)?;
     let (bytes, image_descriptor) = parse_image_descriptor(bytes)?;
     let (bytes, local_color_table) = parse_local_color_table(bytes, &image_descriptor)?;
-    let (bytes, frame_indices) = parse_image_data(bytes)?;
+    let (bytes, (lzw_minimum_code_size, compressed_data, sub_blocks)) = parse_image_data(bytes)?;
     Ok((
         bytes,
-        GifFrame {
+        RawFrame {
             image_descriptor,
             local_color_table,
-            frame_indices,
+            lzw_minimum_code_size,
+            compressed_data,
+            sub_blocks,
             extensions,
         },
     ))
 }
 
+/// The still compressed image data of every frame, for tools that want to look
+/// at the LZW code stream itself. Stops at the first block that can't be parsed.
+pub fn compressed_frames(bytes: &[u8]) -> Result<Vec<CompressedFrame>, ParseError> {
+    let (bytes, _) = parse_header(bytes).map_err(|_| ParseError::invalid_header(bytes))?;
+    let (bytes, logical_screen_descriptor) = parse_logical_screen_descriptor(bytes)
+        .map_err(|_| ParseError::InvalidBlock("Logical Screen Descriptor"))?;
+    let (mut bytes, _) = parse_global_color_table(bytes, &logical_screen_descriptor)
+        .map_err(|_| ParseError::InvalidBlock("Global Color Table"))?;
+
+    let mut frames = Vec::new();
+    while let Ok((rest, raw_frame)) = parse_frame(bytes) {
+        frames.push(CompressedFrame {
+            lzw_minimum_code_size: raw_frame.lzw_minimum_code_size,
+            compressed_data: raw_frame.compressed_data,
+            sub_blocks: raw_frame.sub_blocks,
+        });
+        bytes = rest;
+    }
+    if frames.is_empty() {
+        return Err(ParseError::InvalidBlock("Image Descriptor"));
+    }
+    Ok(frames)
+}
+
+impl CompressedFrame {
+    /// Decompresses the image data within `limits`, calling `trace` with every
+    /// code read. `total_decoded` is the number of indices of the frames before it.
+    pub fn decompress_traced(
+        self,
+        limits: &DecodeLimits,
+        total_decoded: u64,
+        trace: &mut dyn FnMut(&TracedCode),
+    ) -> Result<Decompressed, ParseError> {
+        let compressed_len = self.compressed_data.len();
+        let max_decoded_len = limits.max_decoded_len(compressed_len, total_decoded);
+        let max_len = usize::try_from(max_decoded_len.0).unwrap_or(usize::MAX);
+        lzw::decompress_traced(
+            self.compressed_data,
+            self.lzw_minimum_code_size,
+            max_len,
+            trace,
+        )
+        .map_err(|err| {
+            decompress_error(err, limits, max_decoded_len, compressed_len, total_decoded)
+        })
+    }
+}
+
+// Checks that every frame fits inside the Logical Screen, resizing it
+// according to the `CanvasMode` if needed.
+fn fit_canvas(
+    lsd: &mut LogicalScreenDescriptor,
+    frames: &[GifFrame],
+    canvas_mode: CanvasMode,
+) -> Vec<DecodeWarning> {
+    let mut warnings = Vec::new();
+    if lsd.canvas_width == 0 || lsd.canvas_height == 0 {
+        warnings.push(DecodeWarning::ZeroSizedCanvas);
+    }
+    for (i, frame) in frames.iter().enumerate() {
+        let id = &frame.image_descriptor;
+        if id.left as usize + id.width as usize > lsd.canvas_width as usize
+            || id.top as usize + id.height as usize > lsd.canvas_height as usize
+        {
+            warnings.push(DecodeWarning::FrameOutsideCanvas(i));
+        }
+    }
+
+    let (width, height) = canvas_mode.canvas_size(
+        lsd.canvas_width,
+        lsd.canvas_height,
+        frames.iter().map(|frame| &frame.image_descriptor),
+    );
+    // The Logical Screen can't be bigger than 65535x65535,
+    // anything past that still gets clipped.
+    let width = width.min(u16::MAX as usize) as u16;
+    let height = height.min(u16::MAX as usize) as u16;
+    if (width, height) != (lsd.canvas_width, lsd.canvas_height) {
+        lsd.canvas_width = width;
+        lsd.canvas_height = height;
+        warnings.push(DecodeWarning::CanvasResized { width, height });
+    }
+    warnings
+}
+
 impl GifFile {
-    pub fn new(bytes: &[u8]) -> Result<GifFile, &'static str> {
+    pub fn new(bytes: &[u8]) -> Result<GifFile, ParseError> {
+        GifFile::decode(bytes, &DecodeOptions::default()).map(|(gif_file, _)| gif_file)
+    }
+
+    pub fn with_limits(bytes: &[u8], limits: &DecodeLimits) -> Result<GifFile, ParseError> {
+        let options = DecodeOptions {
+            limits: *limits,
+            ..DecodeOptions::default()
+        };
+        GifFile::decode(bytes, &options).map(|(gif_file, _)| gif_file)
+    }
+
+    /// Decodes a GIF file, also returning any problems that were worked around.
+    pub fn decode(
+        bytes: &[u8],
+        options: &DecodeOptions,
+    ) -> Result<(GifFile, Vec<DecodeWarning>), ParseError> {
         const TRAILER: &[u8] = &[0x3B];
-        let (bytes, header) = parse_header(bytes).unwrap();
-        let (bytes, logical_screen_descriptor) = parse_logical_screen_descriptor(bytes).unwrap();
-        let (bytes, global_color_table) =
-            parse_global_color_table(bytes, &logical_screen_descriptor).unwrap();
-        let (bytes, frames) = many1(parse_frame)(bytes).unwrap();
-        let (bytes, _) = tag::<&[u8], &[u8], nom::error::Error<&[u8]>>(TRAILER)(bytes).unwrap();
-        let (_, _) = eof::<&[u8], nom::error::Error<&[u8]>>(bytes).unwrap();
-        Ok(GifFile {
+        let (bytes, header) = parse_header(bytes).map_err(|_| ParseError::invalid_header(bytes))?;
+        let limits = &options.limits;
+        let (bytes, mut logical_screen_descriptor) = parse_logical_screen_descriptor(bytes)
+            .map_err(|_| ParseError::InvalidBlock("Logical Screen Descriptor"))?;
+        limits.check_canvas(&logical_screen_descriptor)?;
+        let (mut bytes, global_color_table) =
+            parse_global_color_table(bytes, &logical_screen_descriptor)
+                .map_err(|_| ParseError::InvalidBlock("Global Color Table"))?;
+
+        let mut frames = Vec::new();
+        let mut warnings = Vec::new();
+        let mut total_decoded: u64 = 0;
+        while let Ok((rest, raw_frame)) = parse_frame(bytes) {
+            limits.check_frame_count(frames.len() + 1)?;
+            limits.check_frame(&raw_frame.image_descriptor)?;
+            let (frame, frame_warnings) =
+                raw_frame.decompress(frames.len(), limits, total_decoded)?;
+            warnings.extend(frame_warnings);
+            total_decoded += frame.frame_indices.len() as u64;
+            frames.push(frame);
+            bytes = rest;
+        }
+        if frames.is_empty() {
+            return Err(ParseError::InvalidBlock("Image Descriptor"));
+        }
+        // Some exporters put Comment or Application Extensions
+        // after the last image, right before the trailer.
+        let (bytes, trailing_extensions) =
+            parse_extensions(bytes).map_err(|_| ParseError::InvalidBlock("Extension"))?;
+
+        let (bytes, _) = tag::<&[u8], &[u8], nom::error::Error<&[u8]>>(TRAILER)(bytes)
+            .map_err(|_| ParseError::InvalidBlock("Trailer"))?;
+        let (_, _) = eof::<&[u8], nom::error::Error<&[u8]>>(bytes)
+            .map_err(|_| ParseError::InvalidBlock("Trailer"))?;
+
+        warnings.extend(fit_canvas(
+            &mut logical_screen_descriptor,
+            &frames,
+            options.canvas_mode,
+        ));
+        limits.check_canvas(&logical_screen_descriptor)?;
+        let gif_file = GifFile {
             header,
             logical_screen_descriptor,
             global_color_table,
             frames,
-        })
+            trailing_extensions,
+        };
+        if gif_file.header == GifHeader::GIF87a && gif_file.required_version() == GifHeader::GIF89a
+        {
+            warnings.push(DecodeWarning::Gif89aFeaturesInGif87a);
+        }
+        Ok((gif_file, warnings))
     }
 }
 
 #[cfg(test)]
+#[allow(non_upper_case_globals)]
 mod tests {
     use super::*;
+    use crate::decoder::fixtures::SAMPLE_GIF;
     const leftover: &[u8] = &[127, 42];
 
     #[test]
@@ -500,4 +777,289 @@ mod tests {
             ))
         );
     }
+
+    #[test]
+    fn read_gif_file() {
+        let gif_file = GifFile::new(SAMPLE_GIF).unwrap();
+        assert_eq!(gif_file.header, GifHeader::GIF89a);
+        assert_eq!(gif_file.frames.len(), 2);
+        assert_eq!(gif_file.frames[0].frame_indices.len(), 100);
+    }
+
+    #[test]
+    fn read_compressed_frames() {
+        let frames = compressed_frames(SAMPLE_GIF).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].lzw_minimum_code_size, 2);
+        assert_eq!(
+            lzw::decompress(frames[0].compressed_data.clone(), 2).map(|indices| indices.len()),
+            Ok(100)
+        );
+    }
+
+    #[test]
+    fn decode_limits() {
+        use super::super::LimitKind;
+        let limits = DecodeLimits {
+            max_canvas_pixels: Some(99),
+            ..DecodeLimits::none()
+        };
+        assert_eq!(
+            GifFile::with_limits(SAMPLE_GIF, &limits).err(),
+            Some(ParseError::LimitExceeded {
+                limit: LimitKind::CanvasPixels,
+                max: 99,
+                actual: 100
+            })
+        );
+
+        let limits = DecodeLimits {
+            max_frame_count: Some(1),
+            ..DecodeLimits::none()
+        };
+        assert_eq!(
+            GifFile::with_limits(SAMPLE_GIF, &limits).err(),
+            Some(ParseError::LimitExceeded {
+                limit: LimitKind::FrameCount,
+                max: 1,
+                actual: 2
+            })
+        );
+
+        let limits = DecodeLimits {
+            max_frame_pixels: Some(10),
+            ..DecodeLimits::none()
+        };
+        assert!(matches!(
+            GifFile::with_limits(SAMPLE_GIF, &limits),
+            Err(ParseError::LimitExceeded {
+                limit: LimitKind::FramePixels,
+                ..
+            })
+        ));
+
+        let limits = DecodeLimits {
+            max_total_decoded_bytes: Some(150),
+            ..DecodeLimits::none()
+        };
+        assert!(matches!(
+            GifFile::with_limits(SAMPLE_GIF, &limits),
+            Err(ParseError::LimitExceeded {
+                limit: LimitKind::TotalDecodedBytes,
+                max: 150,
+                ..
+            })
+        ));
+
+        // 22 compressed bytes expand to 100 indices
+        let limits = DecodeLimits {
+            max_compression_ratio: Some(3),
+            ..DecodeLimits::none()
+        };
+        assert!(matches!(
+            GifFile::with_limits(SAMPLE_GIF, &limits),
+            Err(ParseError::LimitExceeded {
+                limit: LimitKind::CompressionRatio,
+                max: 3,
+                ..
+            })
+        ));
+        let limits = DecodeLimits {
+            max_compression_ratio: Some(5),
+            ..DecodeLimits::none()
+        };
+        assert!(GifFile::with_limits(SAMPLE_GIF, &limits).is_ok());
+    }
+
+    #[test]
+    fn decode_tiny_frame_with_huge_stream() {
+        use super::super::LimitKind;
+        // A 1x1 frame whose LZW stream expands to 1 MiB
+        let mut gif_file = GifFile::new(SAMPLE_GIF).unwrap();
+        gif_file.frames.truncate(1);
+        let frame = &mut gif_file.frames[0];
+        frame.image_descriptor.width = 1;
+        frame.image_descriptor.height = 1;
+        frame.frame_indices = vec![1; 1 << 20];
+        let bytes = gif_file.to_bytes();
+        assert!(bytes.len() < 4096);
+        // Only the first index is kept, whatever the limits
+        for limits in [DecodeLimits::default(), DecodeLimits::none()] {
+            let options = DecodeOptions {
+                limits,
+                ..DecodeOptions::default()
+            };
+            let (decoded, warnings) = GifFile::decode(&bytes, &options).unwrap();
+            assert_eq!(decoded.frames[0].frame_indices, vec![1]);
+            assert_eq!(
+                warnings,
+                vec![DecodeWarning::PixelStreamTooLong {
+                    frame: 0,
+                    expected: 1,
+                    actual: 1 << 20
+                }]
+            );
+        }
+        // The whole stream still counts for the limits that are set
+        let limits = DecodeLimits {
+            max_frame_pixels: Some(1000),
+            ..DecodeLimits::none()
+        };
+        assert!(matches!(
+            GifFile::with_limits(&bytes, &limits),
+            Err(ParseError::LimitExceeded {
+                limit: LimitKind::FramePixels,
+                max: 1000,
+                ..
+            })
+        ));
+        let limits = DecodeLimits {
+            max_compression_ratio: Some(10),
+            ..DecodeLimits::none()
+        };
+        assert!(matches!(
+            GifFile::with_limits(&bytes, &limits),
+            Err(ParseError::LimitExceeded {
+                limit: LimitKind::CompressionRatio,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn decode_canvas_modes() {
+        // Same as SAMPLE_GIF but with a 0x0 Logical Screen
+        let mut data = SAMPLE_GIF.to_vec();
+        data[6..10].copy_from_slice(&[0, 0, 0, 0]);
+
+        let (gif_file, warnings) = GifFile::decode(&data, &DecodeOptions::default()).unwrap();
+        assert_eq!(gif_file.logical_screen_descriptor.canvas_width, 0);
+        assert_eq!(
+            warnings,
+            vec![
+                DecodeWarning::ZeroSizedCanvas,
+                DecodeWarning::FrameOutsideCanvas(0),
+                DecodeWarning::FrameOutsideCanvas(1),
+            ]
+        );
+
+        for canvas_mode in [CanvasMode::GrowToFit, CanvasMode::InferFromFirstFrame] {
+            let options = DecodeOptions {
+                canvas_mode,
+                ..DecodeOptions::default()
+            };
+            let (gif_file, warnings) = GifFile::decode(&data, &options).unwrap();
+            assert_eq!(gif_file.logical_screen_descriptor.canvas_width, 10);
+            assert_eq!(gif_file.logical_screen_descriptor.canvas_height, 10);
+            assert_eq!(
+                warnings.last(),
+                Some(&DecodeWarning::CanvasResized {
+                    width: 10,
+                    height: 10
+                })
+            );
+        }
+
+        let (_, warnings) = GifFile::decode(SAMPLE_GIF, &DecodeOptions::default()).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn read_trailing_extensions() {
+        const COMMENT: &[u8] = &[0x21, 0xFE, 0x05, b'h', b'e', b'l', b'l', b'o', 0x00];
+        let data = [&SAMPLE_GIF[..SAMPLE_GIF.len() - 1], COMMENT, &[0x3B]].concat();
+        let gif_file = GifFile::new(&data).unwrap();
+        assert_eq!(gif_file.frames.len(), 2);
+        assert_eq!(
+            gif_file.trailing_extensions,
+            vec![Extension::Comment {
+                text: "hello".into()
+            }]
+        );
+        assert!(GifFile::new(SAMPLE_GIF)
+            .unwrap()
+            .trailing_extensions
+            .is_empty());
+    }
+
+    #[test]
+    fn read_unknown_extensions() {
+        // Label 0x99 with sub-blocks of 2 and 1 bytes
+        const UNKNOWN: &[u8] = &[0x21, 0x99, 0x02, 0xAB, 0xCD, 0x01, 0xEF, 0x00];
+        const SECOND_FRAME: usize = 68;
+        assert_eq!(SAMPLE_GIF[SECOND_FRAME], 0x2C);
+        let data = [
+            &SAMPLE_GIF[..SECOND_FRAME],
+            UNKNOWN,
+            &SAMPLE_GIF[SECOND_FRAME..SAMPLE_GIF.len() - 1],
+            UNKNOWN,
+            &[0x3B],
+        ]
+        .concat();
+        let gif_file = GifFile::new(&data).unwrap();
+        let unknown = || Extension::Unknown {
+            label: 0x99,
+            data: vec![vec![0xAB, 0xCD], vec![0xEF]],
+        };
+        assert_eq!(gif_file.frames.len(), 2);
+        assert_eq!(gif_file.frames[1].extensions, vec![unknown()]);
+        assert_eq!(gif_file.trailing_extensions, vec![unknown()]);
+        assert_eq!(gif_file.to_bytes(), data);
+    }
+
+    #[test]
+    fn decode_corrupted_files() {
+        // Every truncation and a few byte substitutions at every position
+        // must give an error (or a file), never a panic.
+        for len in 0..SAMPLE_GIF.len() {
+            assert!(GifFile::new(&SAMPLE_GIF[..len]).is_err());
+        }
+        let mut data = SAMPLE_GIF.to_vec();
+        for i in 0..data.len() {
+            let original = data[i];
+            for value in [0x00, 0x01, 0x21, 0x2C, 0x3B, 0xF9, 0xFE, 0xFF, !original] {
+                data[i] = value;
+                let _ = GifFile::new(&data);
+                let _ = compressed_frames(&data);
+            }
+            data[i] = original;
+        }
+    }
+
+    #[test]
+    fn read_non_gif_file() {
+        use super::super::DetectedFormat;
+        assert_eq!(
+            GifFile::new(b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR").err(),
+            Some(ParseError::NotAGif {
+                detected: DetectedFormat::Png
+            })
+        );
+        assert_eq!(
+            GifFile::new(b"<!DOCTYPE html><title>404 Not Found</title>").err(),
+            Some(ParseError::NotAGif {
+                detected: DetectedFormat::Html
+            })
+        );
+        assert_eq!(
+            GifFile::new(b"GIF88a\x0a\x00").err(),
+            Some(ParseError::InvalidGifMagic("GIF88a".into()))
+        );
+        assert_eq!(
+            GifFile::new(b"").err(),
+            Some(ParseError::NotAGif {
+                detected: DetectedFormat::Empty
+            })
+        );
+    }
+
+    #[test]
+    fn decode_version_mismatch() {
+        // SAMPLE_GIF has a Graphics Control Extension
+        let mut data = SAMPLE_GIF.to_vec();
+        data[4] = b'7';
+        let (gif_file, warnings) = GifFile::decode(&data, &DecodeOptions::default()).unwrap();
+        assert_eq!(gif_file.header, GifHeader::GIF87a);
+        assert_eq!(warnings, vec![DecodeWarning::Gif89aFeaturesInGif87a]);
+    }
 }
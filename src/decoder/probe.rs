@@ -0,0 +1,230 @@
+use super::parser::{
+    parse_header, parse_image_descriptor, parse_logical_screen_descriptor, skip_data_block,
+};
+use super::{GifHeader, ParseError};
+use nom::bytes::complete::take;
+use nom::number::complete::le_u8;
+use nom::IResult;
+use std::time::Duration;
+
+/// Summary of a GIF file, gathered by only parsing the block headers and
+/// skipping over all the (LZW compressed) image data by length.
+#[derive(Debug, PartialEq, Clone)]
+pub struct GifInfo {
+    pub header: GifHeader,
+    pub canvas_width: u16,
+    pub canvas_height: u16,
+    pub frame_count: usize,
+    // Sum of the delay timers of every frame
+    pub total_duration: Duration,
+    // `None` if there is no NETSCAPE2.0 extension (the animation plays once),
+    // `Some(0)` means it loops forever.
+    pub loop_count: Option<u16>,
+    pub is_animated: bool,
+    // Whether any Graphics Control Extension sets the transparent color flag
+    pub has_transparency: bool,
+}
+
+const EXTENSION_INTRODUCER: u8 = 0x21;
+const IMAGE_SEPARATOR: u8 = 0x2C;
+const TRAILER: u8 = 0x3B;
+
+const GRAPHICS_CONTROL_LABEL: u8 = 0xF9;
+const APPLICATION_LABEL: u8 = 0xFF;
+
+// Delay timers are in hundredths of a second
+const DELAY_UNIT_MS: u64 = 10;
+
+#[derive(Default)]
+struct ProbeState {
+    frame_count: usize,
+    total_delay: u64,
+    pending_delay: Option<u16>,
+    loop_count: Option<u16>,
+    has_transparency: bool,
+}
+
+// A single length-prefixed sub-block. An empty slice means the block terminator was read.
+fn take_subblock(bytes: &[u8]) -> IResult<&[u8], &[u8]> {
+    let (bytes, subblock_length) = le_u8(bytes)?;
+    take(subblock_length)(bytes)
+}
+
+fn skip_color_table(bytes: &[u8], flag: bool, size: u8) -> IResult<&[u8], ()> {
+    if !flag {
+        return Ok((bytes, ()));
+    }
+    let (bytes, _) = take(3 * (2_usize).pow((size + 1).into()))(bytes)?;
+    Ok((bytes, ()))
+}
+
+fn probe_extension<'a>(bytes: &'a [u8], state: &mut ProbeState) -> IResult<&'a [u8], ()> {
+    let (bytes, label) = le_u8(bytes)?;
+    let (bytes, first) = take_subblock(bytes)?;
+    if first.is_empty() {
+        return Ok((bytes, ()));
+    }
+    let bytes = match label {
+        GRAPHICS_CONTROL_LABEL if first.len() >= 4 => {
+            let packed_field = first[0];
+            let delay_timer = u16::from_le_bytes([first[1], first[2]]);
+            state.pending_delay = Some(delay_timer);
+            state.has_transparency |= packed_field & 0x01 != 0;
+            bytes
+        }
+        APPLICATION_LABEL if first == b"NETSCAPE2.0" || first == b"ANIMEXTS1.0" => {
+            let (bytes, data) = take_subblock(bytes)?;
+            if data.is_empty() {
+                return Ok((bytes, ()));
+            }
+            // Sub-block id 1 holds the loop count
+            if data.len() >= 3 && data[0] == 0x01 {
+                state.loop_count = Some(u16::from_le_bytes([data[1], data[2]]));
+            }
+            bytes
+        }
+        _ => bytes,
+    };
+    let (bytes, _) = skip_data_block(bytes)?;
+    Ok((bytes, ()))
+}
+
+fn probe_image<'a>(bytes: &'a [u8], state: &mut ProbeState) -> IResult<&'a [u8], ()> {
+    let (bytes, image_descriptor) = parse_image_descriptor(bytes)?;
+    let (bytes, _) = skip_color_table(
+        bytes,
+        image_descriptor.local_color_table_flag,
+        image_descriptor.local_color_table_size,
+    )?;
+    let (bytes, _lzw_minimum_code_size) = le_u8(bytes)?;
+    let (bytes, _) = skip_data_block(bytes)?;
+
+    state.frame_count += 1;
+    if let Some(delay_timer) = state.pending_delay.take() {
+        state.total_delay += delay_timer as u64;
+    }
+    Ok((bytes, ()))
+}
+
+/// Reads the metadata of a GIF file without decompressing any of the frames.
+pub fn probe(bytes: &[u8]) -> Result<GifInfo, ParseError> {
+    let (bytes, header) = parse_header(bytes).map_err(|_| ParseError::invalid_header(bytes))?;
+    let (bytes, lsd) = parse_logical_screen_descriptor(bytes)
+        .map_err(|_| ParseError::InvalidBlock("Logical Screen Descriptor"))?;
+    let (mut bytes, _) = skip_color_table(
+        bytes,
+        lsd.global_color_table_flag,
+        lsd.global_color_table_size as u8,
+    )
+    .map_err(|_| ParseError::InvalidBlock("Global Color Table"))?;
+
+    let mut state = ProbeState::default();
+    loop {
+        bytes = match bytes.first() {
+            Some(&EXTENSION_INTRODUCER) => {
+                probe_extension(&bytes[1..], &mut state)
+                    .map_err(|_| ParseError::InvalidBlock("Extension"))?
+                    .0
+            }
+            Some(&IMAGE_SEPARATOR) => {
+                probe_image(bytes, &mut state)
+                    .map_err(|_| ParseError::InvalidBlock("Image"))?
+                    .0
+            }
+            Some(&TRAILER) => break,
+            Some(_) => return Err(ParseError::InvalidBlock("Block")),
+            None => return Err(ParseError::InvalidBlock("Trailer")),
+        };
+    }
+
+    Ok(GifInfo {
+        header,
+        canvas_width: lsd.canvas_width,
+        canvas_height: lsd.canvas_height,
+        frame_count: state.frame_count,
+        total_duration: Duration::from_millis(state.total_delay * DELAY_UNIT_MS),
+        loop_count: state.loop_count,
+        is_animated: state.frame_count > 1,
+        has_transparency: state.has_transparency,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::fixtures::{GCE, HEADER, IMAGE, LOGICAL_SCREEN, TRAILER_BLOCK};
+    use crate::decoder::DetectedFormat;
+
+    const NETSCAPE: &[u8] = &[
+        0x21, 0xFF, 0x0B, b'N', b'E', b'T', b'S', b'C', b'A', b'P', b'E', b'2', b'.', b'0', 0x03,
+        0x01, 0x05, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn probe_static_image() {
+        let data = [HEADER, LOGICAL_SCREEN, IMAGE, TRAILER_BLOCK].concat();
+        assert_eq!(
+            probe(&data),
+            Ok(GifInfo {
+                header: GifHeader::GIF89a,
+                canvas_width: 10,
+                canvas_height: 10,
+                frame_count: 1,
+                total_duration: Duration::ZERO,
+                loop_count: None,
+                is_animated: false,
+                has_transparency: false,
+            })
+        );
+    }
+
+    #[test]
+    fn probe_animation() {
+        let data = [
+            HEADER,
+            LOGICAL_SCREEN,
+            NETSCAPE,
+            GCE,
+            IMAGE,
+            GCE,
+            IMAGE,
+            TRAILER_BLOCK,
+        ]
+        .concat();
+        assert_eq!(
+            probe(&data),
+            Ok(GifInfo {
+                header: GifHeader::GIF89a,
+                canvas_width: 10,
+                canvas_height: 10,
+                frame_count: 2,
+                total_duration: Duration::from_millis(200),
+                loop_count: Some(5),
+                is_animated: true,
+                has_transparency: true,
+            })
+        );
+    }
+
+    #[test]
+    fn probe_invalid_files() {
+        assert_eq!(
+            probe(b"\x89PNG\r\n\x1a\n"),
+            Err(ParseError::NotAGif {
+                detected: DetectedFormat::Png
+            })
+        );
+        let truncated = [HEADER, LOGICAL_SCREEN, &IMAGE[..20]].concat();
+        assert_eq!(probe(&truncated), Err(ParseError::InvalidBlock("Image")));
+        let unterminated = [HEADER, LOGICAL_SCREEN, IMAGE].concat();
+        assert_eq!(
+            probe(&unterminated),
+            Err(ParseError::InvalidBlock("Trailer"))
+        );
+        let unknown_block = [HEADER, LOGICAL_SCREEN, &[0x00]].concat();
+        assert_eq!(
+            probe(&unknown_block),
+            Err(ParseError::InvalidBlock("Block"))
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+
+/// File format guessed from the magic numbers at the start of a file.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DetectedFormat {
+    Gif,
+    Png,
+    Jpeg,
+    WebP,
+    Bmp,
+    Tiff,
+    Ico,
+    Avif,
+    Heif,
+    Mp4,
+    QuickTime,
+    // Also covers Matroska
+    WebM,
+    Avi,
+    Pdf,
+    Svg,
+    Html,
+    Empty,
+    Unknown,
+}
+
+impl DetectedFormat {
+    pub fn mime_type(&self) -> Option<&'static str> {
+        use DetectedFormat::*;
+        match self {
+            Gif => Some("image/gif"),
+            Png => Some("image/png"),
+            Jpeg => Some("image/jpeg"),
+            WebP => Some("image/webp"),
+            Bmp => Some("image/bmp"),
+            Tiff => Some("image/tiff"),
+            Ico => Some("image/vnd.microsoft.icon"),
+            Avif => Some("image/avif"),
+            Heif => Some("image/heif"),
+            Mp4 => Some("video/mp4"),
+            QuickTime => Some("video/quicktime"),
+            WebM => Some("video/webm"),
+            Avi => Some("video/x-msvideo"),
+            Pdf => Some("application/pdf"),
+            Svg => Some("image/svg+xml"),
+            Html => Some("text/html"),
+            Empty | Unknown => None,
+        }
+    }
+}
+
+impl fmt::Display for DetectedFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use DetectedFormat::*;
+        let name = match self {
+            Gif => "GIF image",
+            Png => "PNG image",
+            Jpeg => "JPEG image",
+            WebP => "WebP image",
+            Bmp => "BMP image",
+            Tiff => "TIFF image",
+            Ico => "ICO image",
+            Avif => "AVIF image",
+            Heif => "HEIF image",
+            Mp4 => "MP4 video",
+            QuickTime => "QuickTime video",
+            WebM => "WebM video",
+            Avi => "AVI video",
+            Pdf => "PDF document",
+            Svg => "SVG image",
+            Html => "HTML document",
+            Empty => "empty file",
+            Unknown => "unknown file format",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Formats based on the ISO base media file format, identified by the major brand
+fn sniff_ftyp(brand: &[u8]) -> DetectedFormat {
+    match brand {
+        b"avif" | b"avis" => DetectedFormat::Avif,
+        b"heic" | b"heix" | b"hevc" | b"hevx" | b"mif1" | b"msf1" => DetectedFormat::Heif,
+        b"qt  " => DetectedFormat::QuickTime,
+        _ => DetectedFormat::Mp4,
+    }
+}
+
+// Text based formats, which may start with a byte order mark or whitespace
+fn sniff_markup(bytes: &[u8]) -> DetectedFormat {
+    const SNIFF_LEN: usize = 512;
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let text = String::from_utf8_lossy(&bytes[start..bytes.len().min(start + SNIFF_LEN)])
+        .to_ascii_lowercase();
+    if text.starts_with("<svg") || (text.starts_with("<?xml") && text.contains("<svg")) {
+        DetectedFormat::Svg
+    } else if ["<!doctype html", "<html", "<head", "<body", "<!--"]
+        .iter()
+        .any(|tag| text.starts_with(tag))
+    {
+        DetectedFormat::Html
+    } else {
+        DetectedFormat::Unknown
+    }
+}
+
+/// Guesses the format of a file from its first few bytes.
+pub fn sniff(bytes: &[u8]) -> DetectedFormat {
+    use DetectedFormat::*;
+    match bytes {
+        [] => Empty,
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Gif,
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Png,
+        [0xFF, 0xD8, 0xFF, ..] => Jpeg,
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => WebP,
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'A', b'V', b'I', b' ', ..] => Avi,
+        [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..] if brand.len() >= 4 => {
+            sniff_ftyp(&brand[..4])
+        }
+        [0x1A, 0x45, 0xDF, 0xA3, ..] => WebM,
+        [b'I', b'I', 0x2A, 0x00, ..] | [b'M', b'M', 0x00, 0x2A, ..] => Tiff,
+        [b'%', b'P', b'D', b'F', ..] => Pdf,
+        [b'B', b'M', _, _, _, _, 0x00, 0x00, 0x00, 0x00, ..] => Bmp,
+        [0x00, 0x00, 0x01, 0x00, ..] => Ico,
+        _ => sniff_markup(bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniff_formats() {
+        use DetectedFormat::*;
+        assert_eq!(sniff(b"GIF89a\x0a\x00"), Gif);
+        assert_eq!(sniff(b"GIF87a"), Gif);
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR"), Png);
+        assert_eq!(sniff(b"\xFF\xD8\xFF\xE0\x00\x10JFIF"), Jpeg);
+        assert_eq!(sniff(b"RIFF\x24\x00\x00\x00WEBPVP8 "), WebP);
+        assert_eq!(sniff(b"RIFF\x24\x00\x00\x00AVI LIST"), Avi);
+        assert_eq!(sniff(b"\x00\x00\x00\x1cftypavif\x00\x00\x00\x00"), Avif);
+        assert_eq!(sniff(b"\x00\x00\x00\x18ftypheic"), Heif);
+        assert_eq!(sniff(b"\x00\x00\x00\x18ftypisom\x00\x00\x02\x00"), Mp4);
+        assert_eq!(sniff(b"\x00\x00\x00\x14ftypqt  "), QuickTime);
+        assert_eq!(sniff(b"\x1A\x45\xDF\xA3\x9f\x42\x86\x81"), WebM);
+        assert_eq!(sniff(b"II*\x00\x08\x00\x00\x00"), Tiff);
+        assert_eq!(sniff(b"%PDF-1.7"), Pdf);
+        assert_eq!(sniff(b"BM\x36\x00\x0c\x00\x00\x00\x00\x00\x36"), Bmp);
+        assert_eq!(sniff(b"\x00\x00\x01\x00\x01\x00\x10\x10"), Ico);
+        assert_eq!(sniff(b"<svg xmlns=\"http://www.w3.org/2000/svg\">"), Svg);
+        assert_eq!(sniff(b"<?xml version=\"1.0\"?>\n<svg width=\"10\">"), Svg);
+        assert_eq!(
+            sniff(b"\xEF\xBB\xBF\r\n  <!DOCTYPE html><html><body>502 Bad Gateway"),
+            Html
+        );
+        assert_eq!(sniff(b"<HTML><HEAD>"), Html);
+        assert_eq!(sniff(b""), Empty);
+        assert_eq!(sniff(b"GIF88a"), Unknown);
+        assert_eq!(sniff(b"hello world"), Unknown);
+    }
+}
@@ -0,0 +1,125 @@
+use super::lzw::CodeKind;
+use super::{compressed_frames, DecodeLimits, GifFile, ParseError};
+
+/// How well the image data of a single frame is compressed.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FrameStats {
+    // Size of the LZW data, without the sub-block length bytes
+    pub compressed_bytes: usize,
+    pub sub_blocks: usize,
+    // Number of indices in the decompressed stream, one per pixel
+    pub decoded_pixels: usize,
+    pub clear_codes: usize,
+    // Widest code read from the stream, in bits
+    pub max_code_size: u8,
+    // Number of distinct color indices in the frame
+    pub colors_used: usize,
+}
+
+impl FrameStats {
+    /// Decoded pixels per compressed byte, the higher the better.
+    pub fn compression_ratio(&self) -> f64 {
+        if self.compressed_bytes == 0 {
+            return 0.0;
+        }
+        self.decoded_pixels as f64 / self.compressed_bytes as f64
+    }
+}
+
+impl GifFile {
+    /// Compression statistics of every frame in `bytes`, which is decompressed
+    /// within `limits` like `GifFile::with_limits` does.
+    pub fn stats(bytes: &[u8], limits: &DecodeLimits) -> Result<Vec<FrameStats>, ParseError> {
+        let mut total_decoded = 0;
+        compressed_frames(bytes)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                limits.check_frame_count(i + 1)?;
+                let compressed_bytes = frame.compressed_data.len();
+                let sub_blocks = frame.sub_blocks;
+                let mut clear_codes = 0;
+                let mut max_code_size = 0;
+                let decompressed = frame.decompress_traced(limits, total_decoded, &mut |code| {
+                    if code.kind == CodeKind::Clear {
+                        clear_codes += 1;
+                    }
+                    max_code_size = max_code_size.max(code.code_size);
+                })?;
+                total_decoded += decompressed.index_stream.len() as u64;
+                let mut used = [false; 256];
+                for &index in &decompressed.index_stream {
+                    used[index as usize] = true;
+                }
+                Ok(FrameStats {
+                    compressed_bytes,
+                    sub_blocks,
+                    decoded_pixels: decompressed.index_stream.len(),
+                    clear_codes,
+                    max_code_size,
+                    colors_used: used.iter().filter(|&&used| used).count(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::fixtures::{xorshift, HEADER, IMAGE, LOGICAL_SCREEN, TRAILER_BLOCK};
+    use crate::decoder::LimitKind;
+    use crate::encoder::lzw::compress;
+
+    #[test]
+    fn frame_stats() {
+        // 200x200 frame of noise, big enough to fill the code table
+        // and to need more than one sub-block
+        let mut next = xorshift(1);
+        let indices: Vec<u8> = (0..40_000).map(|_| (next() >> 30) as u8).collect();
+        let compressed = compress(&indices, 2);
+        let mut noise = vec![
+            0x2C, 0x00, 0x00, 0x00, 0x00, 0xC8, 0x00, 0xC8, 0x00, 0x00, 0x02,
+        ];
+        for chunk in compressed.chunks(255) {
+            noise.push(chunk.len() as u8);
+            noise.extend(chunk);
+        }
+        noise.push(0x00);
+
+        let data = [HEADER, LOGICAL_SCREEN, IMAGE, &noise, TRAILER_BLOCK].concat();
+        let stats = GifFile::stats(&data, &DecodeLimits::default()).unwrap();
+        assert_eq!(
+            stats[0],
+            FrameStats {
+                compressed_bytes: 22,
+                sub_blocks: 1,
+                decoded_pixels: 100,
+                clear_codes: 1,
+                max_code_size: 6,
+                colors_used: 3,
+            }
+        );
+        assert!((stats[0].compression_ratio() - 100.0 / 22.0).abs() < 1e-9);
+
+        assert_eq!(stats[1].compressed_bytes, compressed.len());
+        assert_eq!(stats[1].sub_blocks, compressed.len().div_ceil(255));
+        assert_eq!(stats[1].decoded_pixels, 40_000);
+        assert!(stats[1].clear_codes > 1);
+        assert_eq!(stats[1].max_code_size, 12);
+        assert_eq!(stats[1].colors_used, 4);
+
+        let limits = DecodeLimits {
+            max_total_decoded_bytes: Some(10_000),
+            ..DecodeLimits::none()
+        };
+        assert!(matches!(
+            GifFile::stats(&data, &limits),
+            Err(ParseError::LimitExceeded {
+                limit: LimitKind::TotalDecodedBytes,
+                max: 10_000,
+                ..
+            })
+        ));
+    }
+}
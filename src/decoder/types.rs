@@ -1,4 +1,5 @@
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GifHeader {
     GIF89a,
     GIF87a,
@@ -14,6 +15,7 @@ impl GifHeader {
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LogicalScreenDescriptor {
     pub canvas_width: u16,
     pub canvas_height: u16,
@@ -31,6 +33,7 @@ pub struct LogicalScreenDescriptor {
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pixel {
     pub red: u8,
     pub green: u8,
@@ -39,15 +42,41 @@ pub struct Pixel {
 
 pub type GlobalColorTable = Vec<Pixel>;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DisposalMethod {
     NoDisposal,
     DoNotDispose,
     RestoreToBackground,
     RestoreToPrevious,
+    // 4 to 7 are "to be defined" by the spec, decoders treat them as `NoDisposal`
+    Undefined(u8),
+}
+
+impl DisposalMethod {
+    pub fn from(value: u8) -> DisposalMethod {
+        match value {
+            0 => DisposalMethod::NoDisposal,
+            1 => DisposalMethod::DoNotDispose,
+            2 => DisposalMethod::RestoreToBackground,
+            3 => DisposalMethod::RestoreToPrevious,
+            x => DisposalMethod::Undefined(x),
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match self {
+            DisposalMethod::NoDisposal => 0,
+            DisposalMethod::DoNotDispose => 1,
+            DisposalMethod::RestoreToBackground => 2,
+            DisposalMethod::RestoreToPrevious => 3,
+            DisposalMethod::Undefined(x) => *x,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Extension {
     GraphicsControlExtension {
         reserved: u8,
@@ -59,20 +88,37 @@ pub enum Extension {
         transparent_color_index: u8,
     },
     PlainText {
-        text: String,
+        // Position and size of the text grid on the Logical Screen, in pixels
+        text_grid_left: u16,
+        text_grid_top: u16,
+        text_grid_width: u16,
+        text_grid_height: u16,
+        cell_width: u8,
+        cell_height: u8,
+        text_foreground_color_index: u8,
+        text_background_color_index: u8,
+        // Raw bytes, meant to be 7-bit ASCII
+        text: Vec<u8>,
     },
     Application {
-        identifier: String,
-        authentication_code: String,
-        // Custom data for application-specific purposes
-        data: Vec<u8>,
+        identifier: [u8; 8],
+        authentication_code: [u8; 3],
+        // Custom data for application-specific purposes, one entry per sub-block
+        data: Vec<Vec<u8>>,
     },
     Comment {
-        text: String,
+        // Raw bytes, meant to be 7-bit ASCII but anything goes in practice
+        text: Vec<u8>,
+    },
+    // Extension with a label this crate doesn't know, kept so it can be written back
+    Unknown {
+        label: u8,
+        data: Vec<Vec<u8>>,
     },
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ImageDescriptor {
     pub left: u16,
     pub top: u16,
@@ -88,7 +134,17 @@ pub struct ImageDescriptor {
 pub type LocalColorTable = Vec<Pixel>;
 pub type FrameIndices = Vec<u8>;
 
-#[derive(Debug)]
+/// Image data of a frame as stored in the file, before LZW decompression.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CompressedFrame {
+    pub lzw_minimum_code_size: u8,
+    // All the sub-blocks joined together
+    pub compressed_data: Vec<u8>,
+    pub sub_blocks: usize,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GifFrame {
     pub image_descriptor: ImageDescriptor,
     pub local_color_table: Option<LocalColorTable>,
@@ -96,9 +152,82 @@ pub struct GifFrame {
     pub extensions: Vec<Extension>,
 }
 
+impl GifFrame {
+    // There should be at most one Graphics Control Extension per frame,
+    // if there are more then the last one wins.
+    fn graphics_control(&self) -> Option<&Extension> {
+        self.extensions
+            .iter()
+            .rev()
+            .find(|ext| matches!(ext, Extension::GraphicsControlExtension { .. }))
+    }
+
+    pub fn disposal_method(&self) -> DisposalMethod {
+        match self.graphics_control() {
+            Some(Extension::GraphicsControlExtension {
+                disposal_method, ..
+            }) => *disposal_method,
+            _ => DisposalMethod::NoDisposal,
+        }
+    }
+
+    // In hundredths of a second
+    pub fn delay_timer(&self) -> u16 {
+        match self.graphics_control() {
+            Some(Extension::GraphicsControlExtension { delay_timer, .. }) => *delay_timer,
+            _ => 0,
+        }
+    }
+
+    // Whether the viewer should wait for user input before the next frame
+    pub fn user_input_flag(&self) -> bool {
+        match self.graphics_control() {
+            Some(Extension::GraphicsControlExtension {
+                user_input_flag, ..
+            }) => *user_input_flag,
+            _ => false,
+        }
+    }
+
+    pub fn transparent_color_index(&self) -> Option<u8> {
+        match self.graphics_control() {
+            Some(Extension::GraphicsControlExtension {
+                transparent_color_flag: true,
+                transparent_color_index,
+                ..
+            }) => Some(*transparent_color_index),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GifFile {
     pub header: GifHeader,
     pub logical_screen_descriptor: LogicalScreenDescriptor,
     pub global_color_table: Option<GlobalColorTable>,
     pub frames: Vec<GifFrame>,
+    // Extensions after the last image, right before the trailer
+    pub trailing_extensions: Vec<Extension>,
+}
+
+impl GifFile {
+    /// `GIF89a` if the file uses anything that was added in it (any extension,
+    /// sort flags or a pixel aspect ratio), otherwise `GIF87a`.
+    pub fn required_version(&self) -> GifHeader {
+        let lsd = &self.logical_screen_descriptor;
+        let uses_89a = !self.trailing_extensions.is_empty()
+            || lsd.sort_flag
+            || lsd.pixel_aspect_ratio != 0
+            || self
+                .frames
+                .iter()
+                .any(|frame| !frame.extensions.is_empty() || frame.image_descriptor.sort_flag);
+        if uses_89a {
+            GifHeader::GIF89a
+        } else {
+            GifHeader::GIF87a
+        }
+    }
 }
@@ -0,0 +1,160 @@
+use std::sync::OnceLock;
+
+// Side of the tiled threshold texture
+pub(super) const SIZE: usize = 32;
+// Spread of the gaussian "energy" every dot puts on its neighbours
+const SIGMA: f32 = 1.5;
+
+// Energy of every pixel: how crowded it is by the dots around it, on a torus
+struct Energy {
+    dots: Vec<bool>,
+    energy: Vec<f32>,
+    // Gaussian falloff by (dx, dy), already wrapped around
+    kernel: Vec<f32>,
+}
+
+impl Energy {
+    fn new(dots: Vec<bool>) -> Self {
+        let kernel = (0..SIZE * SIZE)
+            .map(|i| {
+                let wrap = |d: usize| d.min(SIZE - d) as f32;
+                let (dx, dy) = (wrap(i % SIZE), wrap(i / SIZE));
+                (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+            })
+            .collect();
+        let mut ret = Energy {
+            dots: vec![false; SIZE * SIZE],
+            energy: vec![0.0; SIZE * SIZE],
+            kernel,
+        };
+        for (i, dot) in dots.into_iter().enumerate() {
+            if dot {
+                ret.set(i, true);
+            }
+        }
+        ret
+    }
+
+    fn set(&mut self, position: usize, dot: bool) {
+        self.dots[position] = dot;
+        let sign = if dot { 1.0 } else { -1.0 };
+        let (x, y) = (position % SIZE, position / SIZE);
+        for (i, energy) in self.energy.iter_mut().enumerate() {
+            let dx = (i % SIZE + SIZE - x) % SIZE;
+            let dy = (i / SIZE + SIZE - y) % SIZE;
+            *energy += sign * self.kernel[dy * SIZE + dx];
+        }
+    }
+
+    // Dot with the most energy around it
+    fn tightest_cluster(&self) -> usize {
+        self.extreme(true, |a, b| a > b)
+    }
+
+    // Empty pixel with the least energy around it
+    fn largest_void(&self) -> usize {
+        self.extreme(false, |a, b| a < b)
+    }
+
+    fn extreme(&self, dot: bool, better: impl Fn(f32, f32) -> bool) -> usize {
+        let mut best = None;
+        for (i, &energy) in self.energy.iter().enumerate() {
+            if self.dots[i] == dot && best.is_none_or(|(_, e)| better(energy, e)) {
+                best = Some((i, energy));
+            }
+        }
+        best.map_or(0, |(i, _)| i)
+    }
+}
+
+// Ulichney's void-and-cluster method: every pixel gets a rank such that the
+// pixels below any threshold are spread out as evenly as possible.
+fn void_and_cluster() -> Vec<u16> {
+    let len = SIZE * SIZE;
+    // About 10% of random dots to start from
+    let mut state: u32 = 0x1234_5678;
+    let initial: Vec<bool> = (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state.is_multiple_of(10)
+        })
+        .collect();
+
+    // Spread the initial dots out, until moving the tightest one doesn't help
+    let mut pattern = Energy::new(initial);
+    for _ in 0..len {
+        let cluster = pattern.tightest_cluster();
+        pattern.set(cluster, false);
+        let void = pattern.largest_void();
+        pattern.set(void, true);
+        if void == cluster {
+            break;
+        }
+    }
+    let initial = pattern.dots.clone();
+    let ones = initial.iter().filter(|&&dot| dot).count();
+    let mut ranks = vec![0; len];
+
+    // Ranks below the initial dots: remove them, tightest first
+    let mut energy = Energy::new(initial.clone());
+    for rank in (0..ones).rev() {
+        let cluster = energy.tightest_cluster();
+        energy.set(cluster, false);
+        ranks[cluster] = rank as u16;
+    }
+
+    // Up to half full: fill the largest voids
+    let mut energy = Energy::new(initial);
+    for rank in ones..len / 2 {
+        let void = energy.largest_void();
+        energy.set(void, true);
+        ranks[void] = rank as u16;
+    }
+
+    // Above half, the empty pixels become the minority: fill their tightest cluster
+    let mut energy = Energy::new(energy.dots.iter().map(|&dot| !dot).collect());
+    for rank in len / 2..len {
+        let cluster = energy.tightest_cluster();
+        energy.set(cluster, false);
+        ranks[cluster] = rank as u16;
+    }
+    ranks
+}
+
+/// Rank of every pixel of the 32x32 blue noise texture, from 0 to 1023.
+pub(super) fn blue_noise() -> &'static [u16] {
+    static RANKS: OnceLock<Vec<u16>> = OnceLock::new();
+    RANKS.get_or_init(void_and_cluster)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blue_noise_ranks() {
+        let ranks = blue_noise();
+        let mut sorted = ranks.to_vec();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..1024).collect::<Vec<u16>>());
+
+        // Any threshold gives evenly spread dots: no 8x8 tile is far off
+        for threshold in [64, 256, 512, 900] {
+            for tile in 0..16 {
+                let (left, top) = (tile % 4 * 8, tile / 4 * 8);
+                let dots = (0..64)
+                    .filter(|i| ranks[(top + i / 8) * SIZE + left + i % 8] < threshold)
+                    .count();
+                let expected = threshold as usize / 16;
+                assert!(
+                    dots.abs_diff(expected) <= expected / 3 + 3,
+                    "{} {}",
+                    threshold,
+                    dots
+                );
+            }
+        }
+    }
+}
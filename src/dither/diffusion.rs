@@ -0,0 +1,114 @@
+use super::{DitherMethod, Target};
+
+// (dx, dy, weight) of the neighbours the error goes to, for left to right scanning
+type Kernel = (&'static [(isize, usize, f32)], f32);
+
+const FLOYD_STEINBERG: Kernel = (&[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0);
+// Only 3/4 of the error is passed on, which keeps more contrast
+const ATKINSON: Kernel = (
+    &[
+        (1, 0, 1.0),
+        (2, 0, 1.0),
+        (-1, 1, 1.0),
+        (0, 1, 1.0),
+        (1, 1, 1.0),
+        (0, 2, 1.0),
+    ],
+    8.0,
+);
+const SIERRA: Kernel = (
+    &[
+        (1, 0, 5.0),
+        (2, 0, 3.0),
+        (-2, 1, 2.0),
+        (-1, 1, 4.0),
+        (0, 1, 5.0),
+        (1, 1, 4.0),
+        (2, 1, 2.0),
+        (-1, 2, 2.0),
+        (0, 2, 3.0),
+        (1, 2, 2.0),
+    ],
+    32.0,
+);
+const SIERRA_LITE: Kernel = (&[(1, 0, 2.0), (-1, 1, 1.0), (0, 1, 1.0)], 4.0);
+const JARVIS_JUDICE_NINKE: Kernel = (
+    &[
+        (1, 0, 7.0),
+        (2, 0, 5.0),
+        (-2, 1, 3.0),
+        (-1, 1, 5.0),
+        (0, 1, 7.0),
+        (1, 1, 5.0),
+        (2, 1, 3.0),
+        (-2, 2, 1.0),
+        (-1, 2, 3.0),
+        (0, 2, 5.0),
+        (1, 2, 3.0),
+        (2, 2, 1.0),
+    ],
+    48.0,
+);
+
+pub(super) fn kernel(method: DitherMethod) -> Option<Kernel> {
+    match method {
+        DitherMethod::FloydSteinberg => Some(FLOYD_STEINBERG),
+        DitherMethod::Atkinson => Some(ATKINSON),
+        DitherMethod::Sierra => Some(SIERRA),
+        DitherMethod::SierraLite => Some(SIERRA_LITE),
+        DitherMethod::JarvisJudiceNinke => Some(JARVIS_JUDICE_NINKE),
+        _ => None,
+    }
+}
+
+/// Maps every pixel to the palette and spreads the difference over the pixels
+/// that haven't been mapped yet. Odd rows go right to left if `serpentine`.
+pub(super) fn diffuse(
+    target: &mut Target,
+    (offsets, divisor): Kernel,
+    serpentine: bool,
+) -> Vec<u8> {
+    let (width, height) = (target.width, target.height);
+    // Error carried over to every pixel, in RGB
+    let mut errors = vec![[0f32; 3]; width * height];
+    let mut indices = vec![0; target.len()];
+    let scale = target.strength / divisor;
+    for y in 0..height {
+        let reversed = serpentine && y % 2 == 1;
+        for step in 0..width {
+            let x = if reversed { width - 1 - step } else { step };
+            let i = y * width + x;
+            if i >= target.len() {
+                continue;
+            }
+            if let Some(transparent_index) = target.transparent(i) {
+                indices[i] = transparent_index;
+                continue;
+            }
+            let color = target.color(i);
+            let wanted = [0, 1, 2].map(|c| color[c] as f32 + errors[i][c]);
+            // Unchanged pixels keep their index, but still pass their error on
+            let index = match target.reused(i) {
+                Some(index) => index,
+                None => target.nearest(wanted),
+            };
+            indices[i] = index;
+            let got = target.palette_color(index);
+            let error = [0, 1, 2].map(|c| (wanted[c] - got[c] as f32) * scale);
+            for &(dx, dy, weight) in offsets {
+                let dx = if reversed { -dx } else { dx };
+                let (Some(nx), ny) = (x.checked_add_signed(dx), y + dy) else {
+                    continue;
+                };
+                if nx >= width || ny >= height {
+                    continue;
+                }
+                let neighbour = &mut errors[ny * width + nx];
+                for c in 0..3 {
+                    neighbour[c] += error[c] * weight;
+                }
+            }
+        }
+    }
+    indices
+}
@@ -0,0 +1,346 @@
+mod blue_noise;
+mod diffusion;
+mod temporal;
+pub use temporal::*;
+
+use crate::decoder::Pixel;
+use crate::quantize::{ColorLookup, ColorSpace};
+use std::collections::HashMap;
+
+/// How the difference between a pixel and its closest palette color is hidden.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum DitherMethod {
+    // Plain nearest color
+    None,
+    // Error diffusion, each passing the error of a pixel on to its neighbours
+    #[default]
+    FloydSteinberg,
+    Atkinson,
+    Sierra,
+    SierraLite,
+    JarvisJudiceNinke,
+    // Ordered dithering, a fixed threshold pattern that doesn't crawl in animations
+    Bayer2x2,
+    Bayer4x4,
+    Bayer8x8,
+    // Ordered dithering with a 32x32 void-and-cluster pattern, less regular than Bayer
+    BlueNoise,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DitherOptions {
+    pub method: DitherMethod,
+    // From 0.0 (no dithering) to 1.0 (the full error or threshold)
+    pub strength: f32,
+    // Scan odd rows right to left, which avoids diagonal artifacts of error diffusion
+    pub serpentine: bool,
+    // Space the closest palette color is looked up in, see `QuantizeOptions`
+    pub color_space: ColorSpace,
+    // Pixels with a lower alpha get the transparent index, if there is one
+    pub alpha_threshold: u8,
+}
+
+impl Default for DitherOptions {
+    fn default() -> Self {
+        DitherOptions {
+            method: DitherMethod::default(),
+            strength: 1.0,
+            serpentine: true,
+            color_space: ColorSpace::default(),
+            alpha_threshold: 128,
+        }
+    }
+}
+
+// The image being mapped and the palette it is mapped to
+struct Target<'a> {
+    rgba: &'a [u8],
+    width: usize,
+    height: usize,
+    color_table: &'a [Pixel],
+    transparent_index: Option<u8>,
+    alpha_threshold: u8,
+    strength: f32,
+    lookup: ColorLookup,
+    // Dithered colors are rounded, so the same ones come up over and over
+    cache: HashMap<[u8; 3], u8>,
+    // (rgba, indices) of the previous frame, whose indices are kept for unchanged pixels
+    previous: Option<(&'a [u8], &'a [u8])>,
+}
+
+impl<'a> Target<'a> {
+    fn new(
+        rgba: &'a [u8],
+        width: usize,
+        color_table: &'a [Pixel],
+        transparent_index: Option<u8>,
+        options: &DitherOptions,
+    ) -> Self {
+        let len = rgba.len() / 4;
+        // A width of 0 is taken as a single row
+        let width = if width == 0 { len.max(1) } else { width };
+        Target {
+            rgba,
+            width,
+            height: len.div_ceil(width),
+            color_table,
+            transparent_index,
+            alpha_threshold: options.alpha_threshold,
+            strength: options.strength.clamp(0.0, 1.0),
+            lookup: ColorLookup::opaque(color_table, options.color_space, transparent_index),
+            cache: HashMap::new(),
+            previous: None,
+        }
+    }
+
+    fn dither(&mut self, options: &DitherOptions) -> Vec<u8> {
+        if let Some(kernel) = diffusion::kernel(options.method) {
+            return diffusion::diffuse(self, kernel, options.serpentine);
+        }
+        // Thresholds are anchored to the canvas, so they are the same in every frame
+        let size = match options.method {
+            DitherMethod::Bayer2x2 => 2,
+            DitherMethod::Bayer4x4 => 4,
+            DitherMethod::Bayer8x8 => 8,
+            DitherMethod::BlueNoise => {
+                let ranks = blue_noise::blue_noise();
+                let size = blue_noise::SIZE;
+                return self.ordered(|x, y| {
+                    centered(ranks[(y % size) * size + x % size] as usize, size * size)
+                });
+            }
+            _ => return self.ordered(|_, _| 0.0),
+        };
+        let matrix = bayer(size);
+        self.ordered(|x, y| centered(matrix[(y % size) * size + x % size], size * size))
+    }
+
+    fn len(&self) -> usize {
+        self.rgba.len() / 4
+    }
+
+    // The transparent index, if pixel `i` should use it
+    fn transparent(&self, i: usize) -> Option<u8> {
+        self.transparent_index
+            .filter(|_| self.rgba[i * 4 + 3] < self.alpha_threshold)
+    }
+
+    // The index pixel `i` had in the previous frame, if its color hasn't changed since
+    fn reused(&self, i: usize) -> Option<u8> {
+        let (rgba, indices) = self.previous?;
+        let pixel = i * 4..i * 4 + 4;
+        (rgba.get(pixel.clone()) == self.rgba.get(pixel))
+            .then(|| indices.get(i).copied())
+            .flatten()
+    }
+
+    fn color(&self, i: usize) -> [u8; 3] {
+        [self.rgba[i * 4], self.rgba[i * 4 + 1], self.rgba[i * 4 + 2]]
+    }
+
+    fn palette_color(&self, index: u8) -> [u8; 3] {
+        self.color_table
+            .get(index as usize)
+            .map_or([0; 3], |pixel| [pixel.red, pixel.green, pixel.blue])
+    }
+
+    fn nearest(&mut self, color: [f32; 3]) -> u8 {
+        let [red, green, blue] = color.map(|value| value.round().clamp(0.0, 255.0) as u8);
+        let lookup = &self.lookup;
+        *self
+            .cache
+            .entry([red, green, blue])
+            .or_insert_with(|| lookup.nearest(Pixel { red, green, blue }))
+    }
+
+    // Shifts every pixel by `threshold(x, y)` (in -0.5..0.5) times the distance
+    // between palette colors before looking it up
+    fn ordered(&mut self, threshold: impl Fn(usize, usize) -> f32) -> Vec<u8> {
+        // Distance between neighbouring levels of a channel, if the palette was an
+        // evenly spread cube with at least 2 levels per channel
+        let levels = (self.lookup_len() as f32).cbrt().max(2.0);
+        let spread = 255.0 / (levels - 1.0) * self.strength;
+        (0..self.len())
+            .map(|i| {
+                if let Some(index) = self.transparent(i).or_else(|| self.reused(i)) {
+                    return index;
+                }
+                let offset = threshold(i % self.width, i / self.width) * spread;
+                let color = self.color(i).map(|value| value as f32 + offset);
+                self.nearest(color)
+            })
+            .collect()
+    }
+
+    fn lookup_len(&self) -> usize {
+        self.color_table
+            .len()
+            .saturating_sub(self.transparent_index.is_some() as usize)
+    }
+}
+
+/// Threshold matrix of size `n` (a power of two), values from 0 to n*n-1.
+fn bayer(n: usize) -> Vec<usize> {
+    if n <= 1 {
+        return vec![0];
+    }
+    let half = bayer(n / 2);
+    (0..n * n)
+        .map(|i| {
+            let (x, y) = (i % n, i / n);
+            let quadrant = match (x * 2 / n, y * 2 / n) {
+                (0, 0) => 0,
+                (1, 1) => 1,
+                (1, 0) => 2,
+                _ => 3,
+            };
+            4 * half[(y % (n / 2)) * (n / 2) + x % (n / 2)] + quadrant
+        })
+        .collect()
+}
+
+// Rank out of `levels` to a threshold centered on 0
+fn centered(rank: usize, levels: usize) -> f32 {
+    (rank as f32 + 0.5) / levels as f32 - 0.5
+}
+
+/// Maps `rgba` (4 bytes per pixel, `width` pixels per row) to `color_table`.
+/// `transparent_index` is used for transparent pixels and never for opaque
+/// ones, usually the color table comes from `quantize`.
+pub fn dither(
+    rgba: &[u8],
+    width: usize,
+    color_table: &[Pixel],
+    transparent_index: Option<u8>,
+    options: DitherOptions,
+) -> Vec<u8> {
+    Target::new(rgba, width, color_table, transparent_index, &options).dither(&options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METHODS: [DitherMethod; 10] = [
+        DitherMethod::None,
+        DitherMethod::FloydSteinberg,
+        DitherMethod::Atkinson,
+        DitherMethod::Sierra,
+        DitherMethod::SierraLite,
+        DitherMethod::JarvisJudiceNinke,
+        DitherMethod::Bayer2x2,
+        DitherMethod::Bayer4x4,
+        DitherMethod::Bayer8x8,
+        DitherMethod::BlueNoise,
+    ];
+
+    fn black_and_white() -> Vec<Pixel> {
+        vec![
+            Pixel {
+                red: 0,
+                green: 0,
+                blue: 0,
+            },
+            Pixel {
+                red: 255,
+                green: 255,
+                blue: 255,
+            },
+        ]
+    }
+
+    fn gray(value: u8, pixels: usize) -> Vec<u8> {
+        [value, value, value, 255].repeat(pixels)
+    }
+
+    #[test]
+    fn bayer_matrices() {
+        assert_eq!(bayer(2), vec![0, 2, 3, 1]);
+        assert_eq!(
+            bayer(4),
+            vec![0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5]
+        );
+        let mut matrix = bayer(8);
+        matrix.sort_unstable();
+        assert_eq!(matrix, (0..64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn dither_gray() {
+        // A quarter gray over black and white should come out about a quarter white
+        let rgba = gray(64, 32 * 32);
+        for method in METHODS {
+            for serpentine in [false, true] {
+                let options = DitherOptions {
+                    method,
+                    serpentine,
+                    ..Default::default()
+                };
+                let indices = dither(&rgba, 32, &black_and_white(), None, options);
+                assert_eq!(indices.len(), 32 * 32);
+                let white = indices.iter().filter(|&&i| i == 1).count();
+                match method {
+                    DitherMethod::None => assert_eq!(white, 0),
+                    // Drops a quarter of the error, so light dots are lost
+                    DitherMethod::Atkinson => assert!((128..=256).contains(&white)),
+                    _ => assert!((200..=320).contains(&white), "{:?} {}", method, white),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dither_strength() {
+        let rgba = gray(100, 16 * 16);
+        for method in METHODS {
+            let options = |strength| DitherOptions {
+                method,
+                strength,
+                ..Default::default()
+            };
+            // No strength is the plain nearest color
+            let indices = dither(&rgba, 16, &black_and_white(), None, options(0.0));
+            assert_eq!(indices, vec![0; 16 * 16], "{:?}", method);
+            let half = dither(&rgba, 16, &black_and_white(), None, options(0.5));
+            let full = dither(&rgba, 16, &black_and_white(), None, options(1.0));
+            let white = |indices: &[u8]| indices.iter().filter(|&&i| i == 1).count();
+            assert!(white(&half) <= white(&full), "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn dither_transparency() {
+        let mut color_table = black_and_white();
+        color_table.push(Pixel {
+            red: 0,
+            green: 0,
+            blue: 0,
+        });
+        let mut rgba = gray(0, 10 * 10);
+        for pixel in rgba.chunks_exact_mut(4).step_by(7) {
+            pixel[3] = 0;
+        }
+        for method in METHODS {
+            let options = DitherOptions {
+                method,
+                ..Default::default()
+            };
+            let indices = dither(&rgba, 10, &color_table, Some(2), options);
+            for (i, &index) in indices.iter().enumerate() {
+                // Black opaque pixels never get the black transparent entry
+                assert_eq!(index, if i % 7 == 0 { 2 } else { 0 }, "{:?}", method);
+            }
+        }
+
+        // Partial last row and no rows at all
+        let options = DitherOptions::default();
+        assert_eq!(
+            dither(&gray(255, 7), 3, &black_and_white(), None, options).len(),
+            7
+        );
+        assert_eq!(
+            dither(&[], 0, &black_and_white(), None, options),
+            Vec::<u8>::new()
+        );
+    }
+}
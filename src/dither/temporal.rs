@@ -0,0 +1,155 @@
+use super::{DitherOptions, Target};
+use crate::decoder::Pixel;
+
+// What the last frame was mapped to
+struct PreviousFrame {
+    rgba: Vec<u8>,
+    indices: Vec<u8>,
+    color_table: Vec<Pixel>,
+    transparent_index: Option<u8>,
+}
+
+/// Dithers the frames of an animation one after the other. Pixels that are
+/// the same as in the previous frame keep their index, so that static parts
+/// don't shimmer and unchanged regions compress (and optimize) well.
+///
+/// Frames are whole canvases of `width` pixels per row. Ordered methods
+/// (`Bayer*`, `BlueNoise`) work best here, their pattern is anchored to the
+/// canvas and only changed pixels are touched at all.
+pub struct TemporalDitherer {
+    width: usize,
+    options: DitherOptions,
+    previous: Option<PreviousFrame>,
+}
+
+impl TemporalDitherer {
+    pub fn new(width: usize, options: DitherOptions) -> Self {
+        TemporalDitherer {
+            width,
+            options,
+            previous: None,
+        }
+    }
+
+    /// Like `dither`, for the next frame of the animation. Indices are only
+    /// reused if the color table and transparent index didn't change either.
+    pub fn dither_frame(
+        &mut self,
+        rgba: &[u8],
+        color_table: &[Pixel],
+        transparent_index: Option<u8>,
+    ) -> Vec<u8> {
+        let mut target = Target::new(
+            rgba,
+            self.width,
+            color_table,
+            transparent_index,
+            &self.options,
+        );
+        target.previous = self
+            .previous
+            .as_ref()
+            .filter(|previous| {
+                previous.color_table == color_table
+                    && previous.transparent_index == transparent_index
+            })
+            .map(|previous| (previous.rgba.as_slice(), previous.indices.as_slice()));
+        let indices = target.dither(&self.options);
+        self.previous = Some(PreviousFrame {
+            rgba: rgba.to_vec(),
+            indices: indices.clone(),
+            color_table: color_table.to_vec(),
+            transparent_index,
+        });
+        indices
+    }
+
+    /// Forgets the previous frame, e.g. after a frame that restores the background.
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{dither, DitherMethod};
+    use super::*;
+
+    const WIDTH: usize = 24;
+
+    fn palette() -> Vec<Pixel> {
+        (0..8)
+            .map(|i| Pixel {
+                red: (i & 1) * 255,
+                green: ((i >> 1) & 1) * 255,
+                blue: ((i >> 2) & 1) * 255,
+            })
+            .collect()
+    }
+
+    // A gradient, with a square moved `offset` pixels to the right
+    fn frame(offset: usize) -> Vec<u8> {
+        (0..WIDTH * WIDTH)
+            .flat_map(|i| {
+                let (x, y) = (i % WIDTH, i / WIDTH);
+                if (offset..offset + 4).contains(&x) && (2..6).contains(&y) {
+                    [250, 20, 20, 255]
+                } else {
+                    [(x * 10) as u8, (y * 10) as u8, 128, 255]
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unchanged_pixels_keep_their_index() {
+        let (first, second) = (frame(2), frame(3));
+        let changed = |i: usize| first[i * 4..i * 4 + 4] != second[i * 4..i * 4 + 4];
+        for method in [
+            DitherMethod::FloydSteinberg,
+            DitherMethod::JarvisJudiceNinke,
+            DitherMethod::Bayer4x4,
+            DitherMethod::BlueNoise,
+        ] {
+            let options = DitherOptions {
+                method,
+                ..Default::default()
+            };
+            let mut ditherer = TemporalDitherer::new(WIDTH, options);
+            let before = ditherer.dither_frame(&first, &palette(), None);
+            assert_eq!(before, dither(&first, WIDTH, &palette(), None, options));
+            let after = ditherer.dither_frame(&second, &palette(), None);
+            let independent = dither(&second, WIDTH, &palette(), None, options);
+
+            let differences = |indices: &[u8]| {
+                (0..WIDTH * WIDTH)
+                    .filter(|&i| !changed(i) && indices[i] != before[i])
+                    .count()
+            };
+            assert_eq!(differences(&after), 0, "{:?}", method);
+            if method == DitherMethod::FloydSteinberg {
+                // The change of the square ripples through the rest of the frame
+                assert!(differences(&independent) > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn new_color_table_dithers_again() {
+        let options = DitherOptions::default();
+        let mut ditherer = TemporalDitherer::new(WIDTH, options);
+        let first = frame(0);
+        ditherer.dither_frame(&first, &palette(), None);
+        let mut gray = palette();
+        gray.truncate(2);
+        assert_eq!(
+            ditherer.dither_frame(&first, &gray, None),
+            dither(&first, WIDTH, &gray, None, options)
+        );
+        ditherer.reset();
+        assert_eq!(
+            ditherer.dither_frame(&first, &gray, None),
+            dither(&first, WIDTH, &gray, None, options)
+        );
+    }
+}
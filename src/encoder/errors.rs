@@ -0,0 +1,39 @@
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum EncodeError {
+    // A GIF needs at least one image
+    NoFrames,
+    // The RGBA data of a frame doesn't match the canvas
+    FrameSizeMismatch {
+        frame: usize,
+        expected: usize,
+        actual: usize,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use EncodeError::*;
+        match self {
+            NoFrames => write!(f, "Unable to encode a GIF without frames!"),
+            FrameSizeMismatch {
+                frame,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Frame {} has {} bytes of RGBA data but the canvas needs {}!",
+                frame, actual, expected
+            ),
+            Io(err) => write!(f, "Unable to write GIF: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for EncodeError {
+    fn from(err: io::Error) -> Self {
+        EncodeError::Io(err)
+    }
+}
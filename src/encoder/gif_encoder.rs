@@ -0,0 +1,409 @@
+use super::{color_table_size, encode_with, padded, EncodeError, EncodeOptions, LossyOptions};
+use crate::decoder::{
+    DisposalMethod, Extension, GifFile, GifFrame, GifHeader, ImageDescriptor,
+    LogicalScreenDescriptor, Pixel,
+};
+use crate::dither::{DitherOptions, TemporalDitherer};
+use crate::quantize::{quantize, quantize_frames, QuantizeOptions};
+use std::io::Write;
+use std::time::Duration;
+
+/// Where the colors of every frame are stored.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum PaletteMode {
+    // One Global Color Table for all frames, smaller and steadier between frames
+    #[default]
+    Global,
+    // A Local Color Table per frame, better colors when frames differ a lot
+    Local,
+}
+
+/// Builds a GIF out of RGBA frames, taking care of quantization, transparency,
+/// color tables and compression.
+///
+/// Every frame covers the whole canvas, pixels with an alpha below
+/// `QuantizeOptions::alpha_threshold` are transparent.
+#[derive(Debug, Clone)]
+pub struct GifEncoder {
+    width: u16,
+    height: u16,
+    // Number of times to repeat the animation, 0 is forever
+    loop_count: Option<u16>,
+    palette_mode: PaletteMode,
+    quantize_options: QuantizeOptions,
+    // Plain nearest color if not set
+    dither_options: Option<DitherOptions>,
+    encode_options: EncodeOptions,
+    frames: Vec<(Vec<u8>, Duration)>,
+}
+
+// Delay in hundredths of a second, rounded to the nearest one
+pub(super) fn delay_timer(delay: Duration) -> u16 {
+    ((delay.as_millis() + 5) / 10).min(u16::MAX as u128) as u16
+}
+
+pub(super) fn logical_screen(
+    width: u16,
+    height: u16,
+    global_color_table: Option<&[Pixel]>,
+) -> LogicalScreenDescriptor {
+    LogicalScreenDescriptor {
+        canvas_width: width,
+        canvas_height: height,
+        global_color_table_flag: global_color_table.is_some(),
+        color_resolution: 7,
+        sort_flag: false,
+        global_color_table_size: global_color_table
+            .map_or(0, |gct| color_table_size(gct.len()) as u16),
+        background_color_index: 0,
+        pixel_aspect_ratio: 0,
+    }
+}
+
+pub(super) fn graphics_control(
+    disposal_method: DisposalMethod,
+    delay_timer: u16,
+    transparent_index: Option<u8>,
+) -> Extension {
+    Extension::GraphicsControlExtension {
+        reserved: 0,
+        disposal_method,
+        user_input_flag: false,
+        transparent_color_flag: transparent_index.is_some(),
+        delay_timer,
+        transparent_color_index: transparent_index.unwrap_or(0),
+    }
+}
+
+// A frame covering the whole canvas
+pub(super) fn full_frame(
+    width: u16,
+    height: u16,
+    local_color_table: Option<Vec<Pixel>>,
+    frame_indices: Vec<u8>,
+    extensions: Vec<Extension>,
+) -> GifFrame {
+    let local_color_table = local_color_table.map(padded);
+    GifFrame {
+        image_descriptor: ImageDescriptor {
+            left: 0,
+            top: 0,
+            width,
+            height,
+            local_color_table_flag: local_color_table.is_some(),
+            interlace_flag: false,
+            sort_flag: false,
+            reserved: 0,
+            local_color_table_size: local_color_table
+                .as_ref()
+                .map_or(0, |lct| color_table_size(lct.len())),
+        },
+        local_color_table,
+        frame_indices,
+        extensions,
+    }
+}
+
+pub(super) fn netscape_loop(loop_count: u16) -> Extension {
+    let [low, high] = loop_count.to_le_bytes();
+    Extension::Application {
+        identifier: *b"NETSCAPE",
+        authentication_code: *b"2.0",
+        data: vec![vec![0x01, low, high]],
+    }
+}
+
+impl GifEncoder {
+    pub fn new(width: u16, height: u16) -> Self {
+        GifEncoder {
+            width,
+            height,
+            loop_count: None,
+            palette_mode: PaletteMode::default(),
+            quantize_options: QuantizeOptions::default(),
+            dither_options: None,
+            encode_options: EncodeOptions::default(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Repeats the animation `loop_count` times, 0 means forever.
+    pub fn with_loop(mut self, loop_count: u16) -> Self {
+        self.loop_count = Some(loop_count);
+        self
+    }
+
+    pub fn with_palette_mode(mut self, palette_mode: PaletteMode) -> Self {
+        self.palette_mode = palette_mode;
+        self
+    }
+
+    pub fn with_quantize_options(mut self, quantize_options: QuantizeOptions) -> Self {
+        self.quantize_options = quantize_options;
+        self
+    }
+
+    /// Dithers the frames, its `alpha_threshold` is replaced by the one of
+    /// the `QuantizeOptions`.
+    pub fn with_dither(mut self, dither_options: DitherOptions) -> Self {
+        self.dither_options = Some(dither_options);
+        self
+    }
+
+    /// Compresses the frames with lossy LZW, see `lzw::compress_lossy`.
+    pub fn with_lossy(mut self, lossy: LossyOptions) -> Self {
+        self.encode_options.lossy = Some(lossy);
+        self
+    }
+
+    /// Adds a frame of `width * height` RGBA pixels, shown for `delay`.
+    pub fn add_frame(mut self, rgba: &[u8], delay: Duration) -> Self {
+        self.frames.push((rgba.to_vec(), delay));
+        self
+    }
+
+    // (color table, transparent index, indices) of every frame
+    fn map_frames(&self) -> Vec<(Vec<Pixel>, Option<u8>, Vec<u8>)> {
+        let options = self.quantize_options;
+        let mut mapped: Vec<_> = match self.palette_mode {
+            PaletteMode::Global => {
+                let rgba: Vec<&[u8]> = self.frames.iter().map(|(rgba, _)| &rgba[..]).collect();
+                let quantized = quantize_frames(&rgba, options);
+                quantized
+                    .indices
+                    .into_iter()
+                    .map(|indices| {
+                        let color_table = quantized.color_table.clone();
+                        (color_table, quantized.transparent_index, indices)
+                    })
+                    .collect()
+            }
+            PaletteMode::Local => self
+                .frames
+                .iter()
+                .map(|(rgba, _)| {
+                    let quantized = quantize(rgba, options);
+                    let transparent_index = quantized.transparent_index;
+                    (quantized.color_table, transparent_index, quantized.indices)
+                })
+                .collect(),
+        };
+        if let Some(dither_options) = self.dither_options {
+            let mut ditherer = TemporalDitherer::new(
+                self.width as usize,
+                DitherOptions {
+                    alpha_threshold: options.alpha_threshold,
+                    ..dither_options
+                },
+            );
+            for ((rgba, _), (color_table, transparent_index, indices)) in
+                self.frames.iter().zip(&mut mapped)
+            {
+                *indices = ditherer.dither_frame(rgba, color_table, *transparent_index);
+            }
+        }
+        mapped
+    }
+
+    /// The `GifFile` that `finish` writes out.
+    pub fn build(self) -> Result<GifFile, EncodeError> {
+        if self.frames.is_empty() {
+            return Err(EncodeError::NoFrames);
+        }
+        let expected = self.width as usize * self.height as usize * 4;
+        for (frame, (rgba, _)) in self.frames.iter().enumerate() {
+            if rgba.len() != expected {
+                return Err(EncodeError::FrameSizeMismatch {
+                    frame,
+                    expected,
+                    actual: rgba.len(),
+                });
+            }
+        }
+
+        let mapped = self.map_frames();
+        let is_animation = self.frames.len() > 1;
+        let global_color_table = match self.palette_mode {
+            PaletteMode::Global => Some(padded(mapped[0].0.clone())),
+            PaletteMode::Local => None,
+        };
+
+        let mut frames = Vec::with_capacity(self.frames.len());
+        for (i, ((_, delay), (color_table, transparent_index, indices))) in
+            self.frames.iter().zip(mapped.iter().cloned()).enumerate()
+        {
+            let mut extensions = Vec::new();
+            if let (0, Some(loop_count)) = (i, self.loop_count) {
+                extensions.push(netscape_loop(loop_count));
+            }
+            // Transparent pixels of the next frame (the first one when looping)
+            // have to show the background rather than this frame
+            let (_, next_transparent_index, next_indices) = &mapped[(i + 1) % mapped.len()];
+            let disposal_method =
+                if next_transparent_index.is_some_and(|t| next_indices.contains(&t)) {
+                    DisposalMethod::RestoreToBackground
+                } else {
+                    DisposalMethod::DoNotDispose
+                };
+            let delay_timer = delay_timer(*delay);
+            if is_animation || transparent_index.is_some() || delay_timer > 0 {
+                extensions.push(graphics_control(
+                    disposal_method,
+                    delay_timer,
+                    transparent_index,
+                ));
+            }
+            let local_color_table = match self.palette_mode {
+                PaletteMode::Global => None,
+                PaletteMode::Local => Some(color_table),
+            };
+            frames.push(full_frame(
+                self.width,
+                self.height,
+                local_color_table,
+                indices,
+                extensions,
+            ));
+        }
+
+        let mut gif_file = GifFile {
+            header: GifHeader::GIF89a,
+            logical_screen_descriptor: logical_screen(
+                self.width,
+                self.height,
+                global_color_table.as_deref(),
+            ),
+            global_color_table,
+            frames,
+            trailing_extensions: Vec::new(),
+        };
+        gif_file.header = gif_file.required_version();
+        Ok(gif_file)
+    }
+
+    /// Encodes every frame added so far and writes the GIF to `writer`.
+    pub fn finish<W: Write>(self, mut writer: W) -> Result<(), EncodeError> {
+        let encode_options = self.encode_options;
+        let gif_file = self.build()?;
+        encode_with(&gif_file, &mut writer, &encode_options)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dither::DitherMethod;
+    use crate::render::{render, RenderOptions};
+
+    const WIDTH: usize = 12;
+    const HEIGHT: usize = 8;
+
+    // Few enough colors to be encoded exactly, transparent pixels are all zeros
+    // like the renderer outputs them
+    fn frame(step: usize) -> Vec<u8> {
+        (0..WIDTH * HEIGHT)
+            .flat_map(|i| {
+                let (x, y) = (i % WIDTH, i / WIDTH);
+                match (x + step) % 6 {
+                    0 if y > 2 => [0, 0, 0, 0],
+                    value => [(value * 40) as u8, (y * 30) as u8, (step * 50) as u8, 255],
+                }
+            })
+            .collect()
+    }
+
+    fn encoder(palette_mode: PaletteMode) -> GifEncoder {
+        (0..3).fold(
+            GifEncoder::new(WIDTH as u16, HEIGHT as u16)
+                .with_loop(0)
+                .with_palette_mode(palette_mode),
+            |encoder, step| encoder.add_frame(&frame(step), Duration::from_millis(70)),
+        )
+    }
+
+    #[test]
+    fn encode_rgba_frames() {
+        for palette_mode in [PaletteMode::Global, PaletteMode::Local] {
+            let mut bytes = Vec::new();
+            encoder(palette_mode).finish(&mut bytes).unwrap();
+            let gif_file = GifFile::new(&bytes).unwrap();
+            assert_eq!(gif_file, encoder(palette_mode).build().unwrap());
+            assert_eq!(
+                gif_file.global_color_table.is_some(),
+                palette_mode == PaletteMode::Global
+            );
+            assert_eq!(
+                gif_file.frames[0].extensions[0],
+                netscape_loop(0),
+                "{:?}",
+                palette_mode
+            );
+
+            let rendered = render(&gif_file, RenderOptions::default()).unwrap();
+            for (step, frame) in rendered.iter().enumerate() {
+                assert_eq!(
+                    frame.pixels,
+                    super::tests::frame(step),
+                    "{:?}",
+                    palette_mode
+                );
+                assert_eq!(frame.delay_timer, 7);
+            }
+        }
+    }
+
+    #[test]
+    fn encode_quantized_and_dithered() {
+        // A gradient with far more than 256 colors
+        let gradient: Vec<u8> = (0..64 * 64)
+            .flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 90, 255])
+            .collect();
+        let encoder = || {
+            GifEncoder::new(64, 64)
+                .with_quantize_options(QuantizeOptions {
+                    max_colors: 32,
+                    ..Default::default()
+                })
+                .with_dither(DitherOptions {
+                    method: DitherMethod::BlueNoise,
+                    ..Default::default()
+                })
+                .add_frame(&gradient, Duration::ZERO)
+        };
+        let gif_file = encoder().build().unwrap();
+        // A still image without transparency doesn't need anything from GIF89a
+        assert_eq!(gif_file.header, GifHeader::GIF87a);
+        assert_eq!(gif_file.global_color_table.as_ref().map(Vec::len), Some(32));
+        let bytes = gif_file.to_bytes();
+        assert_eq!(GifFile::new(&bytes), Ok(gif_file));
+
+        let mut lossy = Vec::new();
+        encoder()
+            .with_lossy(LossyOptions::default())
+            .finish(&mut lossy)
+            .unwrap();
+        assert!(lossy.len() < bytes.len());
+        let decoded = GifFile::new(&lossy).unwrap();
+        assert_eq!(decoded.frames[0].frame_indices.len(), 64 * 64);
+    }
+
+    #[test]
+    fn encode_errors() {
+        assert!(matches!(
+            GifEncoder::new(2, 2).build(),
+            Err(EncodeError::NoFrames)
+        ));
+        let err = GifEncoder::new(2, 2)
+            .add_frame(&[0; 16], Duration::ZERO)
+            .add_frame(&[0; 12], Duration::ZERO)
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Frame 1 has 12 bytes of RGBA data but the canvas needs 16!"
+        );
+        assert_eq!(delay_timer(Duration::from_millis(16)), 2);
+        assert_eq!(delay_timer(Duration::from_secs(1000)), u16::MAX);
+    }
+}
@@ -0,0 +1,176 @@
+use super::LossyOptions;
+use crate::decoder::Pixel;
+use std::collections::HashMap;
+
+const MAX_CODE_SIZE: u32 = 12;
+const MAX_TABLE_SIZE: u32 = 1 << MAX_CODE_SIZE;
+
+// Packs variable width codes, least significant bit first
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u32,
+    acc_len: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            bytes: Vec::new(),
+            acc: 0,
+            acc_len: 0,
+        }
+    }
+
+    fn write(&mut self, code: u32, code_size: u32) {
+        self.acc |= code << self.acc_len;
+        self.acc_len += code_size;
+        while self.acc_len >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.acc_len -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.acc_len > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Smallest LZW minimum code size that can represent every index below `num_colors`.
+pub fn minimum_code_size(num_colors: usize) -> u8 {
+    // The spec doesn't allow a minimum code size below 2, even for 2 colors.
+    let mut ret = 2;
+    while (1 << ret) < num_colors && ret < 8 {
+        ret += 1;
+    }
+    ret
+}
+
+// Squared distances between the entries of a color table
+struct Similar {
+    len: usize,
+    distances: Vec<f32>,
+    max_distance: f32,
+}
+
+impl Similar {
+    fn new(color_table: &[Pixel], transparent_index: Option<u8>, options: &LossyOptions) -> Self {
+        let color_table = &color_table[..color_table.len().min(256)];
+        let len = color_table.len();
+        let transparent = |i: usize| Some(i) == transparent_index.map(usize::from);
+        let distances = (0..len * len)
+            .map(|i| {
+                let (a, b) = (i / len, i % len);
+                // Transparent pixels are never swapped for opaque ones or the other way around
+                match transparent(a) || transparent(b) {
+                    true => f32::INFINITY,
+                    false => options.color_space.distance(color_table[a], color_table[b]),
+                }
+            })
+            .collect();
+        Similar {
+            len,
+            distances,
+            max_distance: options.threshold * options.threshold,
+        }
+    }
+
+    // The code among `candidates` (index, code) whose index is closest to `index`, if close enough
+    fn closest(&self, index: u8, candidates: &[(u8, u32)]) -> Option<u32> {
+        let index = index as usize;
+        candidates
+            .iter()
+            .filter(|&&(candidate, _)| index < self.len && (candidate as usize) < self.len)
+            .map(|&(candidate, code)| (self.distances[index * self.len + candidate as usize], code))
+            .filter(|&(distance, _)| distance <= self.max_distance)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, code)| code)
+    }
+}
+
+/// LZW compresses `indices`, which must all be below `2^minimum_code_size`.
+/// A Clear Code is sent every time the code table fills up.
+pub fn compress(indices: &[u8], minimum_code_size: u8) -> Vec<u8> {
+    compress_with(indices, minimum_code_size, None)
+}
+
+/// Like `compress`, but a string in the code table is extended with the next
+/// pixel whenever the color it would add is within `options.threshold` of the
+/// pixel's own color in `color_table`. Decoding gives slightly different indices
+/// in exchange for far fewer codes. The transparent index is always kept as is.
+pub fn compress_lossy(
+    indices: &[u8],
+    minimum_code_size: u8,
+    color_table: &[Pixel],
+    transparent_index: Option<u8>,
+    options: &LossyOptions,
+) -> Vec<u8> {
+    let similar = Similar::new(color_table, transparent_index, options);
+    compress_with(indices, minimum_code_size, Some(&similar))
+}
+
+fn compress_with(indices: &[u8], minimum_code_size: u8, similar: Option<&Similar>) -> Vec<u8> {
+    let clear_code = 1u32 << minimum_code_size;
+    let eoi_code = clear_code + 1;
+    let initial_code_size = minimum_code_size as u32 + 1;
+
+    let mut writer = BitWriter::new();
+    // (prefix code, next index) -> code
+    let mut code_table: HashMap<(u32, u8), u32> = HashMap::new();
+    // (next index, code) of the strings starting with each code, searched for lossy matches
+    let mut extensions: Vec<Vec<(u8, u32)>> = vec![Vec::new(); MAX_TABLE_SIZE as usize];
+    let mut next_code = eoi_code + 1;
+    let mut code_size = initial_code_size;
+    writer.write(clear_code, code_size);
+
+    let mut indices = indices.iter();
+    let mut prefix = match indices.next() {
+        Some(&k) => k as u32,
+        None => {
+            writer.write(eoi_code, code_size);
+            return writer.finish();
+        }
+    };
+    for &k in indices {
+        if let Some(&code) = code_table.get(&(prefix, k)) {
+            prefix = code;
+            continue;
+        }
+        let close = similar.and_then(|similar| similar.closest(k, &extensions[prefix as usize]));
+        if let Some(code) = close {
+            prefix = code;
+            continue;
+        }
+        writer.write(prefix, code_size);
+        if next_code < MAX_TABLE_SIZE {
+            code_table.insert((prefix, k), next_code);
+            if similar.is_some() {
+                extensions[prefix as usize].push((k, next_code));
+            }
+            if next_code == 1 << code_size {
+                code_size += 1;
+            }
+            next_code += 1;
+        } else {
+            writer.write(clear_code, code_size);
+            code_table.clear();
+            if similar.is_some() {
+                extensions.iter_mut().for_each(Vec::clear);
+            }
+            next_code = eoi_code + 1;
+            code_size = initial_code_size;
+        }
+        prefix = k as u32;
+    }
+    writer.write(prefix, code_size);
+    // The decoder adds one last entry after reading the final code,
+    // which might make it switch to a bigger code size for the EOI.
+    if next_code == 1 << code_size && code_size < MAX_CODE_SIZE {
+        code_size += 1;
+    }
+    writer.write(eoi_code, code_size);
+    writer.finish()
+}
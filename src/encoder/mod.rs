@@ -0,0 +1,492 @@
+mod errors;
+mod gif_encoder;
+pub mod lzw;
+mod options;
+mod streaming;
+pub use errors::*;
+pub use gif_encoder::*;
+pub use options::*;
+pub use streaming::*;
+
+use crate::decoder::{Extension, GifFile, GifFrame, GifHeader, LogicalScreenDescriptor, Pixel};
+use crate::render::DEFAULT_COLOR_TABLE;
+use std::io::{self, Write};
+
+const EXTENSION_INTRODUCER: u8 = 0x21;
+const IMAGE_SEPARATOR: u8 = 0x2C;
+const TRAILER: u8 = 0x3B;
+const BLOCK_TERMINATOR: u8 = 0x00;
+const MAX_SUBBLOCK_LENGTH: usize = 255;
+
+// Size field of a color table with `len` entries, the table holds 2^(size+1) colors
+pub(crate) fn color_table_size(len: usize) -> u8 {
+    let mut size = 0;
+    while (2 << size) < len && size < 7 {
+        size += 1;
+    }
+    size
+}
+
+// Pads `color_table` with black to the size that its packed field will say
+pub(crate) fn padded(mut color_table: Vec<Pixel>) -> Vec<Pixel> {
+    color_table.resize(
+        2 << color_table_size(color_table.len()),
+        Pixel {
+            red: 0,
+            green: 0,
+            blue: 0,
+        },
+    );
+    color_table
+}
+
+fn write_header<W: Write>(writer: &mut W, header: GifHeader) -> io::Result<()> {
+    writer.write_all(match header {
+        GifHeader::GIF87a => b"GIF87a",
+        GifHeader::GIF89a => b"GIF89a",
+    })
+}
+
+fn write_logical_screen_descriptor<W: Write>(
+    writer: &mut W,
+    lsd: &LogicalScreenDescriptor,
+    global_color_table: Option<&[Pixel]>,
+) -> io::Result<()> {
+    let packed_field = match global_color_table {
+        Some(gct) => 0x80 | color_table_size(gct.len()),
+        None => 0,
+    } | ((lsd.color_resolution as u8 & 0b111) << 4)
+        | ((lsd.sort_flag as u8) << 3);
+    writer.write_all(&lsd.canvas_width.to_le_bytes())?;
+    writer.write_all(&lsd.canvas_height.to_le_bytes())?;
+    writer.write_all(&[
+        packed_field,
+        lsd.background_color_index,
+        lsd.pixel_aspect_ratio,
+    ])
+}
+
+// Pads the table with black up to the size that the packed field says
+fn write_color_table<W: Write>(writer: &mut W, color_table: &[Pixel]) -> io::Result<()> {
+    let num_colors = 2 << color_table_size(color_table.len());
+    for i in 0..num_colors {
+        match color_table.get(i) {
+            Some(pixel) => writer.write_all(&[pixel.red, pixel.green, pixel.blue])?,
+            None => writer.write_all(&[0, 0, 0])?,
+        }
+    }
+    Ok(())
+}
+
+fn write_data_block<W: Write>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    for subblock in data.chunks(MAX_SUBBLOCK_LENGTH) {
+        writer.write_all(&[subblock.len() as u8])?;
+        writer.write_all(subblock)?;
+    }
+    writer.write_all(&[BLOCK_TERMINATOR])
+}
+
+// Writes every sub-block as it is, only splitting the ones that are too long
+fn write_sub_blocks<W: Write>(writer: &mut W, sub_blocks: &[Vec<u8>]) -> io::Result<()> {
+    for subblock in sub_blocks
+        .iter()
+        .flat_map(|data| data.chunks(MAX_SUBBLOCK_LENGTH))
+    {
+        writer.write_all(&[subblock.len() as u8])?;
+        writer.write_all(subblock)?;
+    }
+    writer.write_all(&[BLOCK_TERMINATOR])
+}
+
+fn write_extension<W: Write>(writer: &mut W, extension: &Extension) -> io::Result<()> {
+    match extension {
+        Extension::GraphicsControlExtension {
+            reserved,
+            disposal_method,
+            user_input_flag,
+            transparent_color_flag,
+            delay_timer,
+            transparent_color_index,
+        } => {
+            let packed_field = ((reserved & 0b111) << 5)
+                | ((disposal_method.value() & 0b111) << 2)
+                | ((*user_input_flag as u8) << 1)
+                | (*transparent_color_flag as u8);
+            writer.write_all(&[EXTENSION_INTRODUCER, 0xF9, 0x04, packed_field])?;
+            writer.write_all(&delay_timer.to_le_bytes())?;
+            writer.write_all(&[*transparent_color_index, BLOCK_TERMINATOR])
+        }
+        Extension::PlainText {
+            text_grid_left,
+            text_grid_top,
+            text_grid_width,
+            text_grid_height,
+            cell_width,
+            cell_height,
+            text_foreground_color_index,
+            text_background_color_index,
+            text,
+        } => {
+            writer.write_all(&[EXTENSION_INTRODUCER, 0x01, 0x0C])?;
+            for value in [
+                text_grid_left,
+                text_grid_top,
+                text_grid_width,
+                text_grid_height,
+            ] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            writer.write_all(&[
+                *cell_width,
+                *cell_height,
+                *text_foreground_color_index,
+                *text_background_color_index,
+            ])?;
+            write_data_block(writer, text)
+        }
+        Extension::Application {
+            identifier,
+            authentication_code,
+            data,
+        } => {
+            writer.write_all(&[EXTENSION_INTRODUCER, 0xFF, 0x0B])?;
+            writer.write_all(identifier)?;
+            writer.write_all(authentication_code)?;
+            write_sub_blocks(writer, data)
+        }
+        Extension::Comment { text } => {
+            writer.write_all(&[EXTENSION_INTRODUCER, 0xFE])?;
+            write_data_block(writer, text)
+        }
+        Extension::Unknown { label, data } => {
+            writer.write_all(&[EXTENSION_INTRODUCER, *label])?;
+            write_sub_blocks(writer, data)
+        }
+    }
+}
+
+fn write_frame<W: Write>(
+    writer: &mut W,
+    frame: &GifFrame,
+    global_color_table: Option<&[Pixel]>,
+    options: &EncodeOptions,
+) -> io::Result<()> {
+    for extension in &frame.extensions {
+        write_extension(writer, extension)?;
+    }
+
+    let id = &frame.image_descriptor;
+    let packed_field = match &frame.local_color_table {
+        Some(lct) => 0x80 | color_table_size(lct.len()),
+        None => 0,
+    } | ((id.interlace_flag as u8) << 6)
+        | ((id.sort_flag as u8) << 5)
+        | ((id.reserved & 0b11) << 3);
+    writer.write_all(&[IMAGE_SEPARATOR])?;
+    for value in [id.left, id.top, id.width, id.height] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.write_all(&[packed_field])?;
+    if let Some(lct) = &frame.local_color_table {
+        write_color_table(writer, lct)?;
+    }
+
+    // Big enough for both the color table and every index actually used
+    let color_table_len = match (&frame.local_color_table, global_color_table) {
+        (Some(lct), _) => 2 << color_table_size(lct.len()),
+        (None, Some(gct)) => 2 << color_table_size(gct.len()),
+        (None, None) => 0,
+    };
+    let max_index = frame
+        .frame_indices
+        .iter()
+        .max()
+        .map_or(0, |&x| x as usize + 1);
+    let minimum_code_size = lzw::minimum_code_size(color_table_len.max(max_index));
+    writer.write_all(&[minimum_code_size])?;
+    let compressed = match &options.lossy {
+        Some(lossy) => {
+            let color_table = match (&frame.local_color_table, global_color_table) {
+                (Some(lct), _) => lct,
+                (None, Some(gct)) => gct,
+                (None, None) => &DEFAULT_COLOR_TABLE[..],
+            };
+            lzw::compress_lossy(
+                &frame.frame_indices,
+                minimum_code_size,
+                color_table,
+                frame.transparent_color_index(),
+                lossy,
+            )
+        }
+        None => lzw::compress(&frame.frame_indices, minimum_code_size),
+    };
+    write_data_block(writer, &compressed)
+}
+
+/// Writes `gif_file` out in the GIF format.
+///
+/// The version in the header is picked automatically: `GIF87a` unless the file
+/// uses features only found in `GIF89a` (see `GifFile::required_version`), for
+/// maximum compatibility. The color table flags and sizes are derived from the
+/// color tables themselves.
+pub fn encode<W: Write>(gif_file: &GifFile, writer: &mut W) -> io::Result<()> {
+    encode_with(gif_file, writer, &EncodeOptions::default())
+}
+
+/// Like `encode`, with lossy compression if `options.lossy` is set.
+pub fn encode_with<W: Write>(
+    gif_file: &GifFile,
+    writer: &mut W,
+    options: &EncodeOptions,
+) -> io::Result<()> {
+    let global_color_table = gif_file.global_color_table.as_deref();
+    write_header(writer, gif_file.required_version())?;
+    write_logical_screen_descriptor(
+        writer,
+        &gif_file.logical_screen_descriptor,
+        global_color_table,
+    )?;
+    if let Some(gct) = global_color_table {
+        write_color_table(writer, gct)?;
+    }
+    for frame in &gif_file.frames {
+        write_frame(writer, frame, global_color_table, options)?;
+    }
+    for extension in &gif_file.trailing_extensions {
+        write_extension(writer, extension)?;
+    }
+    writer.write_all(&[TRAILER])
+}
+
+impl GifFile {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        // Writing to a Vec can't fail
+        encode(self, &mut ret).unwrap();
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{DisposalMethod, ImageDescriptor};
+
+    fn gif_file(extensions: Vec<Extension>) -> GifFile {
+        let color_table = (0..4)
+            .map(|i| Pixel {
+                red: i * 60,
+                green: 0,
+                blue: 255 - i * 60,
+            })
+            .collect();
+        GifFile {
+            header: GifHeader::GIF87a,
+            logical_screen_descriptor: LogicalScreenDescriptor {
+                canvas_width: 20,
+                canvas_height: 10,
+                global_color_table_flag: true,
+                color_resolution: 1,
+                sort_flag: false,
+                global_color_table_size: 1,
+                background_color_index: 0,
+                pixel_aspect_ratio: 0,
+            },
+            global_color_table: Some(color_table),
+            frames: vec![GifFrame {
+                image_descriptor: ImageDescriptor {
+                    left: 0,
+                    top: 0,
+                    width: 20,
+                    height: 10,
+                    local_color_table_flag: false,
+                    interlace_flag: false,
+                    sort_flag: false,
+                    reserved: 0,
+                    local_color_table_size: 0,
+                },
+                local_color_table: None,
+                frame_indices: (0..200).map(|i| ((i / 7) % 4) as u8).collect(),
+                extensions,
+            }],
+            trailing_extensions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn compress_round_trip() {
+        use crate::decoder::lzw::decompress;
+        for minimum_code_size in 2..=8 {
+            let max = 1u32 << minimum_code_size;
+            for len in [0, 1, 2, 100, 5000, 100_000] {
+                let indices: Vec<u8> = (0..len as u32)
+                    .map(|i| ((i * 7 + i / 13) % max) as u8)
+                    .collect();
+                let compressed = lzw::compress(&indices, minimum_code_size);
+                assert_eq!(
+                    decompress(compressed, minimum_code_size),
+                    Ok(indices),
+                    "minimum code size {}, length {}",
+                    minimum_code_size,
+                    len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lossy_compression() {
+        use crate::decoder::lzw::decompress;
+        let grays: Vec<Pixel> = (0..64)
+            .map(|i| Pixel {
+                red: i * 4,
+                green: i * 4,
+                blue: i * 4,
+            })
+            .collect();
+        // A noisy gradient with transparent holes, 63 is the transparent index
+        let indices: Vec<u8> = (0..128 * 128)
+            .map(|i| {
+                let (x, y) = (i % 128, i / 128);
+                match (x * 7 + y * 13) % 29 {
+                    0 => 63,
+                    noise => ((x + y) / 4 + noise % 3).min(62) as u8,
+                }
+            })
+            .collect();
+        let options = LossyOptions::default();
+        let lossless = lzw::compress(&indices, 6);
+        let lossy = lzw::compress_lossy(&indices, 6, &grays, Some(63), &options);
+        assert!(
+            lossy.len() * 10 < lossless.len() * 7,
+            "{} {}",
+            lossy.len(),
+            lossless.len()
+        );
+
+        let decompressed = decompress(lossy, 6).unwrap();
+        assert_eq!(decompressed.len(), indices.len());
+        for (&after, &before) in decompressed.iter().zip(&indices) {
+            assert_eq!(after == 63, before == 63);
+            let distance = options
+                .color_space
+                .distance(grays[after as usize], grays[before as usize]);
+            assert!(distance <= options.threshold * options.threshold);
+        }
+
+        // Nothing is close enough without a threshold
+        let exact = LossyOptions {
+            threshold: 0.0,
+            ..options
+        };
+        assert_eq!(
+            lzw::compress_lossy(&indices, 6, &grays, Some(63), &exact),
+            lossless
+        );
+    }
+
+    #[test]
+    fn minimum_code_sizes() {
+        assert_eq!(lzw::minimum_code_size(0), 2);
+        assert_eq!(lzw::minimum_code_size(2), 2);
+        assert_eq!(lzw::minimum_code_size(4), 2);
+        assert_eq!(lzw::minimum_code_size(5), 3);
+        assert_eq!(lzw::minimum_code_size(256), 8);
+    }
+
+    #[test]
+    fn encode_gif87a() {
+        let gif_file = gif_file(Vec::new());
+        let bytes = gif_file.to_bytes();
+        assert_eq!(&bytes[..6], b"GIF87a");
+        assert_eq!(GifFile::new(&bytes), Ok(gif_file));
+    }
+
+    fn gif89a_file() -> GifFile {
+        let mut gif_file = gif_file(vec![
+            Extension::GraphicsControlExtension {
+                reserved: 0,
+                disposal_method: DisposalMethod::RestoreToPrevious,
+                user_input_flag: false,
+                transparent_color_flag: true,
+                delay_timer: 50,
+                transparent_color_index: 3,
+            },
+            Extension::PlainText {
+                text_grid_left: 2,
+                text_grid_top: 1,
+                text_grid_width: 16,
+                text_grid_height: 8,
+                cell_width: 8,
+                cell_height: 8,
+                text_foreground_color_index: 1,
+                text_background_color_index: 0,
+                text: "hi".into(),
+            },
+        ]);
+        gif_file.header = GifHeader::GIF89a;
+        gif_file.trailing_extensions = vec![
+            Extension::Application {
+                identifier: *b"NETSCAPE",
+                authentication_code: *b"2.0",
+                data: vec![vec![1, 0, 0]],
+            },
+            Extension::Comment {
+                text: "made with gif_me_hd".into(),
+            },
+        ];
+        gif_file
+    }
+
+    #[test]
+    fn encode_gif89a() {
+        let gif_file = gif89a_file();
+        let bytes = gif_file.to_bytes();
+        assert_eq!(&bytes[..6], b"GIF89a");
+        assert_eq!(GifFile::new(&bytes), Ok(gif_file));
+    }
+
+    // Not UTF-8, and sub-blocks that are not split at 255 bytes
+    fn raw_extensions_file() -> GifFile {
+        let mut gif_file = gif89a_file();
+        gif_file.trailing_extensions = vec![
+            Extension::Application {
+                identifier: *b"RAW\xFFDATA",
+                authentication_code: [0, 1, 0x80],
+                data: vec![vec![1, 2, 3], vec![0xC0; 200], vec![0xFF]],
+            },
+            Extension::Comment {
+                text: vec![b'h', 0xFF, 0xFE, 0x80, b'i'],
+            },
+        ];
+        gif_file
+    }
+
+    #[test]
+    fn raw_extensions_round_trip() {
+        let bytes = raw_extensions_file().to_bytes();
+        let gif_file = GifFile::new(&bytes).unwrap();
+        assert_eq!(gif_file, raw_extensions_file());
+        assert_eq!(gif_file.to_bytes(), bytes);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_raw_extensions_round_trip() {
+        let bytes = raw_extensions_file().to_bytes();
+        let json = serde_json::to_string(&GifFile::new(&bytes).unwrap()).unwrap();
+        let from_json: GifFile = serde_json::from_str(&json).unwrap();
+        assert_eq!(from_json.to_bytes(), bytes);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let gif_file = gif89a_file();
+        let json = serde_json::to_string(&gif_file).unwrap();
+        let from_json: GifFile = serde_json::from_str(&json).unwrap();
+        assert_eq!(from_json, gif_file);
+        assert_eq!(from_json.to_bytes(), gif_file.to_bytes());
+    }
+}
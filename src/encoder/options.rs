@@ -0,0 +1,26 @@
+use crate::quantize::ColorSpace;
+
+/// How far lossy LZW compression may stray from the original colors, see
+/// `lzw::compress_lossy`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LossyOptions {
+    // Largest distance between a pixel and the color it is replaced with, in
+    // `color_space`. Oklab distances go up to about 1, below 0.02 is hard to see.
+    pub threshold: f32,
+    pub color_space: ColorSpace,
+}
+
+impl Default for LossyOptions {
+    fn default() -> Self {
+        LossyOptions {
+            threshold: 0.05,
+            color_space: ColorSpace::Oklab,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct EncodeOptions {
+    // Lossless if not set
+    pub lossy: Option<LossyOptions>,
+}
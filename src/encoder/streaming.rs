@@ -0,0 +1,254 @@
+use super::gif_encoder::{
+    delay_timer, full_frame, graphics_control, logical_screen, netscape_loop,
+};
+use super::{
+    write_color_table, write_frame, write_header, write_logical_screen_descriptor, EncodeError,
+    EncodeOptions, LossyOptions, TRAILER,
+};
+use crate::decoder::{DisposalMethod, GifHeader, Pixel};
+use crate::dither::{DitherMethod, DitherOptions, TemporalDitherer};
+use crate::quantize::{quantize, QuantizeOptions};
+use std::io::Write;
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct StreamingOptions {
+    // Number of times to repeat the animation, 0 is forever
+    pub loop_count: Option<u16>,
+    // Fixed palette for every frame, written up front. Without it, every frame
+    // gets a Local Color Table of its own.
+    pub global_color_table: Option<Vec<Pixel>>,
+    pub quantize_options: QuantizeOptions,
+    // Plain nearest color if not set
+    pub dither_options: Option<DitherOptions>,
+    // Lossless if not set
+    pub lossy: Option<LossyOptions>,
+}
+
+/// Writes a GIF frame by frame, for animations too long to keep in memory.
+///
+/// The header and Logical Screen are written by `new`, every frame by
+/// `add_frame` and the trailer by `finish`. Only the previous frame is kept
+/// around (to dither steadily), whatever the number of frames.
+pub struct StreamingEncoder<W: Write> {
+    writer: W,
+    width: u16,
+    height: u16,
+    quantize_options: QuantizeOptions,
+    // Padded Global Color Table and the entry reserved for transparent pixels
+    global_color_table: Option<(Vec<Pixel>, Option<u8>)>,
+    // Always there with a Global Color Table, which frames are mapped to
+    ditherer: Option<TemporalDitherer>,
+    loop_count: Option<u16>,
+    encode_options: EncodeOptions,
+    frames_written: usize,
+}
+
+impl<W: Write> StreamingEncoder<W> {
+    /// Writes the header, the Logical Screen Descriptor and the Global Color Table.
+    ///
+    /// If the Global Color Table has room left, an extra entry is added for
+    /// transparent pixels. Otherwise they get the closest color.
+    pub fn new(
+        mut writer: W,
+        width: u16,
+        height: u16,
+        options: StreamingOptions,
+    ) -> Result<Self, EncodeError> {
+        let global_color_table = options.global_color_table.map(|mut color_table| {
+            color_table.truncate(256);
+            let transparent_index = (color_table.len() < 256).then(|| {
+                color_table.push(Pixel {
+                    red: 0,
+                    green: 0,
+                    blue: 0,
+                });
+                (color_table.len() - 1) as u8
+            });
+            (color_table, transparent_index)
+        });
+        // Extensions are only known frame by frame, so always GIF89a
+        write_header(&mut writer, GifHeader::GIF89a)?;
+        let gct = global_color_table.as_ref().map(|(gct, _)| &gct[..]);
+        write_logical_screen_descriptor(&mut writer, &logical_screen(width, height, gct), gct)?;
+        if let Some(gct) = gct {
+            write_color_table(&mut writer, gct)?;
+        }
+        let quantize_options = options.quantize_options;
+        let dither_options = match options.dither_options {
+            Some(dither_options) => Some(dither_options),
+            // Mapping to the Global Color Table without dithering
+            None => global_color_table.as_ref().map(|_| DitherOptions {
+                method: DitherMethod::None,
+                color_space: quantize_options.color_space,
+                ..Default::default()
+            }),
+        };
+        let ditherer = dither_options.map(|dither_options| {
+            TemporalDitherer::new(
+                width as usize,
+                DitherOptions {
+                    alpha_threshold: quantize_options.alpha_threshold,
+                    ..dither_options
+                },
+            )
+        });
+        Ok(StreamingEncoder {
+            writer,
+            width,
+            height,
+            quantize_options,
+            global_color_table,
+            ditherer,
+            loop_count: options.loop_count,
+            encode_options: EncodeOptions {
+                lossy: options.lossy,
+            },
+            frames_written: 0,
+        })
+    }
+
+    /// Quantizes and writes a frame of `width * height` RGBA pixels, shown for `delay`.
+    pub fn add_frame(&mut self, rgba: &[u8], delay: Duration) -> Result<(), EncodeError> {
+        let expected = self.width as usize * self.height as usize * 4;
+        if rgba.len() != expected {
+            return Err(EncodeError::FrameSizeMismatch {
+                frame: self.frames_written,
+                expected,
+                actual: rgba.len(),
+            });
+        }
+        let (local_color_table, transparent_index, indices) =
+            match (&self.global_color_table, &mut self.ditherer) {
+                (Some((gct, transparent_index)), Some(ditherer)) => {
+                    let indices = ditherer.dither_frame(rgba, gct, *transparent_index);
+                    (None, *transparent_index, indices)
+                }
+                (_, ditherer) => {
+                    let quantized = quantize(rgba, self.quantize_options);
+                    let transparent_index = quantized.transparent_index;
+                    let indices = match ditherer {
+                        Some(ditherer) => {
+                            ditherer.dither_frame(rgba, &quantized.color_table, transparent_index)
+                        }
+                        None => quantized.indices,
+                    };
+                    (Some(quantized.color_table), transparent_index, indices)
+                }
+            };
+
+        let mut extensions = Vec::new();
+        if let (0, Some(loop_count)) = (self.frames_written, self.loop_count) {
+            extensions.push(netscape_loop(loop_count));
+        }
+        // The next frame isn't known yet. Frames cover the whole canvas, so
+        // clearing it is always right, and needed if the next one is transparent.
+        extensions.push(graphics_control(
+            DisposalMethod::RestoreToBackground,
+            delay_timer(delay),
+            transparent_index,
+        ));
+        let frame = full_frame(
+            self.width,
+            self.height,
+            local_color_table,
+            indices,
+            extensions,
+        );
+        let gct = self.global_color_table.as_ref().map(|(gct, _)| &gct[..]);
+        write_frame(&mut self.writer, &frame, gct, &self.encode_options)?;
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Writes the trailer and gives the writer back.
+    pub fn finish(mut self) -> Result<W, EncodeError> {
+        if self.frames_written == 0 {
+            return Err(EncodeError::NoFrames);
+        }
+        self.writer.write_all(&[TRAILER])?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::GifFile;
+    use crate::render::{render, RenderOptions};
+
+    const WIDTH: usize = 10;
+    const HEIGHT: usize = 6;
+
+    // Few colors, transparent pixels are all zeros like the renderer outputs them
+    fn frame(step: usize) -> Vec<u8> {
+        (0..WIDTH * HEIGHT)
+            .flat_map(|i| match (i + step) % 5 {
+                0 => [0, 0, 0, 0],
+                value => [(value * 60) as u8, 0, 255, 255],
+            })
+            .collect()
+    }
+
+    fn palette() -> Vec<Pixel> {
+        (1..5)
+            .map(|value| Pixel {
+                red: value * 60,
+                green: 0,
+                blue: 255,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stream_frames() {
+        for global_color_table in [None, Some(palette())] {
+            let options = StreamingOptions {
+                loop_count: Some(0),
+                global_color_table: global_color_table.clone(),
+                ..Default::default()
+            };
+            let mut encoder =
+                StreamingEncoder::new(Vec::new(), WIDTH as u16, HEIGHT as u16, options).unwrap();
+            let mut written = encoder.get_ref().len();
+            // Header and Logical Screen Descriptor, then the color table
+            assert_eq!(written, 13 + global_color_table.map_or(0, |_| 8 * 3));
+            for step in 0..4 {
+                encoder
+                    .add_frame(&frame(step), Duration::from_millis(100))
+                    .unwrap();
+                assert!(encoder.get_ref().len() > written);
+                written = encoder.get_ref().len();
+            }
+            let bytes = encoder.finish().unwrap();
+            assert_eq!(bytes.len(), written + 1);
+
+            let gif_file = GifFile::new(&bytes).unwrap();
+            assert_eq!(gif_file.frames.len(), 4);
+            let rendered = render(&gif_file, RenderOptions::default()).unwrap();
+            for (step, frame) in rendered.iter().enumerate() {
+                assert_eq!(frame.pixels, super::tests::frame(step));
+                assert_eq!(frame.delay_timer, 10);
+            }
+        }
+    }
+
+    #[test]
+    fn stream_errors() {
+        let encoder = StreamingEncoder::new(Vec::new(), 2, 2, Default::default()).unwrap();
+        assert!(matches!(encoder.finish(), Err(EncodeError::NoFrames)));
+        let mut encoder = StreamingEncoder::new(Vec::new(), 2, 2, Default::default()).unwrap();
+        assert!(matches!(
+            encoder.add_frame(&[0; 12], Duration::ZERO),
+            Err(EncodeError::FrameSizeMismatch {
+                frame: 0,
+                expected: 16,
+                actual: 12
+            })
+        ));
+    }
+}
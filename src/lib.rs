@@ -1 +1,8 @@
 pub mod decoder;
+pub mod dither;
+pub mod encoder;
+pub mod optimize;
+pub mod quantize;
+pub mod render;
+pub mod validate;
+pub use decoder::{probe, sniff, DetectedFormat, GifInfo};
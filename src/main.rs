@@ -1,13 +1,11 @@
-use gif_me_hd::decoder;
+use gif_me_hd::decoder::{self, DecodeLimits};
+use gif_me_hd::validate::{self, Severity};
 use std::env;
+use std::fs;
+use std::process;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        panic!("Not enough arguments!");
-    }
-    let file = &args[1];
-    let gif_file = decoder::load(&file[..]).unwrap();
+fn print_gif(file: &str) {
+    let gif_file = decoder::load(file).unwrap();
     println!(
         "Logical Screen Descriptor: {:#?}",
         gif_file.logical_screen_descriptor
@@ -19,4 +17,227 @@ fn main() {
     gif_file.frames.into_iter().for_each(|frame| {
         println!("Frame: {:#?}", frame);
     });
+    gif_file
+        .trailing_extensions
+        .into_iter()
+        .for_each(|extension| {
+            println!("Trailing Extension: {:#?}", extension);
+        });
+}
+
+fn info(file: &str) {
+    let bytes = fs::read(file).expect("Unable to read file");
+    let info = gif_me_hd::probe(&bytes).unwrap();
+    println!("Version: {:?}", info.header);
+    println!("Canvas: {}x{}", info.canvas_width, info.canvas_height);
+    println!("Frames: {}", info.frame_count);
+    println!("Duration: {:.2}s", info.total_duration.as_secs_f64());
+    match info.loop_count {
+        Some(0) => println!("Loop Count: infinite"),
+        Some(n) => println!("Loop Count: {}", n),
+        None => println!("Loop Count: none"),
+    }
+    println!("Animated: {}", info.is_animated);
+    println!("Transparency: {}", info.has_transparency);
+}
+
+fn lint(file: &str) {
+    let bytes = fs::read(file).expect("Unable to read file");
+    let diagnostics = validate::validate_bytes(&bytes);
+    if diagnostics.is_empty() {
+        println!("{}: no problems found", file);
+        return;
+    }
+    diagnostics
+        .iter()
+        .for_each(|diagnostic| println!("{}: {}", file, diagnostic));
+    if diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity() == Severity::Error)
+    {
+        process::exit(1);
+    }
+}
+
+fn dump(file: &str, json: bool) {
+    let bytes = fs::read(file).expect("Unable to read file");
+    let gif_dump = decoder::dump(&bytes);
+    if json {
+        print_json_dump(&gif_dump);
+    } else {
+        print!("{}", gif_dump);
+    }
+    if gif_dump.error.is_some() {
+        process::exit(1);
+    }
+}
+
+fn lzw_trace(file: &str, frame: usize) {
+    let bytes = fs::read(file).expect("Unable to read file");
+    let frames = decoder::compressed_frames(&bytes).unwrap();
+    let compressed = match frames.into_iter().nth(frame) {
+        Some(compressed) => compressed,
+        None => panic!("Frame {} does not exist!", frame),
+    };
+    println!(
+        "Frame {}: LZW minimum code size {}, {} compressed bytes",
+        frame,
+        compressed.lzw_minimum_code_size,
+        compressed.compressed_data.len()
+    );
+    println!(
+        "{:>10} {:>6} {:>5} {:>8} {:>6} {:>8}",
+        "bit", "code", "width", "kind", "table", "indices"
+    );
+    // Traced on its own, as if it were the first frame
+    let result = compressed.decompress_traced(&DecodeLimits::default(), 0, &mut |code| {
+        println!(
+            "{:>10} {:>6} {:>5} {:>8} {:>6} {:>8}",
+            code.bit_offset,
+            code.code,
+            code.code_size,
+            code.kind,
+            code.table_size,
+            code.indices_written
+        )
+    });
+    match result {
+        Ok(decompressed) => println!(
+            "Decoded {} indices, {} bytes after End Of Information",
+            decompressed.index_stream.len(),
+            decompressed.trailing_bytes
+        ),
+        Err(err) => {
+            println!("Decompression failed: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+fn stats(file: &str) {
+    let bytes = fs::read(file).expect("Unable to read file");
+    let stats = decoder::GifFile::stats(&bytes, &DecodeLimits::default()).unwrap();
+    println!(
+        "{:>5} {:>10} {:>10} {:>7} {:>10} {:>6} {:>9} {:>6}",
+        "frame", "compressed", "pixels", "ratio", "sub-blocks", "clears", "max width", "colors"
+    );
+    for (i, frame) in stats.iter().enumerate() {
+        println!(
+            "{:>5} {:>10} {:>10} {:>7.2} {:>10} {:>6} {:>9} {:>6}",
+            i,
+            frame.compressed_bytes,
+            frame.decoded_pixels,
+            frame.compression_ratio(),
+            frame.sub_blocks,
+            frame.clear_codes,
+            frame.max_code_size,
+            frame.colors_used
+        );
+    }
+    let compressed: usize = stats.iter().map(|frame| frame.compressed_bytes).sum();
+    let pixels: usize = stats.iter().map(|frame| frame.decoded_pixels).sum();
+    println!(
+        "Total: {} compressed bytes for {} pixels in {} frames",
+        compressed,
+        pixels,
+        stats.len()
+    );
+}
+
+#[cfg(feature = "serde")]
+fn print_json_dump(gif_dump: &decoder::GifDump) {
+    println!("{}", gif_dump.to_json());
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json_dump(_gif_dump: &decoder::GifDump) {
+    unreachable!("--json is rejected without the serde feature");
+}
+
+#[cfg(feature = "serde")]
+fn to_json(file: &str, output: Option<&String>) {
+    let gif_file = decoder::load(file).unwrap();
+    let json = serde_json::to_string_pretty(&gif_file).expect("Unable to serialize GIF");
+    match output {
+        Some(output) => fs::write(output, json).expect("Unable to write file"),
+        None => println!("{}", json),
+    }
+}
+
+#[cfg(feature = "serde")]
+fn from_json(file: &str, output: &str) {
+    let json = fs::read_to_string(file).expect("Unable to read file");
+    let gif_file: decoder::GifFile = serde_json::from_str(&json).expect("Invalid GIF JSON");
+    fs::write(output, gif_file.to_bytes()).expect("Unable to write file");
+}
+
+#[cfg(not(feature = "serde"))]
+fn to_json(_file: &str, _output: Option<&String>) {
+    panic!("to-json needs the serde feature!");
+}
+
+#[cfg(not(feature = "serde"))]
+fn from_json(_file: &str, _output: &str) {
+    panic!("from-json needs the serde feature!");
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        panic!("Not enough arguments!");
+    }
+    match args[1].as_str() {
+        "info" => match args.get(2) {
+            Some(file) => info(file),
+            None => panic!("Not enough arguments!"),
+        },
+        "lint" => match args.get(2) {
+            Some(file) => lint(file),
+            None => panic!("Not enough arguments!"),
+        },
+        "dump" => {
+            let (file, json) = match args.get(2).map(String::as_str) {
+                Some("--json") => (args.get(3), true),
+                _ => (
+                    args.get(2),
+                    args.get(3).map(String::as_str) == Some("--json"),
+                ),
+            };
+            // Checked before reading the file, so that nothing gets printed
+            if json && !cfg!(feature = "serde") {
+                eprintln!("dump --json needs the serde feature");
+                process::exit(2);
+            }
+            match file {
+                Some(file) => dump(file, json),
+                None => panic!("Not enough arguments!"),
+            }
+        }
+        "stats" => match args.get(2) {
+            Some(file) => stats(file),
+            None => panic!("Not enough arguments!"),
+        },
+        "to-json" => match args.get(2) {
+            Some(file) => to_json(file, args.get(3)),
+            None => panic!("Not enough arguments!"),
+        },
+        "from-json" => match (args.get(2), args.get(3)) {
+            (Some(file), Some(output)) => from_json(file, output),
+            _ => panic!("Not enough arguments!"),
+        },
+        "lzw-trace" => match args.get(2) {
+            Some(file) => {
+                let frame = match args.get(3).map(String::as_str) {
+                    Some("--frame") => args
+                        .get(4)
+                        .and_then(|frame| frame.parse().ok())
+                        .expect("--frame needs a frame number"),
+                    _ => 0,
+                };
+                lzw_trace(file, frame)
+            }
+            None => panic!("Not enough arguments!"),
+        },
+        file => print_gif(file),
+    }
 }
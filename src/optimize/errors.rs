@@ -0,0 +1,29 @@
+use crate::render::RenderError;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum OptimizeError {
+    // The changed part of a frame needs more colors than a color table holds
+    TooManyColors { frame: usize },
+    // The frames could not be composited, e.g. the canvas is too big
+    Render(RenderError),
+}
+
+impl fmt::Display for OptimizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptimizeError::TooManyColors { frame } => write!(
+                f,
+                "Frame {} needs more than 256 colors after optimization!",
+                frame
+            ),
+            OptimizeError::Render(err) => write!(f, "Unable to render: {}", err),
+        }
+    }
+}
+
+impl From<RenderError> for OptimizeError {
+    fn from(err: RenderError) -> Self {
+        OptimizeError::Render(err)
+    }
+}
@@ -0,0 +1,526 @@
+use super::palette::rgb;
+use super::OptimizeError;
+use crate::decoder::{DisposalMethod, Extension, GifFile, GifFrame, ImageDescriptor, Pixel};
+use crate::encoder::{color_table_size, padded};
+use crate::render::{active_color_table, render, RenderOptions, RgbaFrame};
+use std::collections::{HashMap, HashSet};
+
+// A composited canvas, shown by one or more consecutive frames of the original
+struct Canvas {
+    pixels: Vec<u8>,
+    delay_timer: u16,
+    user_input_flag: bool,
+    frames: Vec<usize>,
+}
+
+// Part of the canvas, right and bottom excluded
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Rect {
+    left: usize,
+    top: usize,
+    right: usize,
+    bottom: usize,
+}
+
+impl Rect {
+    // Smallest rectangle around `pixels`, offsets into a canvas `width` pixels wide
+    fn bounding(width: usize, pixels: impl Iterator<Item = usize>) -> Option<Rect> {
+        pixels.fold(None, |rect: Option<Rect>, i| {
+            let (x, y) = (i % width, i / width);
+            let pixel = Rect {
+                left: x,
+                top: y,
+                right: x + 1,
+                bottom: y + 1,
+            };
+            Some(rect.map_or(pixel, |rect| rect.union(pixel)))
+        })
+    }
+
+    fn union(self, other: Rect) -> Rect {
+        Rect {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    fn area(&self) -> usize {
+        (self.right - self.left) * (self.bottom - self.top)
+    }
+
+    // Offsets of the pixels inside, row by row
+    fn pixels(self, width: usize) -> impl Iterator<Item = usize> {
+        (self.top..self.bottom)
+            .flat_map(move |y| (self.left..self.right).map(move |x| y * width + x))
+    }
+}
+
+fn pixel(rgba: &[u8], i: usize) -> &[u8] {
+    &rgba[i * 4..i * 4 + 4]
+}
+
+// The renderer only ever outputs fully opaque or fully transparent pixels
+fn opaque(rgba: &[u8], i: usize) -> bool {
+    rgba[i * 4 + 3] != 0
+}
+
+fn changes(width: usize, before: &[u8], after: &[u8]) -> Option<Rect> {
+    Rect::bounding(
+        width,
+        (0..after.len() / 4).filter(|&i| pixel(before, i) != pixel(after, i)),
+    )
+}
+
+// Consecutive frames that look the same become one, shown for as long as all of them.
+// Browsers show frames without a delay for about 100ms, so those are left alone,
+// and so are frames waiting for user input.
+fn merge(rendered: Vec<RgbaFrame>, frames: &[GifFrame]) -> Vec<Canvas> {
+    let mut canvases: Vec<Canvas> = Vec::new();
+    for (i, (frame, source)) in rendered.into_iter().zip(frames).enumerate() {
+        let user_input_flag = source.user_input_flag();
+        if let Some(last) = canvases.last_mut() {
+            let timed = last.delay_timer > 0 && frame.delay_timer > 0;
+            let delay_timer = last.delay_timer.checked_add(frame.delay_timer);
+            let same = last.pixels == frame.pixels && !last.user_input_flag && !user_input_flag;
+            if let (true, true, Some(delay_timer)) = (same, timed, delay_timer) {
+                last.delay_timer = delay_timer;
+                last.frames.push(i);
+                continue;
+            }
+        }
+        canvases.push(Canvas {
+            pixels: frame.pixels,
+            delay_timer: frame.delay_timer,
+            user_input_flag,
+            frames: vec![i],
+        });
+    }
+    canvases
+}
+
+// Picks the disposal of a frame drawing `rect` over `base` to get `canvas`, that leaves
+// the least for the next frame to draw. Returns it with the rectangle of the frame
+// (grown if pixels have to be cleared) and the canvas the next frame is drawn over.
+fn dispose(
+    width: usize,
+    base: &[u8],
+    canvas: &[u8],
+    rect: Rect,
+    next: &[u8],
+) -> (DisposalMethod, Rect, Vec<u8>) {
+    let len = canvas.len() / 4;
+    // Pixels can't be drawn transparent, only disposal takes them away
+    let cleared = Rect::bounding(
+        width,
+        (0..len).filter(|&i| opaque(canvas, i) && !opaque(next, i)),
+    );
+    let mut candidates = Vec::new();
+    if cleared.is_none() {
+        candidates.push((DisposalMethod::DoNotDispose, rect, canvas.to_vec()));
+    }
+    let background_rect = cleared.map_or(rect, |cleared| rect.union(cleared));
+    let mut background = canvas.to_vec();
+    for i in background_rect.pixels(width) {
+        background[i * 4..i * 4 + 4].fill(0);
+    }
+    candidates.push((
+        DisposalMethod::RestoreToBackground,
+        background_rect,
+        background,
+    ));
+    if (0..len).all(|i| opaque(next, i) || !opaque(base, i)) {
+        candidates.push((DisposalMethod::RestoreToPrevious, rect, base.to_vec()));
+    }
+    // Ties go to the most widely supported disposal, the first one
+    candidates
+        .into_iter()
+        .min_by_key(|(_, rect, left)| {
+            rect.area() + changes(width, left, next).map_or(0, |changes| changes.area())
+        })
+        .unwrap()
+}
+
+// What a pixel inside the rectangle of a frame has to do
+enum Target {
+    // Same as what is already there, the color if it is opaque
+    Keep(Option<[u8; 3]>),
+    Draw([u8; 3]),
+}
+
+fn targets(width: usize, base: &[u8], canvas: &[u8], rect: Rect) -> Vec<Target> {
+    rect.pixels(width)
+        .map(|i| {
+            let color = [canvas[i * 4], canvas[i * 4 + 1], canvas[i * 4 + 2]];
+            match (pixel(base, i) == pixel(canvas, i), opaque(canvas, i)) {
+                (true, true) => Target::Keep(Some(color)),
+                (true, false) => Target::Keep(None),
+                // Disposal made sure that drawn pixels are opaque
+                (false, _) => Target::Draw(color),
+            }
+        })
+        .collect()
+}
+
+// Indices of `targets` in `color_table`, kept pixels are transparent if an index can
+// be spared for it (`preferred` if possible). None if a color is missing.
+fn map_targets(
+    targets: &[Target],
+    color_table: &[Pixel],
+    preferred: Option<u8>,
+) -> Option<(Vec<u8>, Option<u8>)> {
+    let drawn: HashSet<[u8; 3]> = targets
+        .iter()
+        .filter_map(|target| match target {
+            Target::Draw(color) => Some(*color),
+            Target::Keep(_) => None,
+        })
+        .collect();
+    // An index is free if its color isn't drawn, or is also found at another index
+    let free = |i: usize| {
+        let color = rgb(&color_table[i]);
+        !drawn.contains(&color)
+            || color_table
+                .iter()
+                .enumerate()
+                .any(|(j, pixel)| j != i && rgb(pixel) == color)
+    };
+    let transparent_index = match targets.iter().any(|t| matches!(t, Target::Keep(_))) {
+        true => preferred
+            .map(usize::from)
+            .into_iter()
+            .chain((0..color_table.len()).rev())
+            .find(|&i| i < color_table.len() && free(i)),
+        false => None,
+    };
+
+    let mut lookup = HashMap::new();
+    for (i, pixel) in color_table.iter().enumerate() {
+        if Some(i) != transparent_index {
+            lookup.entry(rgb(pixel)).or_insert(i as u8);
+        }
+    }
+    let indices = targets
+        .iter()
+        .map(|target| match (target, transparent_index) {
+            (Target::Keep(_), Some(index)) => Some(index as u8),
+            (Target::Keep(Some(color)), None) | (Target::Draw(color), _) => {
+                lookup.get(color).copied()
+            }
+            (Target::Keep(None), None) => None,
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    Some((indices, transparent_index.map(|index| index as u8)))
+}
+
+fn graphics_control(
+    disposal_method: DisposalMethod,
+    delay_timer: u16,
+    user_input_flag: bool,
+    transparent_index: Option<u8>,
+) -> Option<Extension> {
+    let needed = disposal_method != DisposalMethod::DoNotDispose
+        || delay_timer > 0
+        || user_input_flag
+        || transparent_index.is_some();
+    needed.then_some(Extension::GraphicsControlExtension {
+        reserved: 0,
+        disposal_method,
+        user_input_flag,
+        transparent_color_flag: transparent_index.is_some(),
+        delay_timer,
+        transparent_color_index: transparent_index.unwrap_or(0),
+    })
+}
+
+/// Shrinks an animation without changing how it looks: every frame is cropped to
+/// the part of the canvas that changed, pixels inside that didn't change become
+/// transparent (long runs of one index compress well), each frame gets the
+/// disposal leaving the least to redraw, and consecutive frames that look the
+/// same are merged into one with their delays added up (unless a delay is 0 or
+/// a frame waits for user input).
+///
+/// Frames keep their color tables, except in the rare case where the changed
+/// colors can't be expressed with it and a Local Color Table is made instead.
+/// Extensions other than the Graphics Control Extension are kept.
+pub fn optimize_frames(gif_file: &mut GifFile) -> Result<(), OptimizeError> {
+    let lsd = &gif_file.logical_screen_descriptor;
+    let width = lsd.canvas_width as usize;
+    if width == 0 || lsd.canvas_height == 0 {
+        return Ok(());
+    }
+    let rendered = render(gif_file, RenderOptions::default())?;
+    let canvases = merge(rendered, &gif_file.frames);
+
+    let mut base = vec![0; width * lsd.canvas_height as usize * 4];
+    let mut optimized = Vec::new();
+    for (i, canvas) in canvases.iter().enumerate() {
+        // A frame can't be empty, so an unchanged one draws its first pixel again
+        let rect = changes(width, &base, &canvas.pixels).unwrap_or(Rect {
+            left: 0,
+            top: 0,
+            right: 1,
+            bottom: 1,
+        });
+        let (disposal_method, rect, next_base) = match canvases.get(i + 1) {
+            Some(next) => dispose(width, &base, &canvas.pixels, rect, &next.pixels),
+            None => (DisposalMethod::DoNotDispose, rect, Vec::new()),
+        };
+
+        let number = canvas.frames[0];
+        let source = &gif_file.frames[number];
+        let targets = targets(width, &base, &canvas.pixels, rect);
+        let color_table = active_color_table(gif_file, source);
+        let (local_color_table, (frame_indices, transparent_index)) =
+            match map_targets(&targets, color_table, source.transparent_color_index()) {
+                Some(mapped) => (source.local_color_table.clone(), mapped),
+                None => {
+                    // Just the drawn colors and a transparent entry
+                    let mut colors: Vec<Pixel> = Vec::new();
+                    for target in &targets {
+                        if let Target::Draw([red, green, blue]) = *target {
+                            let color = Pixel { red, green, blue };
+                            if !colors.contains(&color) {
+                                colors.push(color);
+                            }
+                        }
+                    }
+                    let transparent_index = (colors.len() < 256).then_some(colors.len() as u8);
+                    let colors = padded(colors);
+                    let mapped = map_targets(&targets, &colors, transparent_index)
+                        .ok_or(OptimizeError::TooManyColors { frame: number })?;
+                    (Some(colors), mapped)
+                }
+            };
+
+        optimized.push(GifFrame {
+            image_descriptor: ImageDescriptor {
+                left: rect.left as u16,
+                top: rect.top as u16,
+                width: (rect.right - rect.left) as u16,
+                height: (rect.bottom - rect.top) as u16,
+                local_color_table_flag: local_color_table.is_some(),
+                interlace_flag: false,
+                sort_flag: false,
+                reserved: 0,
+                local_color_table_size: local_color_table
+                    .as_ref()
+                    .map_or(0, |lct| color_table_size(lct.len())),
+            },
+            local_color_table,
+            frame_indices,
+            extensions: graphics_control(
+                disposal_method,
+                canvas.delay_timer,
+                canvas.user_input_flag,
+                transparent_index,
+            )
+            .into_iter()
+            .collect(),
+        });
+        base = next_base;
+    }
+
+    let mut frames = std::mem::take(&mut gif_file.frames);
+    for (frame, canvas) in optimized.iter_mut().zip(&canvases) {
+        // Everything else goes in front of the new Graphics Control Extension
+        let mut extensions: Vec<Extension> = canvas
+            .frames
+            .iter()
+            .flat_map(|&i| std::mem::take(&mut frames[i].extensions))
+            .filter(|ext| !matches!(ext, Extension::GraphicsControlExtension { .. }))
+            .collect();
+        extensions.append(&mut frame.extensions);
+        frame.extensions = extensions;
+    }
+    gif_file.frames = optimized;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{GifHeader, LogicalScreenDescriptor};
+
+    const WIDTH: usize = 16;
+    const HEIGHT: usize = 8;
+
+    fn palette() -> Vec<Pixel> {
+        (0..4)
+            .map(|i| Pixel {
+                red: i * 80,
+                green: 255 - i * 80,
+                blue: 40,
+            })
+            .collect()
+    }
+
+    // A frame covering the canvas, `square` (3 or the transparent index 0) drawn at
+    // `offset` over a background of `background`
+    fn frame(offset: usize, background: Option<u8>, delay_timer: u16) -> GifFrame {
+        GifFrame {
+            image_descriptor: ImageDescriptor {
+                left: 0,
+                top: 0,
+                width: WIDTH as u16,
+                height: HEIGHT as u16,
+                local_color_table_flag: false,
+                interlace_flag: false,
+                sort_flag: false,
+                reserved: 0,
+                local_color_table_size: 0,
+            },
+            local_color_table: None,
+            frame_indices: (0..WIDTH * HEIGHT)
+                .map(|i| {
+                    let (x, y) = (i % WIDTH, i / WIDTH);
+                    if (offset..offset + 3).contains(&x) && (2..5).contains(&y) {
+                        3
+                    } else {
+                        background.map_or(0, |value| value + (x % 2) as u8)
+                    }
+                })
+                .collect(),
+            extensions: graphics_control(
+                DisposalMethod::RestoreToBackground,
+                delay_timer,
+                false,
+                background.is_none().then_some(0),
+            )
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    fn gif_file(frames: Vec<GifFrame>) -> GifFile {
+        GifFile {
+            header: GifHeader::GIF89a,
+            logical_screen_descriptor: LogicalScreenDescriptor {
+                canvas_width: WIDTH as u16,
+                canvas_height: HEIGHT as u16,
+                global_color_table_flag: true,
+                color_resolution: 7,
+                sort_flag: false,
+                global_color_table_size: 1,
+                background_color_index: 0,
+                pixel_aspect_ratio: 0,
+            },
+            global_color_table: Some(palette()),
+            frames,
+            trailing_extensions: Vec::new(),
+        }
+    }
+
+    // Rendered canvases with consecutive identical ones merged, after a round trip
+    fn timeline(gif_file: &GifFile) -> Vec<(Vec<u8>, u16)> {
+        let gif_file = GifFile::new(&gif_file.to_bytes()).unwrap();
+        merge(
+            render(&gif_file, RenderOptions::default()).unwrap(),
+            &gif_file.frames,
+        )
+        .into_iter()
+        .map(|canvas| (canvas.pixels, canvas.delay_timer))
+        .collect()
+    }
+
+    fn optimized(frames: Vec<GifFrame>) -> GifFile {
+        let original = gif_file(frames);
+        let mut gif_file = GifFile::new(&original.to_bytes()).unwrap();
+        optimize_frames(&mut gif_file).unwrap();
+        assert_eq!(timeline(&gif_file), timeline(&original));
+        assert!(gif_file.to_bytes().len() < original.to_bytes().len());
+        gif_file
+    }
+
+    #[test]
+    fn crop_and_merge_frames() {
+        let gif_file = optimized(vec![
+            frame(0, Some(1), 10),
+            frame(2, Some(1), 10),
+            frame(4, Some(1), 10),
+            frame(4, Some(1), 10),
+            frame(4, Some(1), 5),
+        ]);
+        let frames = &gif_file.frames;
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[2].delay_timer(), 25);
+        for frame in &frames[1..] {
+            let id = &frame.image_descriptor;
+            assert_eq!((id.width, id.height), (5, 3));
+            assert_eq!(frame.disposal_method(), DisposalMethod::DoNotDispose);
+            // Where the squares overlap is left as is, with the only unused index
+            assert_eq!(frame.transparent_color_index(), Some(0));
+            assert_eq!(frame.frame_indices[2], 0);
+        }
+    }
+
+    #[test]
+    fn keep_untimed_and_user_input_frames() {
+        // Frames without a delay are shown for about 100ms each by browsers
+        let gif_file = optimized(vec![
+            frame(0, Some(1), 10),
+            frame(0, Some(1), 0),
+            frame(0, Some(1), 0),
+            frame(0, Some(1), 10),
+            frame(0, Some(1), 10),
+        ]);
+        let delays: Vec<u16> = gif_file.frames.iter().map(GifFrame::delay_timer).collect();
+        assert_eq!(delays, vec![10, 0, 0, 20]);
+
+        let mut frames = vec![
+            frame(0, Some(1), 10),
+            frame(0, Some(1), 10),
+            frame(0, Some(1), 10),
+        ];
+        if let Extension::GraphicsControlExtension {
+            user_input_flag, ..
+        } = &mut frames[1].extensions[0]
+        {
+            *user_input_flag = true;
+        }
+        let gif_file = optimized(frames);
+        let user_input: Vec<bool> = gif_file
+            .frames
+            .iter()
+            .map(GifFrame::user_input_flag)
+            .collect();
+        assert_eq!(user_input, vec![false, true, false]);
+    }
+
+    #[test]
+    fn choose_disposal() {
+        // Moving over a transparent background, the old square has to be cleared
+        let gif_file = optimized(vec![
+            frame(0, None, 10),
+            frame(6, None, 10),
+            frame(12, None, 10),
+        ]);
+        let disposals: Vec<_> = gif_file
+            .frames
+            .iter()
+            .map(GifFrame::disposal_method)
+            .collect();
+        assert_eq!(
+            disposals,
+            vec![
+                DisposalMethod::RestoreToBackground,
+                DisposalMethod::RestoreToBackground,
+                DisposalMethod::DoNotDispose
+            ]
+        );
+        assert_eq!(gif_file.frames[1].image_descriptor.width, 3);
+
+        // Back to what was there before the square
+        let gif_file = optimized(vec![
+            frame(16, Some(1), 10),
+            frame(4, Some(1), 10),
+            frame(16, Some(1), 10),
+        ]);
+        assert_eq!(
+            gif_file.frames[1].disposal_method(),
+            DisposalMethod::RestoreToPrevious
+        );
+        assert_eq!(gif_file.frames[2].frame_indices.len(), 1);
+    }
+}
@@ -0,0 +1,8 @@
+mod errors;
+mod frames;
+mod palette;
+mod unify;
+pub use errors::*;
+pub use frames::*;
+pub use palette::*;
+pub use unify::*;
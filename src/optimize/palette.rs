@@ -0,0 +1,376 @@
+use crate::decoder::{Extension, GifFile, GifFrame, Pixel};
+use crate::encoder::{color_table_size, padded};
+use std::collections::HashMap;
+
+// Frame indices drawn with a color table, and which of them is transparent
+type User<'a> = (&'a [u8], Option<u8>);
+
+// A color table with only the colors that are shown
+struct Shrunk {
+    color_table: Vec<Pixel>,
+    // New index of every old index that is shown
+    indices: [u8; 256],
+    // New transparent index of every user
+    transparent_indices: Vec<Option<u8>>,
+}
+
+pub(super) fn rgb(pixel: &Pixel) -> [u8; 3] {
+    [pixel.red, pixel.green, pixel.blue]
+}
+
+// Drops the entries of `color_table` that no user shows, as well as duplicates.
+// `extra` indices are kept too. None if an index is out of range, since those
+// are drawn differently depending on the `ColorPolicy`.
+fn shrink(color_table: &[Pixel], users: &[User], extra: &[u8]) -> Option<Shrunk> {
+    let visible: Vec<[bool; 256]> = users
+        .iter()
+        .map(|&(frame_indices, transparent_index)| {
+            let mut visible = [false; 256];
+            for &index in frame_indices {
+                visible[index as usize] |= Some(index) != transparent_index;
+            }
+            visible
+        })
+        .collect();
+    let mut used = [false; 256];
+    for &index in extra {
+        used[index as usize] = true;
+    }
+    for visible in &visible {
+        for (used, &visible) in used.iter_mut().zip(visible) {
+            *used |= visible;
+        }
+    }
+    if used[color_table.len().min(256)..].contains(&true) {
+        return None;
+    }
+
+    let mut shrunk = Shrunk {
+        color_table: Vec::new(),
+        indices: [0; 256],
+        transparent_indices: Vec::new(),
+    };
+    let mut lookup = HashMap::new();
+    for (i, pixel) in color_table.iter().enumerate().take(256) {
+        if used[i] {
+            shrunk.indices[i] = *lookup.entry(rgb(pixel)).or_insert_with(|| {
+                shrunk.color_table.push(*pixel);
+                (shrunk.color_table.len() - 1) as u8
+            });
+        }
+    }
+
+    // Transparent pixels need an index that the frame doesn't show
+    let shown: Vec<[bool; 256]> = visible
+        .iter()
+        .map(|visible| {
+            let mut shown = [false; 256];
+            for (i, _) in visible.iter().enumerate().filter(|(_, &visible)| visible) {
+                shown[shrunk.indices[i] as usize] = true;
+            }
+            shown
+        })
+        .collect();
+    let transparent: Vec<bool> = users.iter().map(|(_, index)| index.is_some()).collect();
+    (shrunk.color_table, shrunk.transparent_indices) =
+        transparent_slots(shrunk.color_table, &shown, &transparent);
+    Some(shrunk)
+}
+
+// Pads `color_table`, after adding an entry if a frame with `transparent` pixels
+// shows every color. Transparent pixels get the last index that their frame
+// doesn't show, padding is usually at the end.
+pub(super) fn transparent_slots(
+    mut color_table: Vec<Pixel>,
+    shown: &[[bool; 256]],
+    transparent: &[bool],
+) -> (Vec<Pixel>, Vec<Option<u8>>) {
+    let len = color_table.len();
+    let full = shown
+        .iter()
+        .zip(transparent)
+        .any(|(shown, &transparent)| transparent && !shown[..len].contains(&false));
+    if full {
+        color_table.push(Pixel {
+            red: 0,
+            green: 0,
+            blue: 0,
+        });
+    }
+    let color_table = padded(color_table);
+    let transparent_indices = shown
+        .iter()
+        .zip(transparent)
+        .map(|(shown, &transparent)| {
+            (0..color_table.len())
+                .rev()
+                .find(|&i| transparent && !shown[i])
+                .map(|i| i as u8)
+        })
+        .collect();
+    (color_table, transparent_indices)
+}
+
+fn remap(frame: &mut GifFrame, shrunk: &Shrunk, transparent_index: Option<u8>) {
+    let old_transparent_index = frame.transparent_color_index();
+    for index in frame.frame_indices.iter_mut() {
+        *index = match (Some(*index) == old_transparent_index, transparent_index) {
+            (true, Some(transparent_index)) => transparent_index,
+            _ => shrunk.indices[*index as usize],
+        };
+    }
+    for extension in frame.extensions.iter_mut() {
+        if let Extension::GraphicsControlExtension {
+            transparent_color_flag: true,
+            transparent_color_index,
+            ..
+        } = extension
+        {
+            *transparent_color_index = transparent_index.unwrap_or(0);
+        }
+    }
+}
+
+// Colors of the Plain Text Extensions, which always come from the Global Color Table
+pub(super) fn text_colors(gif_file: &mut GifFile) -> Vec<&mut u8> {
+    gif_file
+        .frames
+        .iter_mut()
+        .flat_map(|frame| frame.extensions.iter_mut())
+        .chain(gif_file.trailing_extensions.iter_mut())
+        .flat_map(|extension| match extension {
+            Extension::PlainText {
+                text_foreground_color_index,
+                text_background_color_index,
+                ..
+            } => vec![text_foreground_color_index, text_background_color_index],
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+/// Shrinks the color tables of `gif_file` without changing how it looks:
+/// colors that are never shown and duplicates are dropped, the indices are
+/// remapped and every table is as small as the power of two holding what is
+/// left. The encoder picks the LZW minimum code size from the table size, so
+/// that goes down as well.
+///
+/// A Global Color Table that no frame (or Plain Text Extension) uses is removed.
+/// Tables that are used with out-of-range indices are left as they are.
+pub fn optimize_palettes(gif_file: &mut GifFile) {
+    for frame in gif_file.frames.iter_mut() {
+        let Some(lct) = &frame.local_color_table else {
+            continue;
+        };
+        let users = [(&frame.frame_indices[..], frame.transparent_color_index())];
+        if let Some(shrunk) = shrink(lct, &users, &[]) {
+            remap(frame, &shrunk, shrunk.transparent_indices[0]);
+            frame.image_descriptor.local_color_table_size =
+                color_table_size(shrunk.color_table.len());
+            frame.local_color_table = Some(shrunk.color_table);
+        }
+    }
+
+    let extra: Vec<u8> = text_colors(gif_file)
+        .into_iter()
+        .map(|index| *index)
+        .collect();
+    let Some(gct) = &gif_file.global_color_table else {
+        return;
+    };
+    let users: Vec<User> = gif_file
+        .frames
+        .iter()
+        .filter(|frame| frame.local_color_table.is_none())
+        .map(|frame| (&frame.frame_indices[..], frame.transparent_color_index()))
+        .collect();
+    let lsd = &mut gif_file.logical_screen_descriptor;
+    if users.is_empty() && extra.is_empty() {
+        gif_file.global_color_table = None;
+        lsd.global_color_table_flag = false;
+        lsd.global_color_table_size = 0;
+        lsd.background_color_index = 0;
+        return;
+    }
+    let Some(shrunk) = shrink(gct, &users, &extra) else {
+        return;
+    };
+    // The background color is kept if it is still around
+    let background = gct.get(lsd.background_color_index as usize).map(rgb);
+    lsd.background_color_index = shrunk
+        .color_table
+        .iter()
+        .position(|pixel| Some(rgb(pixel)) == background)
+        .unwrap_or(0) as u8;
+    lsd.global_color_table_size = color_table_size(shrunk.color_table.len()) as u16;
+
+    let mut transparent_indices = shrunk.transparent_indices.iter();
+    for frame in gif_file.frames.iter_mut() {
+        if frame.local_color_table.is_none() {
+            remap(frame, &shrunk, *transparent_indices.next().unwrap());
+        }
+    }
+    for index in text_colors(gif_file) {
+        *index = shrunk.indices[*index as usize];
+    }
+    gif_file.global_color_table = Some(shrunk.color_table);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{
+        compressed_frames, DisposalMethod, GifHeader, ImageDescriptor, LogicalScreenDescriptor,
+    };
+    use crate::render::{render, RenderOptions};
+
+    // Grays repeating every 8 entries
+    fn grays(len: usize) -> Vec<Pixel> {
+        (0..len)
+            .map(|i| {
+                let value = (i % 8) as u8 * 30;
+                Pixel {
+                    red: value,
+                    green: value,
+                    blue: value,
+                }
+            })
+            .collect()
+    }
+
+    fn frame(
+        local_color_table: Option<Vec<Pixel>>,
+        frame_indices: Vec<u8>,
+        transparent_index: Option<u8>,
+    ) -> GifFrame {
+        GifFrame {
+            image_descriptor: ImageDescriptor {
+                left: 0,
+                top: 0,
+                width: 4,
+                height: 2,
+                local_color_table_flag: local_color_table.is_some(),
+                interlace_flag: false,
+                sort_flag: false,
+                reserved: 0,
+                local_color_table_size: 0,
+            },
+            local_color_table,
+            frame_indices,
+            extensions: vec![Extension::GraphicsControlExtension {
+                reserved: 0,
+                disposal_method: DisposalMethod::DoNotDispose,
+                user_input_flag: false,
+                transparent_color_flag: transparent_index.is_some(),
+                delay_timer: 10,
+                transparent_color_index: transparent_index.unwrap_or(0),
+            }],
+        }
+    }
+
+    fn gif_file(frames: Vec<GifFrame>) -> GifFile {
+        GifFile {
+            header: GifHeader::GIF89a,
+            logical_screen_descriptor: LogicalScreenDescriptor {
+                canvas_width: 4,
+                canvas_height: 2,
+                global_color_table_flag: true,
+                color_resolution: 7,
+                sort_flag: false,
+                global_color_table_size: 7,
+                background_color_index: 9,
+                pixel_aspect_ratio: 0,
+            },
+            global_color_table: Some(grays(256)),
+            frames,
+            trailing_extensions: Vec::new(),
+        }
+    }
+
+    // Optimizes a round trip of `gif_file`, which has to look the same after another one
+    fn optimized(gif_file: GifFile) -> (GifFile, Vec<u8>) {
+        let original = gif_file.to_bytes();
+        let mut gif_file = GifFile::new(&original).unwrap();
+        optimize_palettes(&mut gif_file);
+        let bytes = gif_file.to_bytes();
+        let rendered =
+            |bytes: &[u8]| render(&GifFile::new(bytes).unwrap(), RenderOptions::default()).unwrap();
+        assert_eq!(rendered(&bytes), rendered(&original));
+        assert!(bytes.len() < original.len());
+        (gif_file, bytes)
+    }
+
+    #[test]
+    fn shrink_color_tables() {
+        let (gif_file, bytes) = optimized(gif_file(vec![
+            // 8 and 16 are the same color as 0
+            frame(None, vec![0, 1, 2, 3, 8, 16, 3, 2], None),
+            // Keeps its transparent pixels apart from the color they used to have
+            frame(None, vec![200, 1, 200, 9, 2, 2, 1, 1], Some(200)),
+            frame(Some(grays(16)), vec![5, 13, 5, 13, 6, 6, 6, 6], None),
+        ]));
+        assert_eq!(gif_file.global_color_table, Some(grays(4)));
+        assert_eq!(gif_file.logical_screen_descriptor.background_color_index, 1);
+        assert_eq!(
+            gif_file.frames[0].frame_indices,
+            vec![0, 1, 2, 3, 0, 0, 3, 2]
+        );
+        assert_eq!(gif_file.frames[1].transparent_color_index(), Some(3));
+        assert_eq!(
+            gif_file.frames[1].frame_indices,
+            vec![3, 1, 3, 1, 2, 2, 1, 1]
+        );
+        assert_eq!(
+            gif_file.frames[2].local_color_table.as_ref().unwrap().len(),
+            2
+        );
+        assert_eq!(
+            gif_file.frames[2].frame_indices,
+            vec![0, 0, 0, 0, 1, 1, 1, 1]
+        );
+
+        let code_sizes: Vec<u8> = compressed_frames(&bytes)
+            .unwrap()
+            .iter()
+            .map(|frame| frame.lzw_minimum_code_size)
+            .collect();
+        assert_eq!(code_sizes, vec![2, 2, 2]);
+    }
+
+    #[test]
+    fn global_color_table_users() {
+        // Only the Plain Text Extension uses the Global Color Table
+        let mut text = gif_file(vec![frame(Some(grays(4)), vec![0; 8], None)]);
+        text.trailing_extensions.push(Extension::PlainText {
+            text_grid_left: 0,
+            text_grid_top: 0,
+            text_grid_width: 4,
+            text_grid_height: 2,
+            cell_width: 4,
+            cell_height: 2,
+            text_foreground_color_index: 7,
+            text_background_color_index: 15,
+            text: "x".into(),
+        });
+        let (optimized_text, _) = optimized(text);
+        assert_eq!(optimized_text.global_color_table.unwrap().len(), 2);
+        assert!(matches!(
+            optimized_text.trailing_extensions[0],
+            Extension::PlainText {
+                text_foreground_color_index: 0,
+                text_background_color_index: 0,
+                ..
+            }
+        ));
+
+        let (unused, _) = optimized(gif_file(vec![frame(Some(grays(8)), vec![1; 8], None)]));
+        assert_eq!(unused.global_color_table, None);
+        assert!(!unused.logical_screen_descriptor.global_color_table_flag);
+
+        // Out-of-range indices are drawn depending on the color policy
+        let mut out_of_range = gif_file(vec![frame(None, vec![7, 0, 0, 0, 0, 0, 0, 0], None)]);
+        out_of_range.global_color_table = Some(grays(4));
+        optimize_palettes(&mut out_of_range);
+        assert_eq!(out_of_range.global_color_table, Some(grays(4)));
+    }
+}
@@ -0,0 +1,317 @@
+use super::palette::{rgb, text_colors, transparent_slots};
+use crate::decoder::{Extension, GifFile, Pixel};
+use crate::encoder::{color_table_size, padded};
+use crate::quantize::{nearest_color, quantize_frames, QuantizeOptions};
+use crate::render::active_color_table;
+use std::collections::HashMap;
+
+// Frame indices mapped to the Global Color Table, and the transparent index
+type Unified = (Vec<u8>, Option<u8>);
+
+// Every color shown by `frames` (pixels, None if transparent) and `extra` in
+// one table, if they fit
+fn merge(frames: &[Vec<Option<Pixel>>], extra: &[Pixel]) -> Option<(Vec<Pixel>, Vec<Unified>)> {
+    let mut union = Vec::new();
+    let mut lookup = HashMap::new();
+    for &pixel in frames.iter().flatten().flatten().chain(extra) {
+        lookup.entry(rgb(&pixel)).or_insert_with(|| {
+            union.push(pixel);
+            union.len() - 1
+        });
+    }
+    if union.len() > 256 {
+        return None;
+    }
+
+    let shown: Vec<[bool; 256]> = frames
+        .iter()
+        .map(|pixels| {
+            let mut shown = [false; 256];
+            for pixel in pixels.iter().flatten() {
+                shown[lookup[&rgb(pixel)]] = true;
+            }
+            shown
+        })
+        .collect();
+    let transparent: Vec<bool> = frames.iter().map(|pixels| pixels.contains(&None)).collect();
+    let (color_table, transparent_indices) = transparent_slots(union, &shown, &transparent);
+    let unified = frames
+        .iter()
+        .zip(transparent_indices)
+        .map(|(pixels, transparent_index)| {
+            let frame_indices = pixels
+                .iter()
+                .map(|pixel| match pixel {
+                    Some(pixel) => lookup[&rgb(pixel)] as u8,
+                    None => transparent_index.unwrap_or(0),
+                })
+                .collect();
+            (frame_indices, transparent_index)
+        })
+        .collect();
+    Some((color_table, unified))
+}
+
+// Too many colors for one table, they are clustered instead
+fn cluster(frames: &[Vec<Option<Pixel>>], options: QuantizeOptions) -> (Vec<Pixel>, Vec<Unified>) {
+    let rgba: Vec<Vec<u8>> = frames
+        .iter()
+        .map(|pixels| {
+            pixels
+                .iter()
+                .flat_map(|pixel| match pixel {
+                    Some(pixel) => [pixel.red, pixel.green, pixel.blue, 255],
+                    None => [0; 4],
+                })
+                .collect()
+        })
+        .collect();
+    let rgba: Vec<&[u8]> = rgba.iter().map(Vec::as_slice).collect();
+    let quantized = quantize_frames(&rgba, options);
+    let unified = frames
+        .iter()
+        .zip(quantized.indices)
+        .map(|(pixels, frame_indices)| {
+            let transparent_index = quantized
+                .transparent_index
+                .filter(|_| pixels.contains(&None));
+            (frame_indices, transparent_index)
+        })
+        .collect();
+    (padded(quantized.color_table), unified)
+}
+
+/// Replaces the Local Color Tables of `gif_file` with a single Global Color
+/// Table. If all the colors shown fit in one table the animation looks exactly
+/// the same, otherwise they are clustered down to 256 colors with `options`.
+///
+/// Frames drawn with out-of-range indices, which look different depending on
+/// the `ColorPolicy`, keep a Local Color Table (a copy of the table they used).
+pub fn unify_palettes(gif_file: &mut GifFile, options: QuantizeOptions) {
+    if gif_file
+        .frames
+        .iter()
+        .all(|frame| frame.local_color_table.is_none())
+    {
+        return;
+    }
+    let tables: Vec<Vec<Pixel>> = gif_file
+        .frames
+        .iter()
+        .map(|frame| active_color_table(gif_file, frame).to_vec())
+        .collect();
+    // The color of every pixel, None for out-of-range frames
+    let pixels: Vec<Option<Vec<Option<Pixel>>>> = gif_file
+        .frames
+        .iter()
+        .zip(&tables)
+        .map(|(frame, table)| {
+            let transparent_index = frame.transparent_color_index();
+            frame
+                .frame_indices
+                .iter()
+                .map(|&index| match Some(index) == transparent_index {
+                    true => Some(None),
+                    false => table.get(index as usize).map(|pixel| Some(*pixel)),
+                })
+                .collect()
+        })
+        .collect();
+    let included: Vec<Vec<Option<Pixel>>> = pixels.iter().flatten().cloned().collect();
+    if included.is_empty() {
+        return;
+    }
+
+    // Plain Text Extensions and the background refer to the Global Color Table too
+    let gct = gif_file.global_color_table.clone().unwrap_or_default();
+    let text: Vec<Option<Pixel>> = text_colors(gif_file)
+        .into_iter()
+        .map(|index| gct.get(*index as usize).copied())
+        .collect();
+    let lsd = &mut gif_file.logical_screen_descriptor;
+    let background = gct.get(lsd.background_color_index as usize).copied();
+    let extra: Vec<Pixel> = text.iter().flatten().copied().collect();
+    let (color_table, unified) =
+        merge(&included, &extra).unwrap_or_else(|| cluster(&included, options));
+
+    lsd.global_color_table_flag = true;
+    lsd.global_color_table_size = color_table_size(color_table.len()) as u16;
+    lsd.background_color_index = background.map_or(0, |pixel| nearest_color(&color_table, pixel));
+    for (index, pixel) in text_colors(gif_file).into_iter().zip(text) {
+        if let Some(pixel) = pixel {
+            *index = nearest_color(&color_table, pixel);
+        }
+    }
+
+    let mut unified = unified.into_iter();
+    for ((frame, table), pixels) in gif_file.frames.iter_mut().zip(tables).zip(&pixels) {
+        let id = &mut frame.image_descriptor;
+        if pixels.is_none() {
+            if frame.local_color_table.is_none() {
+                id.local_color_table_flag = true;
+                id.local_color_table_size = color_table_size(table.len());
+                frame.local_color_table = Some(table);
+            }
+            continue;
+        }
+        let (frame_indices, transparent_index) = unified.next().unwrap();
+        id.local_color_table_flag = false;
+        id.local_color_table_size = 0;
+        frame.local_color_table = None;
+        frame.frame_indices = frame_indices;
+        for extension in frame.extensions.iter_mut() {
+            if let Extension::GraphicsControlExtension {
+                transparent_color_flag: flag @ true,
+                transparent_color_index,
+                ..
+            } = extension
+            {
+                // Without transparent pixels there may be no index to spare
+                *flag = transparent_index.is_some();
+                *transparent_color_index = transparent_index.unwrap_or(0);
+            }
+        }
+    }
+    gif_file.global_color_table = Some(color_table);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{
+        DisposalMethod, GifFrame, GifHeader, ImageDescriptor, LogicalScreenDescriptor,
+    };
+    use crate::render::{render, RenderOptions, RgbaFrame};
+
+    fn colors(len: usize, seed: usize) -> Vec<Pixel> {
+        (0..len)
+            .map(|i| Pixel {
+                red: (i * 2 + seed) as u8,
+                green: (i * 3) as u8,
+                blue: seed as u8,
+            })
+            .collect()
+    }
+
+    fn frame(
+        local_color_table: Option<Vec<Pixel>>,
+        frame_indices: Vec<u8>,
+        transparent_index: Option<u8>,
+    ) -> GifFrame {
+        GifFrame {
+            image_descriptor: ImageDescriptor {
+                left: 0,
+                top: 0,
+                width: 16,
+                height: (frame_indices.len() / 16) as u16,
+                local_color_table_flag: local_color_table.is_some(),
+                interlace_flag: false,
+                sort_flag: false,
+                reserved: 0,
+                local_color_table_size: 0,
+            },
+            local_color_table,
+            frame_indices,
+            extensions: vec![Extension::GraphicsControlExtension {
+                reserved: 0,
+                disposal_method: DisposalMethod::DoNotDispose,
+                user_input_flag: false,
+                transparent_color_flag: transparent_index.is_some(),
+                delay_timer: 10,
+                transparent_color_index: transparent_index.unwrap_or(0),
+            }],
+        }
+    }
+
+    fn gif_file(frames: Vec<GifFrame>) -> GifFile {
+        GifFile {
+            header: GifHeader::GIF89a,
+            logical_screen_descriptor: LogicalScreenDescriptor {
+                canvas_width: 16,
+                canvas_height: 16,
+                global_color_table_flag: true,
+                color_resolution: 7,
+                sort_flag: false,
+                global_color_table_size: 1,
+                background_color_index: 0,
+                pixel_aspect_ratio: 0,
+            },
+            global_color_table: Some(colors(4, 0)),
+            frames,
+            trailing_extensions: Vec::new(),
+        }
+    }
+
+    fn rendered(gif_file: &GifFile) -> Vec<RgbaFrame> {
+        let gif_file = GifFile::new(&gif_file.to_bytes()).unwrap();
+        render(&gif_file, RenderOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn merge_local_color_tables() {
+        let original = gif_file(vec![
+            frame(None, (0..64).map(|i| i % 4).collect(), None),
+            // Overlaps with the Global Color Table
+            frame(
+                Some(colors(8, 0)),
+                (0..64).map(|i| i % 8).collect(),
+                Some(7),
+            ),
+            frame(Some(colors(8, 1)), (0..64).map(|i| i % 5).collect(), None),
+        ]);
+        let mut gif_file = GifFile::new(&original.to_bytes()).unwrap();
+        unify_palettes(&mut gif_file, QuantizeOptions::default());
+        assert_eq!(rendered(&gif_file), rendered(&original));
+        assert!(gif_file.to_bytes().len() < original.to_bytes().len());
+
+        // 7 colors from the second frame, 4 of them in the Global Color Table too, and 5
+        // from the last frame. Transparent pixels get an entry of the padding.
+        assert_eq!(gif_file.global_color_table.as_ref().unwrap().len(), 16);
+        for frame in &gif_file.frames {
+            assert_eq!(frame.local_color_table, None);
+            assert!(!frame.image_descriptor.local_color_table_flag);
+        }
+        assert_eq!(gif_file.frames[1].transparent_color_index(), Some(15));
+    }
+
+    #[test]
+    fn cluster_local_color_tables() {
+        let original = gif_file(vec![
+            frame(Some(colors(256, 0)), (0..=255).collect(), Some(0)),
+            frame(Some(colors(256, 1)), (0..=255).collect(), None),
+        ]);
+        let mut gif_file = GifFile::new(&original.to_bytes()).unwrap();
+        unify_palettes(&mut gif_file, QuantizeOptions::default());
+        assert_eq!(gif_file.global_color_table.as_ref().unwrap().len(), 256);
+        assert!(gif_file
+            .frames
+            .iter()
+            .all(|frame| frame.local_color_table.is_none()));
+
+        for (after, before) in rendered(&gif_file).iter().zip(rendered(&original)) {
+            for (after, before) in after.pixels.chunks(4).zip(before.pixels.chunks(4)) {
+                // Transparency is kept exactly, colors closely
+                assert_eq!(after[3], before[3]);
+                let error = (0..3)
+                    .map(|c| after[c].abs_diff(before[c]) as u32)
+                    .sum::<u32>();
+                assert!(error <= 12, "{:?} {:?}", after, before);
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_range_frames_keep_their_table() {
+        let mut gif_file = gif_file(vec![
+            frame(None, vec![9; 16], None),
+            frame(Some(colors(2, 5)), vec![1; 16], None),
+        ]);
+        unify_palettes(&mut gif_file, QuantizeOptions::default());
+        assert_eq!(gif_file.frames[0].local_color_table, Some(colors(4, 0)));
+        assert_eq!(gif_file.frames[1].local_color_table, None);
+        assert_eq!(
+            gif_file.global_color_table,
+            Some(padded(vec![colors(2, 5)[1]]))
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use crate::decoder::Pixel;
+
+/// Space in which colors are compared when building a palette and mapping
+/// pixels to it. The perceptual spaces avoid the banding that RGB distances
+/// cause in gradients and skin tones, at the cost of a conversion per color.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum ColorSpace {
+    #[default]
+    Rgb,
+    Oklab,
+    // CIE L*a*b* with a D65 white point, compared with the CIE76 distance
+    Lab,
+}
+
+// sRGB transfer function, 0..=255 to linear 0.0..=1.0
+fn linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn oklab([red, green, blue]: [f32; 3]) -> [f32; 3] {
+    let l = 0.412_221_46 * red + 0.536_332_55 * green + 0.051_445_995 * blue;
+    let m = 0.211_903_5 * red + 0.680_699_5 * green + 0.107_396_96 * blue;
+    let s = 0.088_302_46 * red + 0.281_718_85 * green + 0.629_978_7 * blue;
+    let (l, m, s) = (l.cbrt(), m.cbrt(), s.cbrt());
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+fn lab([red, green, blue]: [f32; 3]) -> [f32; 3] {
+    // Relative to the D65 white point
+    let x = (0.412_456_4 * red + 0.357_576_1 * green + 0.180_437_5 * blue) / 0.950_47;
+    let y = 0.212_672_9 * red + 0.715_152_2 * green + 0.072_175 * blue;
+    let z = (0.019_333_9 * red + 0.119_192 * green + 0.950_304_1 * blue) / 1.088_83;
+    let f = |t: f32| {
+        const DELTA: f32 = 6.0 / 29.0;
+        if t > DELTA * DELTA * DELTA {
+            t.cbrt()
+        } else {
+            t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+        }
+    };
+    let (x, y, z) = (f(x), f(y), f(z));
+    [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+}
+
+impl ColorSpace {
+    pub(super) fn position(&self, color: [u8; 3]) -> [f32; 3] {
+        match self {
+            ColorSpace::Rgb => color.map(|value| value as f32),
+            ColorSpace::Oklab => oklab(color.map(linear)),
+            ColorSpace::Lab => lab(color.map(linear)),
+        }
+    }
+
+    /// Coordinates of `pixel` in this color space.
+    pub fn coordinates(&self, pixel: Pixel) -> [f32; 3] {
+        self.position([pixel.red, pixel.green, pixel.blue])
+    }
+
+    /// Squared euclidean distance between `a` and `b` in this color space.
+    pub fn distance(&self, a: Pixel, b: Pixel) -> f32 {
+        distance(self.coordinates(a), self.coordinates(b))
+    }
+}
+
+pub(super) fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3], tolerance: f32) {
+        for (actual, expected) in actual.iter().zip(&expected) {
+            assert!(
+                (actual - expected).abs() < tolerance,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn convert_colors() {
+        let white = [255, 255, 255];
+        let red = [255, 0, 0];
+        assert_close(ColorSpace::Oklab.position([0, 0, 0]), [0.0, 0.0, 0.0], 1e-4);
+        assert_close(ColorSpace::Oklab.position(white), [1.0, 0.0, 0.0], 1e-3);
+        assert_close(
+            ColorSpace::Oklab.position(red),
+            [0.628, 0.2249, 0.1258],
+            1e-3,
+        );
+        assert_close(ColorSpace::Lab.position(white), [100.0, 0.0, 0.0], 1e-2);
+        assert_close(ColorSpace::Lab.position(red), [53.24, 80.09, 67.20], 2e-2);
+        assert_eq!(ColorSpace::Rgb.position(red), [255.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,111 @@
+use super::color_space::{distance, ColorSpace};
+use super::WeightedColor;
+
+/// Initial centroids spread out over the colors: the most common color first,
+/// then every time the color furthest from the centroids picked so far.
+pub(super) fn farthest_points(colors: &[WeightedColor], max_colors: usize) -> Vec<[u8; 3]> {
+    let Some(first) = colors.iter().max_by_key(|color| color.weight) else {
+        return Vec::new();
+    };
+    let mut centroids = vec![first.color];
+    // Distance from every color to its closest centroid
+    let mut distances: Vec<f32> = colors
+        .iter()
+        .map(|color| distance(color.position, first.position))
+        .collect();
+    while centroids.len() < max_colors {
+        let Some((furthest, _)) = distances
+            .iter()
+            .enumerate()
+            .filter(|(_, &distance)| distance > 0.0)
+            .max_by(|a, b| a.1.total_cmp(b.1))
+        else {
+            break;
+        };
+        let centroid = colors[furthest].position;
+        centroids.push(colors[furthest].color);
+        for (closest, color) in distances.iter_mut().zip(colors) {
+            *closest = closest.min(distance(color.position, centroid));
+        }
+    }
+    centroids
+}
+
+fn nearest(centroids: &[[f32; 3]], position: [f32; 3]) -> usize {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(i, &centroid)| (i, distance(centroid, position)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(0, |(i, _)| i)
+}
+
+/// Lloyd's algorithm: moves every centroid to the weighted mean of the colors
+/// closest to it, until nothing changes or `iterations` runs out. Clusters are
+/// formed in `color_space`, the palette is the mean RGB color of each cluster.
+pub(super) fn refine(
+    colors: &[WeightedColor],
+    mut palette: Vec<[u8; 3]>,
+    iterations: usize,
+    color_space: ColorSpace,
+) -> Vec<[u8; 3]> {
+    let mut centroids: Vec<[f32; 3]> = palette
+        .iter()
+        .map(|&color| color_space.position(color))
+        .collect();
+    for _ in 0..iterations {
+        let mut color_sums = vec![[0u64; 3]; centroids.len()];
+        let mut position_sums = vec![[0f64; 3]; centroids.len()];
+        let mut weights = vec![0u64; centroids.len()];
+        for color in colors {
+            let cluster = nearest(&centroids, color.position);
+            let weight = color.weight as u64;
+            weights[cluster] += weight;
+            for (sum, &value) in color_sums[cluster].iter_mut().zip(&color.color) {
+                *sum += value as u64 * weight;
+            }
+            for (sum, &value) in position_sums[cluster].iter_mut().zip(&color.position) {
+                *sum += value as f64 * weight as f64;
+            }
+        }
+        let mut changed = false;
+        for (i, &weight) in weights.iter().enumerate() {
+            // Empty clusters keep their centroid
+            if weight == 0 {
+                continue;
+            }
+            let mean = color_sums[i].map(|sum| ((sum + weight / 2) / weight) as u8);
+            changed |= mean != palette[i];
+            palette[i] = mean;
+            centroids[i] = position_sums[i].map(|sum| (sum / weight as f64) as f32);
+        }
+        if !changed {
+            break;
+        }
+    }
+    palette
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refine_clusters() {
+        let colors = [
+            ([0, 0, 0], 2),
+            ([10, 0, 0], 1),
+            ([200, 0, 0], 1),
+            ([210, 0, 0], 1),
+        ]
+        .map(|(color, weight)| WeightedColor::new(color, weight, ColorSpace::Rgb));
+        let initial = farthest_points(&colors, 2);
+        assert_eq!(initial, vec![[0, 0, 0], [210, 0, 0]]);
+        assert_eq!(
+            refine(&colors, initial, 10, ColorSpace::Rgb),
+            vec![[3, 0, 0], [205, 0, 0]]
+        );
+        // Never more centroids than distinct colors
+        assert_eq!(farthest_points(&colors[..1], 4).len(), 1);
+    }
+}
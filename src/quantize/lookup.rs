@@ -0,0 +1,143 @@
+use super::color_space::{distance, ColorSpace};
+use crate::decoder::Pixel;
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    position: [f32; 3],
+    index: u8,
+}
+
+/// Finds the closest entry of a color table, with a k-d tree built once
+/// so that mapping big frames doesn't compare every pixel to every color.
+#[derive(Debug, Clone)]
+pub struct ColorLookup {
+    color_space: ColorSpace,
+    // Implicit tree: the root of every sub-slice is its middle entry, split
+    // on axis `depth % 3`, with the smaller values before it
+    entries: Vec<Entry>,
+}
+
+fn build(entries: &mut [Entry], depth: usize) {
+    if entries.len() < 2 {
+        return;
+    }
+    let axis = depth % 3;
+    let middle = entries.len() / 2;
+    entries.select_nth_unstable_by(middle, |a, b| a.position[axis].total_cmp(&b.position[axis]));
+    let (lower, upper) = entries.split_at_mut(middle);
+    build(lower, depth + 1);
+    build(&mut upper[1..], depth + 1);
+}
+
+// Closest (distance, index) so far, ties go to the lowest index like a linear search
+fn search(entries: &[Entry], depth: usize, position: [f32; 3], best: &mut (f32, u8)) {
+    if entries.is_empty() {
+        return;
+    }
+    let middle = entries.len() / 2;
+    let entry = entries[middle];
+    let candidate = (distance(entry.position, position), entry.index);
+    if candidate.0 < best.0 || (candidate.0 == best.0 && candidate.1 < best.1) {
+        *best = candidate;
+    }
+    let axis = depth % 3;
+    let offset = position[axis] - entry.position[axis];
+    let (near, far) = if offset < 0.0 {
+        (&entries[..middle], &entries[middle + 1..])
+    } else {
+        (&entries[middle + 1..], &entries[..middle])
+    };
+    search(near, depth + 1, position, best);
+    // The other side can only be closer if the splitting plane is
+    if offset * offset <= best.0 {
+        search(far, depth + 1, position, best);
+    }
+}
+
+impl ColorLookup {
+    pub fn new(color_table: &[Pixel], color_space: ColorSpace) -> Self {
+        Self::opaque(color_table, color_space, None)
+    }
+
+    /// Like `new`, but `transparent_index` is never returned.
+    pub fn opaque(
+        color_table: &[Pixel],
+        color_space: ColorSpace,
+        transparent_index: Option<u8>,
+    ) -> Self {
+        let mut entries: Vec<Entry> = color_table
+            .iter()
+            .take(256)
+            .enumerate()
+            .filter(|&(i, _)| Some(i as u8) != transparent_index)
+            .map(|(i, &pixel)| Entry {
+                position: color_space.coordinates(pixel),
+                index: i as u8,
+            })
+            .collect();
+        build(&mut entries, 0);
+        ColorLookup {
+            color_space,
+            entries,
+        }
+    }
+
+    /// Index of the entry closest to `pixel`, 0 for an empty color table.
+    pub fn nearest(&self, pixel: Pixel) -> u8 {
+        self.nearest_position(self.color_space.coordinates(pixel))
+    }
+
+    fn nearest_position(&self, position: [f32; 3]) -> u8 {
+        let mut best = (f32::INFINITY, 0);
+        search(&self.entries, 0, position, &mut best);
+        best.1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::fixtures::xorshift;
+
+    #[test]
+    fn lookup_matches_linear_search() {
+        let mut next = xorshift(7);
+        let mut random = || {
+            let state = next();
+            Pixel {
+                red: state as u8,
+                green: (state >> 8) as u8,
+                blue: (state >> 16) as u8,
+            }
+        };
+        let mut color_table: Vec<Pixel> = (0..200).map(|_| random()).collect();
+        // Duplicates must resolve to the first one
+        color_table.push(color_table[3]);
+        for color_space in [ColorSpace::Rgb, ColorSpace::Oklab, ColorSpace::Lab] {
+            let lookup = ColorLookup::new(&color_table, color_space);
+            for _ in 0..500 {
+                let pixel = random();
+                let expected = color_table
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &entry)| (color_space.distance(entry, pixel), i))
+                    .min_by(|a, b| a.0.total_cmp(&b.0))
+                    .unwrap();
+                let found = lookup.nearest(pixel) as usize;
+                assert_eq!(
+                    color_space.distance(color_table[found], pixel),
+                    expected.0,
+                    "{:?}",
+                    color_space
+                );
+            }
+            assert_eq!(lookup.nearest(color_table[200]), 3);
+            let opaque = ColorLookup::opaque(&color_table, color_space, Some(3));
+            assert_eq!(opaque.nearest(color_table[3]), 200);
+        }
+        assert_eq!(
+            ColorLookup::new(&[], ColorSpace::Oklab).nearest(random()),
+            0
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use super::color_space::distance;
+use super::{Quality, WeightedColor};
+
+// A set of colors, split in two along its widest axis until there are enough
+struct ColorBox {
+    colors: Vec<WeightedColor>,
+}
+
+impl ColorBox {
+    fn weight(&self) -> u64 {
+        self.colors.iter().map(|color| color.weight as u64).sum()
+    }
+
+    // (axis, range) of the axis with the widest range, in the working color space
+    fn widest_axis(&self) -> (usize, f32) {
+        (0..3)
+            .map(|axis| {
+                let (min, max) = self.colors.iter().fold(
+                    (f32::INFINITY, f32::NEG_INFINITY),
+                    |(min, max), color| {
+                        (min.min(color.position[axis]), max.max(color.position[axis]))
+                    },
+                );
+                (axis, (max - min).max(0.0))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0, 0.0))
+    }
+
+    // Mean of the colors, weighted by the number of pixels
+    fn mean(&self) -> [u8; 3] {
+        let weight = self.weight().max(1);
+        let mut sums = [0u64; 3];
+        for color in &self.colors {
+            for (sum, &value) in sums.iter_mut().zip(&color.color) {
+                *sum += value as u64 * color.weight as u64;
+            }
+        }
+        sums.map(|sum| ((sum + weight / 2) / weight) as u8)
+    }
+
+    // Sum of the squared distances to the mean position, weighted by the number of pixels
+    fn variance(&self) -> f64 {
+        let weight = self.weight().max(1) as f64;
+        let mut sums = [0f64; 3];
+        for color in &self.colors {
+            for (sum, &value) in sums.iter_mut().zip(&color.position) {
+                *sum += value as f64 * color.weight as f64;
+            }
+        }
+        let mean = sums.map(|sum| (sum / weight) as f32);
+        self.colors
+            .iter()
+            .map(|color| distance(color.position, mean) as f64 * color.weight as f64)
+            .sum()
+    }
+
+    // How much splitting this box would help, 0 if it can't be split
+    fn priority(&self, quality: Quality) -> f64 {
+        if self.colors.len() < 2 {
+            return 0.0;
+        }
+        match quality {
+            Quality::Best => self.variance(),
+            Quality::Fast | Quality::Balanced => {
+                self.widest_axis().1 as f64 * (self.weight() as f64).sqrt()
+            }
+        }
+    }
+
+    // Splits at the weighted median of the widest axis, both halves are non-empty
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (axis, _) = self.widest_axis();
+        self.colors
+            .sort_unstable_by(|a, b| a.position[axis].total_cmp(&b.position[axis]));
+        let half = self.weight() / 2;
+        let mut seen = 0;
+        let mut median = self
+            .colors
+            .iter()
+            .position(|color| {
+                seen += color.weight as u64;
+                seen > half
+            })
+            .unwrap_or(0);
+        median = median.clamp(1, self.colors.len() - 1);
+        let upper = self.colors.split_off(median);
+        (self, ColorBox { colors: upper })
+    }
+}
+
+/// Median cut palette of at most `max_colors` colors.
+pub(super) fn palette(
+    colors: Vec<WeightedColor>,
+    max_colors: usize,
+    quality: Quality,
+) -> Vec<[u8; 3]> {
+    let mut boxes = vec![ColorBox { colors }];
+    while boxes.len() < max_colors {
+        let (i, priority) = boxes
+            .iter()
+            .enumerate()
+            .map(|(i, color_box)| (i, color_box.priority(quality)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0, 0.0));
+        if priority <= 0.0 {
+            break;
+        }
+        let (lower, upper) = boxes.swap_remove(i).split();
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+    boxes
+        .iter()
+        .filter(|color_box| !color_box.colors.is_empty())
+        .map(ColorBox::mean)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quantize::ColorSpace;
+
+    #[test]
+    fn split_at_weighted_median() {
+        let colors = [(0, 1), (10, 1), (20, 5), (200, 1)]
+            .map(|(red, weight)| WeightedColor::new([red, 0, 0], weight, ColorSpace::Rgb))
+            .to_vec();
+        let (lower, upper) = ColorBox { colors }.split();
+        assert_eq!(lower.colors.len(), 2);
+        assert_eq!(upper.colors.len(), 2);
+        assert_eq!(lower.mean(), [5, 0, 0]);
+
+        let palette = palette(upper.colors, 2, Quality::Balanced);
+        assert_eq!(palette, vec![[20, 0, 0], [200, 0, 0]]);
+    }
+}
@@ -0,0 +1,494 @@
+mod color_space;
+mod kmeans;
+mod lookup;
+mod median_cut;
+mod octree;
+pub use color_space::*;
+pub use lookup::*;
+
+use crate::decoder::Pixel;
+use std::collections::HashMap;
+
+/// Algorithm used to pick the colors of the palette.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum QuantizeMethod {
+    // Recursively splits the box of colors with the widest range at its median
+    #[default]
+    MedianCut,
+    // Merges the least used branches of an 8 level RGB tree
+    Octree,
+    // Refines an initial palette by moving every color to the mean of its cluster,
+    // starting from the median cut palette if `seed_with_median_cut` is set
+    KMeans {
+        seed_with_median_cut: bool,
+    },
+}
+
+/// Trades quantization speed for palette quality.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Quality {
+    // Only looks at every 4th pixel and barely refines k-means
+    Fast,
+    #[default]
+    Balanced,
+    // Splits median cut boxes by variance and runs k-means until it settles
+    Best,
+}
+
+impl Quality {
+    // Distance between the pixels used to build the palette
+    fn sample_step(&self) -> usize {
+        match self {
+            Quality::Fast => 4,
+            Quality::Balanced | Quality::Best => 1,
+        }
+    }
+
+    fn kmeans_iterations(&self) -> usize {
+        match self {
+            Quality::Fast => 2,
+            Quality::Balanced => 8,
+            Quality::Best => 32,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct QuantizeOptions {
+    pub method: QuantizeMethod,
+    // Clamped to 2..=256, including the transparent entry if there is one
+    pub max_colors: usize,
+    pub quality: Quality,
+    // Pixels with a lower alpha are transparent, the others are made opaque
+    pub alpha_threshold: u8,
+    // Used both to build the palette and to map pixels to it
+    pub color_space: ColorSpace,
+}
+
+impl Default for QuantizeOptions {
+    fn default() -> Self {
+        QuantizeOptions {
+            method: QuantizeMethod::default(),
+            max_colors: 256,
+            quality: Quality::default(),
+            alpha_threshold: 128,
+            color_space: ColorSpace::default(),
+        }
+    }
+}
+
+/// A color and the number of pixels that have it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct WeightedColor {
+    color: [u8; 3],
+    // Coordinates in the color space the palette is built in
+    position: [f32; 3],
+    weight: u32,
+}
+
+impl WeightedColor {
+    fn new(color: [u8; 3], weight: u32, color_space: ColorSpace) -> Self {
+        WeightedColor {
+            color,
+            position: color_space.position(color),
+            weight,
+        }
+    }
+}
+
+/// A color table and the indices of every pixel into it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct QuantizedImage {
+    // At most 256 entries, ready to be used as a Local or Global Color Table
+    pub color_table: Vec<Pixel>,
+    pub indices: Vec<u8>,
+    // Entry used by the transparent pixels, always the last one of the table
+    pub transparent_index: Option<u8>,
+}
+
+/// Like `QuantizedImage`, for several frames sharing a single color table.
+#[derive(Debug, PartialEq, Clone)]
+pub struct QuantizedFrames {
+    pub color_table: Vec<Pixel>,
+    // One index map per frame, in the same order
+    pub indices: Vec<Vec<u8>>,
+    pub transparent_index: Option<u8>,
+}
+
+fn to_pixel([red, green, blue]: [u8; 3]) -> Pixel {
+    Pixel { red, green, blue }
+}
+
+// Squared euclidean distance in RGB space
+fn distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    let red = a[0].abs_diff(b[0]) as u32;
+    let green = a[1].abs_diff(b[1]) as u32;
+    let blue = a[2].abs_diff(b[2]) as u32;
+    red * red + green * green + blue * blue
+}
+
+/// Index of the entry of `color_table` closest to `color`, in RGB space.
+/// See `ColorLookup` for other color spaces, or to map many colors.
+pub fn nearest_color(color_table: &[Pixel], color: Pixel) -> u8 {
+    let color = [color.red, color.green, color.blue];
+    color_table
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, pixel)| distance(color, [pixel.red, pixel.green, pixel.blue]))
+        .map_or(0, |(i, _)| i as u8)
+}
+
+// Number of pixels of every distinct opaque color, and whether any pixel is transparent
+fn histogram<'a>(
+    frames: impl Iterator<Item = &'a [u8]>,
+    options: &QuantizeOptions,
+) -> (Vec<WeightedColor>, bool) {
+    let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+    let mut has_transparency = false;
+    let mut first_opaque = None;
+    for rgba in frames {
+        for pixel in rgba.chunks_exact(4) {
+            has_transparency |= pixel[3] < options.alpha_threshold;
+            if first_opaque.is_none() && pixel[3] >= options.alpha_threshold {
+                first_opaque = Some([pixel[0], pixel[1], pixel[2]]);
+            }
+        }
+        for pixel in rgba.chunks_exact(4).step_by(options.quality.sample_step()) {
+            if pixel[3] >= options.alpha_threshold {
+                *counts.entry([pixel[0], pixel[1], pixel[2]]).or_default() += 1;
+            }
+        }
+    }
+    // Sampling can miss every opaque pixel, which would then have no color to map to
+    if let (true, Some(color)) = (counts.is_empty(), first_opaque) {
+        counts.insert(color, 1);
+    }
+    let mut colors: Vec<WeightedColor> = counts
+        .into_iter()
+        .map(|(color, weight)| WeightedColor::new(color, weight, options.color_space))
+        .collect();
+    // HashMap order is random, keep the output deterministic
+    colors.sort_unstable_by_key(|color| color.color);
+    (colors, has_transparency)
+}
+
+fn build_palette(
+    colors: Vec<WeightedColor>,
+    max_colors: usize,
+    options: &QuantizeOptions,
+) -> Vec<[u8; 3]> {
+    if colors.len() <= max_colors {
+        return colors.into_iter().map(|color| color.color).collect();
+    }
+    match options.method {
+        QuantizeMethod::MedianCut => median_cut::palette(colors, max_colors, options.quality),
+        QuantizeMethod::Octree => octree::palette(&colors, max_colors),
+        QuantizeMethod::KMeans {
+            seed_with_median_cut,
+        } => {
+            let initial = if seed_with_median_cut {
+                median_cut::palette(colors.clone(), max_colors, options.quality)
+            } else {
+                kmeans::farthest_points(&colors, max_colors)
+            };
+            kmeans::refine(
+                &colors,
+                initial,
+                options.quality.kmeans_iterations(),
+                options.color_space,
+            )
+        }
+    }
+}
+
+// Maps every pixel to its closest color, remembering the colors already seen
+fn remap(
+    rgba: &[u8],
+    lookup: &ColorLookup,
+    transparent_index: Option<u8>,
+    alpha_threshold: u8,
+) -> Vec<u8> {
+    let mut cache: HashMap<[u8; 3], u8> = HashMap::new();
+    rgba.chunks_exact(4)
+        .map(|pixel| match transparent_index {
+            Some(transparent_index) if pixel[3] < alpha_threshold => transparent_index,
+            _ => {
+                let color = [pixel[0], pixel[1], pixel[2]];
+                *cache
+                    .entry(color)
+                    .or_insert_with(|| lookup.nearest(to_pixel(color)))
+            }
+        })
+        .collect()
+}
+
+/// Picks a single color table for all of `frames` (RGBA, 4 bytes per pixel)
+/// and maps each of them to it. Useful to build a Global Color Table.
+pub fn quantize_frames(frames: &[&[u8]], options: QuantizeOptions) -> QuantizedFrames {
+    let max_colors = options.max_colors.clamp(2, 256);
+    let (colors, has_transparency) = histogram(frames.iter().copied(), &options);
+    let opaque_colors = max_colors - has_transparency as usize;
+    let mut color_table: Vec<Pixel> = build_palette(colors, opaque_colors, &options)
+        .into_iter()
+        .map(to_pixel)
+        .collect();
+    // Built before the transparent entry is added, so that it can't be
+    // picked for an opaque pixel
+    let lookup = ColorLookup::new(&color_table, options.color_space);
+    let transparent_index = has_transparency.then(|| {
+        color_table.push(Pixel {
+            red: 0,
+            green: 0,
+            blue: 0,
+        });
+        (color_table.len() - 1) as u8
+    });
+    let indices = frames
+        .iter()
+        .map(|rgba| remap(rgba, &lookup, transparent_index, options.alpha_threshold))
+        .collect();
+    QuantizedFrames {
+        color_table,
+        indices,
+        transparent_index,
+    }
+}
+
+/// Reduces `rgba` (4 bytes per pixel) to at most `options.max_colors` colors.
+/// Trailing bytes that don't make up a whole pixel are ignored.
+pub fn quantize(rgba: &[u8], options: QuantizeOptions) -> QuantizedImage {
+    let mut quantized = quantize_frames(&[rgba], options);
+    QuantizedImage {
+        color_table: quantized.color_table,
+        indices: quantized.indices.pop().unwrap_or_default(),
+        transparent_index: quantized.transparent_index,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Smooth gradient with a lot more than 256 colors
+    fn gradient(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                [
+                    (x * 255 / width) as u8,
+                    (y * 255 / height) as u8,
+                    ((x + y) * 127 / (width + height)) as u8,
+                    255,
+                ]
+            })
+            .collect()
+    }
+
+    // Mean squared error between the original and the quantized pixels
+    fn error(rgba: &[u8], quantized: &QuantizedImage) -> f64 {
+        let total: u64 = rgba
+            .chunks_exact(4)
+            .zip(&quantized.indices)
+            .map(|(pixel, &index)| {
+                let entry = quantized.color_table[index as usize];
+                distance(
+                    [pixel[0], pixel[1], pixel[2]],
+                    [entry.red, entry.green, entry.blue],
+                ) as u64
+            })
+            .sum();
+        total as f64 / quantized.indices.len() as f64
+    }
+
+    const METHODS: [QuantizeMethod; 4] = [
+        QuantizeMethod::MedianCut,
+        QuantizeMethod::Octree,
+        QuantizeMethod::KMeans {
+            seed_with_median_cut: false,
+        },
+        QuantizeMethod::KMeans {
+            seed_with_median_cut: true,
+        },
+    ];
+
+    #[test]
+    fn quantize_few_colors() {
+        // Exact when the image has fewer colors than allowed
+        let rgba = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [255, 0, 0, 255],
+            [0, 0, 255, 255],
+        ]
+        .concat();
+        for method in METHODS {
+            let options = QuantizeOptions {
+                method,
+                ..Default::default()
+            };
+            let quantized = quantize(&rgba, options);
+            assert_eq!(quantized.color_table.len(), 3);
+            assert_eq!(quantized.transparent_index, None);
+            assert_eq!(error(&rgba, &quantized), 0.0);
+            assert_eq!(quantized.indices[0], quantized.indices[2]);
+        }
+    }
+
+    #[test]
+    fn quantize_gradient() {
+        let rgba = gradient(32, 32);
+        for method in METHODS {
+            for quality in [Quality::Fast, Quality::Balanced, Quality::Best] {
+                let errors: Vec<f64> = [2, 16, 256]
+                    .into_iter()
+                    .map(|max_colors| {
+                        let options = QuantizeOptions {
+                            method,
+                            max_colors,
+                            quality,
+                            ..Default::default()
+                        };
+                        let quantized = quantize(&rgba, options);
+                        assert!(quantized.color_table.len() <= max_colors, "{:?}", options);
+                        assert_eq!(quantized.indices.len(), 32 * 32);
+                        assert!(quantized
+                            .indices
+                            .iter()
+                            .all(|&i| (i as usize) < quantized.color_table.len()));
+                        error(&rgba, &quantized)
+                    })
+                    .collect();
+                // More colors, closer to the original
+                assert!(
+                    errors[0] > errors[1] && errors[1] > errors[2],
+                    "{:?}",
+                    errors
+                );
+                assert!(errors[2] < 200.0, "{:?} {:?}", method, quality);
+            }
+        }
+    }
+
+    #[test]
+    fn kmeans_improves_median_cut() {
+        let rgba = gradient(48, 48);
+        let options = |method| QuantizeOptions {
+            method,
+            max_colors: 16,
+            quality: Quality::Best,
+            ..Default::default()
+        };
+        let median_cut = quantize(&rgba, options(QuantizeMethod::MedianCut));
+        let kmeans = quantize(
+            &rgba,
+            options(QuantizeMethod::KMeans {
+                seed_with_median_cut: true,
+            }),
+        );
+        assert!(error(&rgba, &kmeans) <= error(&rgba, &median_cut));
+    }
+
+    #[test]
+    fn quantize_color_spaces() {
+        let rgba = gradient(32, 32);
+        // Error as seen in `color_space`, averaged over the pixels
+        let perceptual_error = |quantized: &QuantizedImage, color_space: ColorSpace| {
+            let total: f32 = rgba
+                .chunks_exact(4)
+                .zip(&quantized.indices)
+                .map(|(pixel, &index)| {
+                    let pixel = to_pixel([pixel[0], pixel[1], pixel[2]]);
+                    color_space.distance(pixel, quantized.color_table[index as usize])
+                })
+                .sum();
+            total / quantized.indices.len() as f32
+        };
+        for color_space in [ColorSpace::Oklab, ColorSpace::Lab] {
+            for method in METHODS {
+                let options = |color_space| QuantizeOptions {
+                    method,
+                    max_colors: 16,
+                    color_space,
+                    ..Default::default()
+                };
+                let rgb = quantize(&rgba, options(ColorSpace::Rgb));
+                let perceptual = quantize(&rgba, options(color_space));
+                assert_eq!(perceptual.indices.len(), 32 * 32);
+                assert!(perceptual.color_table.len() <= 16);
+                assert!(
+                    perceptual_error(&perceptual, color_space)
+                        <= perceptual_error(&rgb, color_space),
+                    "{:?} {:?}",
+                    color_space,
+                    method
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn quantize_transparency() {
+        let mut rgba = gradient(16, 16);
+        for pixel in rgba.chunks_exact_mut(4).step_by(3) {
+            pixel[3] = 0;
+        }
+        let options = QuantizeOptions {
+            max_colors: 8,
+            ..Default::default()
+        };
+        let quantized = quantize(&rgba, options);
+        assert_eq!(quantized.color_table.len(), 8);
+        assert_eq!(quantized.transparent_index, Some(7));
+        for (i, &index) in quantized.indices.iter().enumerate() {
+            assert_eq!(index == 7, i % 3 == 0);
+        }
+    }
+
+    #[test]
+    fn quantize_single_opaque_pixel() {
+        // Skipped by the sampling of `Quality::Fast`
+        let mut rgba = vec![0; 16 * 4];
+        rgba[5 * 4..6 * 4].copy_from_slice(&[10, 200, 30, 255]);
+        for method in [
+            QuantizeMethod::MedianCut,
+            QuantizeMethod::Octree,
+            QuantizeMethod::KMeans {
+                seed_with_median_cut: false,
+            },
+        ] {
+            let options = QuantizeOptions {
+                method,
+                quality: Quality::Fast,
+                ..Default::default()
+            };
+            let quantized = quantize(&rgba, options);
+            assert_eq!(
+                quantized.color_table[0],
+                Pixel {
+                    red: 10,
+                    green: 200,
+                    blue: 30
+                }
+            );
+            assert_eq!(quantized.transparent_index, Some(1));
+            for (i, &index) in quantized.indices.iter().enumerate() {
+                assert_eq!(index, if i == 5 { 0 } else { 1 });
+            }
+        }
+    }
+
+    #[test]
+    fn quantize_shared_color_table() {
+        let red = [255, 0, 0, 255].repeat(10);
+        let blue = [0, 0, 255, 255].repeat(5);
+        let quantized = quantize_frames(&[&red, &blue], QuantizeOptions::default());
+        assert_eq!(quantized.color_table.len(), 2);
+        assert_eq!(quantized.indices[0], vec![1; 10]);
+        assert_eq!(quantized.indices[1], vec![0; 5]);
+
+        assert_eq!(
+            quantize(&[], QuantizeOptions::default()).indices,
+            Vec::<u8>::new()
+        );
+    }
+}
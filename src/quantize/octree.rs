@@ -0,0 +1,158 @@
+use super::WeightedColor;
+
+// Leaves at this depth hold a single exact color
+const MAX_DEPTH: usize = 8;
+const NO_CHILD: usize = usize::MAX;
+
+#[derive(Debug, Clone)]
+struct Node {
+    children: [usize; 8],
+    // Sums of the channels of every pixel below this node
+    sums: [u64; 3],
+    weight: u64,
+    is_leaf: bool,
+}
+
+impl Node {
+    fn new(is_leaf: bool) -> Self {
+        Node {
+            children: [NO_CHILD; 8],
+            sums: [0; 3],
+            weight: 0,
+            is_leaf,
+        }
+    }
+
+    fn mean(&self) -> [u8; 3] {
+        let weight = self.weight.max(1);
+        self.sums.map(|sum| ((sum + weight / 2) / weight) as u8)
+    }
+}
+
+// Branch taken at `depth`, one bit of each channel
+fn child_index(color: [u8; 3], depth: usize) -> usize {
+    let shift = 7 - depth;
+    (((color[0] >> shift) & 1) << 2 | ((color[1] >> shift) & 1) << 1 | ((color[2] >> shift) & 1))
+        as usize
+}
+
+struct Octree {
+    // Nodes refer to their children by index, the root is the first one
+    nodes: Vec<Node>,
+    // Nodes that have children, by depth
+    reducible: Vec<Vec<usize>>,
+    leaves: usize,
+}
+
+impl Octree {
+    fn new() -> Self {
+        let mut reducible = vec![Vec::new(); MAX_DEPTH];
+        reducible[0].push(0);
+        Octree {
+            nodes: vec![Node::new(false)],
+            reducible,
+            leaves: 0,
+        }
+    }
+
+    fn insert(&mut self, color: WeightedColor) {
+        let mut node = 0;
+        for depth in 0..=MAX_DEPTH {
+            let weight = color.weight as u64;
+            let current = &mut self.nodes[node];
+            current.weight += weight;
+            for (sum, &value) in current.sums.iter_mut().zip(&color.color) {
+                *sum += value as u64 * weight;
+            }
+            if current.is_leaf {
+                return;
+            }
+            let child = child_index(color.color, depth);
+            if current.children[child] == NO_CHILD {
+                let is_leaf = depth + 1 == MAX_DEPTH;
+                let len = self.nodes.len();
+                self.nodes[node].children[child] = len;
+                self.nodes.push(Node::new(is_leaf));
+                if is_leaf {
+                    self.leaves += 1;
+                } else {
+                    self.reducible[depth + 1].push(self.nodes.len() - 1);
+                }
+            }
+            node = self.nodes[node].children[child];
+        }
+    }
+
+    // Turns the least used node of the deepest level into a leaf
+    fn reduce(&mut self) -> bool {
+        let Some(depth) = (0..MAX_DEPTH)
+            .rev()
+            .find(|&d| !self.reducible[d].is_empty())
+        else {
+            return false;
+        };
+        let nodes = &self.nodes;
+        let (position, _) = self.reducible[depth]
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, &node)| nodes[node].weight)
+            .unwrap();
+        let node = self.reducible[depth].swap_remove(position);
+        let children = self.nodes[node]
+            .children
+            .iter()
+            .filter(|&&child| child != NO_CHILD)
+            .count();
+        // Sums and weights already include every child
+        self.nodes[node].is_leaf = true;
+        self.nodes[node].children = [NO_CHILD; 8];
+        self.leaves = self.leaves + 1 - children;
+        true
+    }
+
+    fn colors(&self) -> Vec<[u8; 3]> {
+        let mut ret = Vec::new();
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if node.is_leaf {
+                ret.push(node.mean());
+            } else {
+                stack.extend(
+                    node.children
+                        .iter()
+                        .rev()
+                        .filter(|&&child| child != NO_CHILD),
+                );
+            }
+        }
+        ret
+    }
+}
+
+/// Octree palette of at most `max_colors` colors. The tree is always split
+/// on RGB bits, whatever the color space.
+pub(super) fn palette(colors: &[WeightedColor], max_colors: usize) -> Vec<[u8; 3]> {
+    let mut octree = Octree::new();
+    for &color in colors {
+        octree.insert(color);
+    }
+    while octree.leaves > max_colors && octree.reduce() {}
+    octree.colors()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quantize::ColorSpace;
+
+    #[test]
+    fn reduce_octree() {
+        let colors = [[0, 0, 0], [1, 0, 0], [255, 255, 255], [254, 255, 255]]
+            .map(|color| WeightedColor::new(color, 1, ColorSpace::Rgb));
+        assert_eq!(palette(&colors, 4).len(), 4);
+        // The two nearly identical pairs are merged first
+        assert_eq!(palette(&colors, 2), vec![[1, 0, 0], [255, 255, 255]]);
+        assert_eq!(palette(&colors, 1).len(), 1);
+    }
+}
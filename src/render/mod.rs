@@ -0,0 +1,428 @@
+mod palette;
+pub use palette::*;
+
+use crate::decoder::{DecodeLimits, DisposalMethod, GifFile, ImageDescriptor, LimitKind};
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct RenderOptions {
+    pub color_policy: ColorPolicy,
+    // Only `max_canvas_pixels` matters here. The canvas is the Logical Screen,
+    // as resized by the `CanvasMode` when decoding.
+    pub limits: DecodeLimits,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RenderError {
+    ColorIndexOutOfRange {
+        frame: usize,
+        index: u8,
+        color_table_len: usize,
+    },
+    // The canvas is bigger than `DecodeLimits::max_canvas_pixels`
+    LimitExceeded {
+        limit: LimitKind,
+        max: u64,
+        actual: u64,
+    },
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderError::ColorIndexOutOfRange {
+                frame,
+                index,
+                color_table_len,
+            } => write!(
+                f,
+                "Frame {} uses color index {} but its color table only has {} colors!",
+                frame, index, color_table_len
+            ),
+            RenderError::LimitExceeded { limit, max, actual } => write!(
+                f,
+                "Render limit exceeded for {:?}! Max is {} and found {}!",
+                limit, max, actual
+            ),
+        }
+    }
+}
+
+/// A fully composited canvas, 4 bytes (RGBA) per pixel.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RgbaFrame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    // In hundredths of a second
+    pub delay_timer: u16,
+}
+
+/// For every row in the (interlaced) stream, the row of the image it belongs to.
+pub fn interlaced_rows(height: usize) -> Vec<usize> {
+    // (first row, step) of each of the 4 passes
+    const PASSES: [(usize, usize); 4] = [(0, 8), (4, 8), (2, 4), (1, 2)];
+    PASSES
+        .iter()
+        .flat_map(|&(start, step)| (start..height).step_by(step))
+        .collect()
+}
+
+// Part of the canvas covered by a frame, already clipped to the canvas
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    left: usize,
+    top: usize,
+    right: usize,
+    bottom: usize,
+}
+
+impl Rect {
+    fn clipped(image_descriptor: &ImageDescriptor, width: usize, height: usize) -> Rect {
+        let left = (image_descriptor.left as usize).min(width);
+        let top = (image_descriptor.top as usize).min(height);
+        Rect {
+            left,
+            top,
+            right: (left + image_descriptor.width as usize).min(width),
+            bottom: (top + image_descriptor.height as usize).min(height),
+        }
+    }
+}
+
+/// Draws the frames of a `GifFile` on top of each other, handling transparency,
+/// interlacing and disposal methods. Every item is the canvas after drawing a frame.
+pub struct Compositor<'a> {
+    gif_file: &'a GifFile,
+    options: RenderOptions,
+    width: usize,
+    height: usize,
+    canvas: Vec<u8>,
+    // Canvas to go back to for `DisposalMethod::RestoreToPrevious`
+    previous: Option<Vec<u8>>,
+    // Disposal of the last drawn frame, done right before drawing the next one
+    pending_disposal: Option<(DisposalMethod, Rect)>,
+    next_frame: usize,
+}
+
+impl<'a> Compositor<'a> {
+    /// Fails instead of allocating a canvas bigger than the limits allow.
+    pub fn new(gif_file: &'a GifFile, options: RenderOptions) -> Result<Self, RenderError> {
+        let lsd = &gif_file.logical_screen_descriptor;
+        let pixels = lsd.canvas_width as u64 * lsd.canvas_height as u64;
+        if let Some(max) = options.limits.max_canvas_pixels.filter(|&max| pixels > max) {
+            return Err(RenderError::LimitExceeded {
+                limit: LimitKind::CanvasPixels,
+                max,
+                actual: pixels,
+            });
+        }
+        let width = lsd.canvas_width as usize;
+        let height = lsd.canvas_height as usize;
+        Ok(Compositor {
+            gif_file,
+            options,
+            width,
+            height,
+            // Like browsers, the background is transparent rather than
+            // the background color.
+            canvas: vec![0; width * height * 4],
+            previous: None,
+            pending_disposal: None,
+            next_frame: 0,
+        })
+    }
+
+    fn dispose(&mut self) {
+        match self.pending_disposal.take() {
+            Some((DisposalMethod::RestoreToBackground, rect)) => {
+                for y in rect.top..rect.bottom {
+                    let start = (y * self.width + rect.left) * 4;
+                    let end = (y * self.width + rect.right) * 4;
+                    self.canvas[start..end].fill(0);
+                }
+            }
+            Some((DisposalMethod::RestoreToPrevious, _)) => {
+                if let Some(previous) = self.previous.take() {
+                    self.canvas = previous;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn draw(&mut self, frame_number: usize) -> Result<RgbaFrame, RenderError> {
+        let frame = &self.gif_file.frames[frame_number];
+        let image_descriptor = &frame.image_descriptor;
+        let color_table = active_color_table(self.gif_file, frame);
+        let transparent_color_index = frame.transparent_color_index();
+        let disposal_method = frame.disposal_method();
+
+        self.dispose();
+        if disposal_method == DisposalMethod::RestoreToPrevious {
+            self.previous = Some(self.canvas.clone());
+        }
+
+        let frame_width = image_descriptor.width as usize;
+        let frame_height = image_descriptor.height as usize;
+        let rows: Vec<usize> = if image_descriptor.interlace_flag {
+            interlaced_rows(frame_height)
+        } else {
+            (0..frame_height).collect()
+        };
+        // Indices past the end of a short stream are left transparent,
+        // any extra indices are ignored.
+        let indices = frame.frame_indices.chunks(frame_width.max(1));
+        for (row, indices) in rows.into_iter().zip(indices) {
+            let y = image_descriptor.top as usize + row;
+            if y >= self.height {
+                continue;
+            }
+            for (column, &index) in indices.iter().enumerate() {
+                let x = image_descriptor.left as usize + column;
+                if x >= self.width || Some(index) == transparent_color_index {
+                    continue;
+                }
+                let pixel = match resolve_color(color_table, index, self.options.color_policy) {
+                    ResolvedColor::Color(pixel) => pixel,
+                    ResolvedColor::Transparent => continue,
+                    ResolvedColor::OutOfRange => {
+                        return Err(RenderError::ColorIndexOutOfRange {
+                            frame: frame_number,
+                            index,
+                            color_table_len: color_table.len(),
+                        })
+                    }
+                };
+                let offset = (y * self.width + x) * 4;
+                self.canvas[offset..offset + 4].copy_from_slice(&[
+                    pixel.red,
+                    pixel.green,
+                    pixel.blue,
+                    0xFF,
+                ]);
+            }
+        }
+
+        self.pending_disposal = Some((
+            disposal_method,
+            Rect::clipped(image_descriptor, self.width, self.height),
+        ));
+        Ok(RgbaFrame {
+            width: self.width,
+            height: self.height,
+            pixels: self.canvas.clone(),
+            delay_timer: frame.delay_timer(),
+        })
+    }
+}
+
+impl Iterator for Compositor<'_> {
+    type Item = Result<RgbaFrame, RenderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_frame >= self.gif_file.frames.len() {
+            return None;
+        }
+        self.next_frame += 1;
+        Some(self.draw(self.next_frame - 1))
+    }
+}
+
+/// Composites every frame of `gif_file`.
+pub fn render(gif_file: &GifFile, options: RenderOptions) -> Result<Vec<RgbaFrame>, RenderError> {
+    Compositor::new(gif_file, options)?.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{
+        CanvasMode, DecodeOptions, Extension, GifFrame, GifHeader, LogicalScreenDescriptor, Pixel,
+    };
+
+    const RED: Pixel = Pixel {
+        red: 0xFF,
+        green: 0,
+        blue: 0,
+    };
+    const GREEN: Pixel = Pixel {
+        red: 0,
+        green: 0xFF,
+        blue: 0,
+    };
+
+    fn frame(
+        left: u16,
+        width: u16,
+        frame_indices: Vec<u8>,
+        disposal_method: DisposalMethod,
+    ) -> GifFrame {
+        GifFrame {
+            image_descriptor: ImageDescriptor {
+                left,
+                top: 0,
+                width,
+                height: 1,
+                local_color_table_flag: false,
+                interlace_flag: false,
+                sort_flag: false,
+                reserved: 0,
+                local_color_table_size: 0,
+            },
+            local_color_table: None,
+            frame_indices,
+            extensions: vec![Extension::GraphicsControlExtension {
+                reserved: 0,
+                disposal_method,
+                user_input_flag: false,
+                transparent_color_flag: false,
+                delay_timer: 10,
+                transparent_color_index: 0,
+            }],
+        }
+    }
+
+    fn gif_file(global_color_table: Option<Vec<Pixel>>, frames: Vec<GifFrame>) -> GifFile {
+        GifFile {
+            header: GifHeader::GIF89a,
+            logical_screen_descriptor: LogicalScreenDescriptor {
+                canvas_width: 2,
+                canvas_height: 1,
+                global_color_table_flag: global_color_table.is_some(),
+                color_resolution: 0,
+                sort_flag: false,
+                global_color_table_size: 0,
+                background_color_index: 0,
+                pixel_aspect_ratio: 0,
+            },
+            global_color_table,
+            frames,
+            trailing_extensions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn interlaced_row_order() {
+        assert_eq!(interlaced_rows(8), vec![0, 4, 2, 6, 1, 3, 5, 7]);
+        assert_eq!(interlaced_rows(3), vec![0, 2, 1]);
+    }
+
+    #[test]
+    fn out_of_range_color_index() {
+        let gif_file = gif_file(
+            Some(vec![RED, GREEN]),
+            vec![frame(0, 2, vec![0, 5], DisposalMethod::NoDisposal)],
+        );
+        let render_with = |color_policy| {
+            let options = RenderOptions {
+                color_policy,
+                ..RenderOptions::default()
+            };
+            render(&gif_file, options).map(|frames| frames[0].pixels.clone())
+        };
+        assert_eq!(
+            render_with(ColorPolicy::Error),
+            Err(RenderError::ColorIndexOutOfRange {
+                frame: 0,
+                index: 5,
+                color_table_len: 2
+            })
+        );
+        assert_eq!(
+            render_with(ColorPolicy::Transparent),
+            Ok(vec![0xFF, 0, 0, 0xFF, 0, 0, 0, 0])
+        );
+        assert_eq!(
+            render_with(ColorPolicy::Black),
+            Ok(vec![0xFF, 0, 0, 0xFF, 0, 0, 0, 0xFF])
+        );
+        assert_eq!(
+            render_with(ColorPolicy::Clamp),
+            Ok(vec![0xFF, 0, 0, 0xFF, 0, 0xFF, 0, 0xFF])
+        );
+    }
+
+    #[test]
+    fn missing_color_table() {
+        let gif_file = gif_file(
+            None,
+            vec![frame(0, 2, vec![0, 1], DisposalMethod::NoDisposal)],
+        );
+        let frames = render(&gif_file, RenderOptions::default()).unwrap();
+        assert_eq!(
+            frames[0].pixels,
+            vec![0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+    }
+
+    #[test]
+    fn disposal_methods() {
+        let gif_file = gif_file(
+            Some(vec![RED, GREEN]),
+            vec![
+                frame(0, 2, vec![0, 0], DisposalMethod::RestoreToBackground),
+                frame(1, 1, vec![1], DisposalMethod::RestoreToPrevious),
+                frame(0, 1, vec![1], DisposalMethod::NoDisposal),
+            ],
+        );
+        let frames = render(&gif_file, RenderOptions::default()).unwrap();
+        assert_eq!(frames[0].pixels, vec![0xFF, 0, 0, 0xFF, 0xFF, 0, 0, 0xFF]);
+        assert_eq!(frames[1].pixels, vec![0, 0, 0, 0, 0, 0xFF, 0, 0xFF]);
+        assert_eq!(frames[2].pixels, vec![0, 0xFF, 0, 0xFF, 0, 0, 0, 0]);
+        assert_eq!(frames[2].delay_timer, 10);
+    }
+
+    #[test]
+    fn frames_outside_canvas() {
+        let gif_file = gif_file(
+            Some(vec![RED, GREEN]),
+            vec![frame(1, 3, vec![0, 1, 0], DisposalMethod::NoDisposal)],
+        );
+        let frames = render(&gif_file, RenderOptions::default()).unwrap();
+        assert_eq!(frames[0].pixels, vec![0, 0, 0, 0, 0xFF, 0, 0, 0xFF]);
+
+        // The canvas comes from the Logical Screen as fitted by the decoder
+        let options = DecodeOptions {
+            canvas_mode: CanvasMode::GrowToFit,
+            ..DecodeOptions::default()
+        };
+        let (gif_file, _) = GifFile::decode(&gif_file.to_bytes(), &options).unwrap();
+        let frames = render(&gif_file, RenderOptions::default()).unwrap();
+        assert_eq!((frames[0].width, frames[0].height), (4, 1));
+        assert_eq!(
+            frames[0].pixels,
+            vec![0, 0, 0, 0, 0xFF, 0, 0, 0xFF, 0, 0xFF, 0, 0xFF, 0xFF, 0, 0, 0xFF]
+        );
+    }
+
+    #[test]
+    fn canvas_limit() {
+        let mut gif_file = gif_file(
+            Some(vec![RED, GREEN]),
+            vec![frame(0, 2, vec![0, 1], DisposalMethod::NoDisposal)],
+        );
+        gif_file.logical_screen_descriptor.canvas_width = u16::MAX;
+        gif_file.logical_screen_descriptor.canvas_height = u16::MAX;
+        assert_eq!(
+            Compositor::new(&gif_file, RenderOptions::default()).err(),
+            Some(RenderError::LimitExceeded {
+                limit: LimitKind::CanvasPixels,
+                max: 1 << 26,
+                actual: u16::MAX as u64 * u16::MAX as u64,
+            })
+        );
+
+        gif_file.logical_screen_descriptor.canvas_width = 2;
+        gif_file.logical_screen_descriptor.canvas_height = 1;
+        let options = RenderOptions {
+            limits: DecodeLimits {
+                max_canvas_pixels: Some(1),
+                ..DecodeLimits::none()
+            },
+            ..RenderOptions::default()
+        };
+        assert!(render(&gif_file, options).is_err());
+        assert!(render(&gif_file, RenderOptions::default()).is_ok());
+    }
+}
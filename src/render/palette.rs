@@ -0,0 +1,78 @@
+use crate::decoder::{GifFile, GifFrame, Pixel};
+
+/// What to do with a color index that is beyond the end of the active color table.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum ColorPolicy {
+    // Fail rendering with `RenderError::ColorIndexOutOfRange`
+    Error,
+    // Leave the canvas pixel untouched, as if it was the transparent index
+    #[default]
+    Transparent,
+    Black,
+    // Use the last entry of the color table
+    Clamp,
+}
+
+const fn create_default_color_table() -> [Pixel; 256] {
+    let mut ret = [Pixel {
+        red: 0,
+        green: 0,
+        blue: 0,
+    }; 256];
+    let mut i = 1;
+    while i < 256 {
+        ret[i] = Pixel {
+            red: i as u8,
+            green: i as u8,
+            blue: i as u8,
+        };
+        i += 1;
+    }
+    // The spec recommends black and white as the first two entries
+    // so that monochrome images still show up properly.
+    ret[1] = Pixel {
+        red: 0xFF,
+        green: 0xFF,
+        blue: 0xFF,
+    };
+    ret
+}
+
+/// Used when a frame has neither a Local nor a Global Color Table.
+pub static DEFAULT_COLOR_TABLE: [Pixel; 256] = create_default_color_table();
+
+/// The color table a frame should be drawn with: its Local Color Table, otherwise
+/// the Global Color Table, otherwise `DEFAULT_COLOR_TABLE`.
+pub fn active_color_table<'a>(gif_file: &'a GifFile, frame: &'a GifFrame) -> &'a [Pixel] {
+    match (&frame.local_color_table, &gif_file.global_color_table) {
+        (Some(lct), _) => lct,
+        (None, Some(gct)) => gct,
+        (None, None) => &DEFAULT_COLOR_TABLE,
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ResolvedColor {
+    Color(Pixel),
+    // Leave the canvas pixel untouched
+    Transparent,
+    // Out of range and the policy is `ColorPolicy::Error`
+    OutOfRange,
+}
+
+/// Looks up `index` in `color_table`, applying `policy` if it is out of range.
+pub fn resolve_color(color_table: &[Pixel], index: u8, policy: ColorPolicy) -> ResolvedColor {
+    if let Some(pixel) = color_table.get(index as usize) {
+        return ResolvedColor::Color(*pixel);
+    }
+    match (policy, color_table.last()) {
+        (ColorPolicy::Error, _) => ResolvedColor::OutOfRange,
+        (ColorPolicy::Transparent, _) | (ColorPolicy::Clamp, None) => ResolvedColor::Transparent,
+        (ColorPolicy::Black, _) => ResolvedColor::Color(Pixel {
+            red: 0,
+            green: 0,
+            blue: 0,
+        }),
+        (ColorPolicy::Clamp, Some(pixel)) => ResolvedColor::Color(*pixel),
+    }
+}
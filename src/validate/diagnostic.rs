@@ -0,0 +1,162 @@
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Severity {
+    // Not wrong, but could be done better
+    Info,
+    // Against the spec, but decoders can work around it
+    Warning,
+    // The image can not be displayed properly
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum DiagnosticKind {
+    // The file could not be decoded at all
+    ParseError(String),
+    ZeroSizedCanvas,
+    // The header says GIF87a, but GIF89a features are used
+    VersionMismatch,
+    ReservedBitsSet {
+        block: &'static str,
+        value: u8,
+    },
+    BackgroundColorOutOfRange {
+        index: u8,
+        color_table_len: usize,
+    },
+    TransparentColorOutOfRange {
+        index: u8,
+        color_table_len: usize,
+    },
+    // Disposal methods 4 to 7 are not defined by the spec
+    InvalidDisposalMethod(u8),
+    FrameOutsideCanvas,
+    MissingColorTable,
+    DataAfterEndOfInformation,
+    // Compared to width * height of the Image Descriptor
+    PixelStreamTooShort {
+        expected: usize,
+        actual: usize,
+    },
+    PixelStreamTooLong {
+        expected: usize,
+        actual: usize,
+    },
+    // `index` is the biggest index that is out of range
+    ColorIndexOutOfRange {
+        index: u8,
+        color_table_len: usize,
+    },
+    UnusedColors {
+        unused: usize,
+        color_table_len: usize,
+    },
+}
+
+impl DiagnosticKind {
+    pub fn severity(&self) -> Severity {
+        use DiagnosticKind::*;
+        match self {
+            ParseError(_) | PixelStreamTooShort { .. } | ColorIndexOutOfRange { .. } => {
+                Severity::Error
+            }
+            UnusedColors { .. } => Severity::Info,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use DiagnosticKind::*;
+        match self {
+            ParseError(err) => write!(f, "{}", err),
+            ZeroSizedCanvas => write!(f, "Logical Screen has a width or height of 0"),
+            VersionMismatch => write!(f, "GIF87a header but GIF89a features are used"),
+            ReservedBitsSet { block, value } => {
+                write!(f, "Reserved bits of the {} are set to {:#b}", block, value)
+            }
+            BackgroundColorOutOfRange {
+                index,
+                color_table_len,
+            } => write!(
+                f,
+                "Background color index {} is outside the Global Color Table ({} colors)",
+                index, color_table_len
+            ),
+            TransparentColorOutOfRange {
+                index,
+                color_table_len,
+            } => write!(
+                f,
+                "Transparent color index {} is outside the color table ({} colors)",
+                index, color_table_len
+            ),
+            InvalidDisposalMethod(value) => write!(f, "Undefined disposal method {}", value),
+            FrameOutsideCanvas => write!(f, "Image Descriptor extends past the canvas"),
+            MissingColorTable => write!(f, "Neither a Local nor a Global Color Table"),
+            DataAfterEndOfInformation => {
+                write!(f, "Image data continues after the End Of Information Code")
+            }
+            PixelStreamTooShort { expected, actual } => write!(
+                f,
+                "Only {} of {} pixels are in the image data",
+                actual, expected
+            ),
+            PixelStreamTooLong { expected, actual } => write!(
+                f,
+                "Image data has {} pixels but only {} are expected",
+                actual, expected
+            ),
+            ColorIndexOutOfRange {
+                index,
+                color_table_len,
+            } => write!(
+                f,
+                "Color index {} is outside the color table ({} colors)",
+                index, color_table_len
+            ),
+            UnusedColors {
+                unused,
+                color_table_len,
+            } => write!(
+                f,
+                "{} of {} color table entries are never used",
+                unused, color_table_len
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    // `None` for problems with the file as a whole
+    pub frame: Option<usize>,
+    pub kind: DiagnosticKind,
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.frame {
+            Some(frame) => write!(f, "{}: frame {}: {}", self.severity(), frame, self.kind),
+            None => write!(f, "{}: {}", self.severity(), self.kind),
+        }
+    }
+}
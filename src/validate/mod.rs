@@ -0,0 +1,385 @@
+mod diagnostic;
+pub use diagnostic::*;
+
+use crate::decoder::{
+    DecodeOptions, DecodeWarning, DisposalMethod, Extension, GifFile, GifFrame, GifHeader, Pixel,
+};
+
+fn check_frame(gif_file: &GifFile, frame: &GifFrame) -> Vec<DiagnosticKind> {
+    use DiagnosticKind::*;
+    let mut ret = Vec::new();
+    let lsd = &gif_file.logical_screen_descriptor;
+    let id = &frame.image_descriptor;
+    let color_table: Option<&[Pixel]> = frame
+        .local_color_table
+        .as_deref()
+        .or(gif_file.global_color_table.as_deref());
+
+    if id.reserved != 0 {
+        ret.push(ReservedBitsSet {
+            block: "Image Descriptor",
+            value: id.reserved,
+        });
+    }
+    for extension in &frame.extensions {
+        if let Extension::GraphicsControlExtension {
+            reserved,
+            disposal_method,
+            transparent_color_flag,
+            transparent_color_index,
+            ..
+        } = extension
+        {
+            if *reserved != 0 {
+                ret.push(ReservedBitsSet {
+                    block: "Graphics Control Extension",
+                    value: *reserved,
+                });
+            }
+            if let DisposalMethod::Undefined(value) = disposal_method {
+                ret.push(InvalidDisposalMethod(*value));
+            }
+            match color_table {
+                Some(color_table)
+                    if *transparent_color_flag
+                        && *transparent_color_index as usize >= color_table.len() =>
+                {
+                    ret.push(TransparentColorOutOfRange {
+                        index: *transparent_color_index,
+                        color_table_len: color_table.len(),
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if id.left as usize + id.width as usize > lsd.canvas_width as usize
+        || id.top as usize + id.height as usize > lsd.canvas_height as usize
+    {
+        ret.push(FrameOutsideCanvas);
+    }
+
+    let expected = id.width as usize * id.height as usize;
+    let actual = frame.frame_indices.len();
+    if actual < expected {
+        ret.push(PixelStreamTooShort { expected, actual });
+    } else if actual > expected {
+        ret.push(PixelStreamTooLong { expected, actual });
+    }
+
+    match color_table {
+        None => ret.push(MissingColorTable),
+        Some(color_table) => {
+            let max_index = frame.frame_indices.iter().max();
+            match max_index {
+                Some(&index) if index as usize >= color_table.len() => {
+                    ret.push(ColorIndexOutOfRange {
+                        index,
+                        color_table_len: color_table.len(),
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+    ret
+}
+
+// Number of entries in `color_table` that none of `frames` use
+fn unused_colors<'a>(color_table: &[Pixel], frames: impl Iterator<Item = &'a GifFrame>) -> usize {
+    let mut used = [false; 256];
+    for frame in frames {
+        for &index in &frame.frame_indices {
+            used[index as usize] = true;
+        }
+    }
+    used[..color_table.len().min(256)]
+        .iter()
+        .filter(|used| !**used)
+        .count()
+}
+
+/// Checks a decoded file against the spec, including everything the decoder
+/// lets slide. Some problems (like data after the End Of Information Code) can
+/// only be found in the raw file, see `validate_bytes`.
+pub fn validate(gif_file: &GifFile) -> Vec<Diagnostic> {
+    let mut ret = Vec::new();
+    let lsd = &gif_file.logical_screen_descriptor;
+    let mut push = |frame, kind| ret.push(Diagnostic { frame, kind });
+
+    if lsd.canvas_width == 0 || lsd.canvas_height == 0 {
+        push(None, DiagnosticKind::ZeroSizedCanvas);
+    }
+    if gif_file.header == GifHeader::GIF87a && gif_file.required_version() == GifHeader::GIF89a {
+        push(None, DiagnosticKind::VersionMismatch);
+    }
+    if let Some(gct) = &gif_file.global_color_table {
+        if lsd.background_color_index as usize >= gct.len() {
+            push(
+                None,
+                DiagnosticKind::BackgroundColorOutOfRange {
+                    index: lsd.background_color_index,
+                    color_table_len: gct.len(),
+                },
+            );
+        }
+    }
+
+    for (i, frame) in gif_file.frames.iter().enumerate() {
+        for kind in check_frame(gif_file, frame) {
+            push(Some(i), kind);
+        }
+    }
+
+    if let Some(gct) = &gif_file.global_color_table {
+        let frames = gif_file
+            .frames
+            .iter()
+            .filter(|frame| frame.local_color_table.is_none());
+        let unused = unused_colors(gct, frames);
+        if unused > 0 {
+            push(
+                None,
+                DiagnosticKind::UnusedColors {
+                    unused,
+                    color_table_len: gct.len(),
+                },
+            );
+        }
+    }
+    for (i, frame) in gif_file.frames.iter().enumerate() {
+        if let Some(lct) = &frame.local_color_table {
+            let unused = unused_colors(lct, std::iter::once(frame));
+            if unused > 0 {
+                push(
+                    Some(i),
+                    DiagnosticKind::UnusedColors {
+                        unused,
+                        color_table_len: lct.len(),
+                    },
+                );
+            }
+        }
+    }
+    ret
+}
+
+/// Decodes and validates a file, reporting decoding failures as diagnostics too.
+pub fn validate_bytes(bytes: &[u8]) -> Vec<Diagnostic> {
+    let (gif_file, warnings) = match GifFile::decode(bytes, &DecodeOptions::default()) {
+        Ok(ret) => ret,
+        Err(err) => {
+            return vec![Diagnostic {
+                frame: None,
+                kind: DiagnosticKind::ParseError(err.to_string()),
+            }]
+        }
+    };
+    let mut ret = validate(&gif_file);
+    // The rest of the warnings are found by `validate` as well
+    for warning in warnings {
+        match warning {
+            DecodeWarning::DataAfterEndOfInformation(frame) => ret.push(Diagnostic {
+                frame: Some(frame),
+                kind: DiagnosticKind::DataAfterEndOfInformation,
+            }),
+            // The extra indices were dropped, the decoded frame looks fine
+            DecodeWarning::PixelStreamTooLong {
+                frame,
+                expected,
+                actual,
+            } => ret.push(Diagnostic {
+                frame: Some(frame),
+                kind: DiagnosticKind::PixelStreamTooLong { expected, actual },
+            }),
+            _ => {}
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{ImageDescriptor, LogicalScreenDescriptor};
+    use DiagnosticKind::*;
+
+    fn gif_file() -> GifFile {
+        GifFile {
+            header: GifHeader::GIF89a,
+            logical_screen_descriptor: LogicalScreenDescriptor {
+                canvas_width: 4,
+                canvas_height: 1,
+                global_color_table_flag: true,
+                color_resolution: 0,
+                sort_flag: false,
+                global_color_table_size: 1,
+                background_color_index: 0,
+                pixel_aspect_ratio: 0,
+            },
+            global_color_table: Some(vec![
+                Pixel {
+                    red: 0,
+                    green: 0,
+                    blue: 0
+                };
+                4
+            ]),
+            frames: vec![GifFrame {
+                image_descriptor: ImageDescriptor {
+                    left: 0,
+                    top: 0,
+                    width: 4,
+                    height: 1,
+                    local_color_table_flag: false,
+                    interlace_flag: false,
+                    sort_flag: false,
+                    reserved: 0,
+                    local_color_table_size: 0,
+                },
+                local_color_table: None,
+                frame_indices: vec![0, 1, 2, 3],
+                extensions: vec![Extension::GraphicsControlExtension {
+                    reserved: 0,
+                    disposal_method: DisposalMethod::NoDisposal,
+                    user_input_flag: false,
+                    transparent_color_flag: true,
+                    delay_timer: 0,
+                    transparent_color_index: 3,
+                }],
+            }],
+            trailing_extensions: Vec::new(),
+        }
+    }
+
+    fn kinds(diagnostics: Vec<Diagnostic>) -> Vec<DiagnosticKind> {
+        diagnostics.into_iter().map(|d| d.kind).collect()
+    }
+
+    #[test]
+    fn valid_file() {
+        assert_eq!(validate(&gif_file()), vec![]);
+        assert_eq!(validate_bytes(&gif_file().to_bytes()), vec![]);
+    }
+
+    #[test]
+    fn invalid_fields() {
+        let mut gif_file = gif_file();
+        gif_file.header = GifHeader::GIF87a;
+        gif_file.logical_screen_descriptor.background_color_index = 9;
+        let frame = &mut gif_file.frames[0];
+        frame.image_descriptor.reserved = 0b10;
+        frame.image_descriptor.left = 1;
+        frame.extensions = vec![Extension::GraphicsControlExtension {
+            reserved: 0b101,
+            disposal_method: DisposalMethod::Undefined(6),
+            user_input_flag: false,
+            transparent_color_flag: true,
+            delay_timer: 0,
+            transparent_color_index: 4,
+        }];
+        assert_eq!(
+            kinds(validate(&gif_file)),
+            vec![
+                VersionMismatch,
+                BackgroundColorOutOfRange {
+                    index: 9,
+                    color_table_len: 4
+                },
+                ReservedBitsSet {
+                    block: "Image Descriptor",
+                    value: 0b10
+                },
+                ReservedBitsSet {
+                    block: "Graphics Control Extension",
+                    value: 0b101
+                },
+                InvalidDisposalMethod(6),
+                TransparentColorOutOfRange {
+                    index: 4,
+                    color_table_len: 4
+                },
+                FrameOutsideCanvas,
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_pixel_streams() {
+        let mut gif_file = gif_file();
+        gif_file.frames[0].frame_indices = vec![0, 7];
+        let diagnostics = validate(&gif_file);
+        assert_eq!(
+            kinds(diagnostics.clone()),
+            vec![
+                PixelStreamTooShort {
+                    expected: 4,
+                    actual: 2
+                },
+                ColorIndexOutOfRange {
+                    index: 7,
+                    color_table_len: 4
+                },
+                UnusedColors {
+                    unused: 3,
+                    color_table_len: 4
+                },
+            ]
+        );
+        assert_eq!(diagnostics[0].severity(), Severity::Error);
+        assert_eq!(diagnostics[2].severity(), Severity::Info);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error: frame 0: Only 2 of 4 pixels are in the image data"
+        );
+
+        gif_file.frames[0].frame_indices = vec![0, 1, 2, 3, 0];
+        gif_file.global_color_table = None;
+        assert_eq!(
+            kinds(validate(&gif_file)),
+            vec![
+                PixelStreamTooLong {
+                    expected: 4,
+                    actual: 5
+                },
+                MissingColorTable,
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        assert_eq!(
+            kinds(validate_bytes(b"%PDF-1.4")),
+            vec![ParseError("Not a GIF file, detected PDF document!".into())]
+        );
+
+        // Stuff an extra sub-block into the image data, after the EOI
+        let mut bytes = gif_file().to_bytes();
+        let trailer = bytes.pop().unwrap();
+        bytes.pop();
+        bytes.extend([0x02, 0xAB, 0xCD, 0x00, trailer]);
+        assert_eq!(
+            validate_bytes(&bytes),
+            vec![Diagnostic {
+                frame: Some(0),
+                kind: DataAfterEndOfInformation
+            }]
+        );
+
+        let mut long_stream = gif_file();
+        let expected = long_stream.frames[0].frame_indices.len();
+        long_stream.frames[0].frame_indices.extend([0; 10]);
+        assert_eq!(
+            validate_bytes(&long_stream.to_bytes()),
+            vec![Diagnostic {
+                frame: Some(0),
+                kind: PixelStreamTooLong {
+                    expected,
+                    actual: expected + 10
+                }
+            }]
+        );
+    }
+}
@@ -0,0 +1,640 @@
+//! End-to-end conformance tests: every GIF in `tests/golden/` is decoded and
+//! rendered, and each composited frame is compared against the RGBA hash in
+//! `tests/golden/expected.txt`.
+//!
+//! The corpus is generated by `regenerate_golden_files`, run it with
+//! `cargo test --test golden -- --ignored` after adding a file or after an
+//! intentional rendering change, and check the diff of `expected.txt`.
+//!
+//! Since that corpus comes from our own encoder, `tests/golden/external/` has
+//! GIFs written by giflib (see `generate_giflib.py` there), and every file of
+//! both corpora must render like the `gif` crate decodes it.
+
+use gif_me_hd::decoder::{
+    compressed_frames, DecodeLimits, DisposalMethod, Extension, GifFile, GifFrame, GifHeader,
+    ImageDescriptor, LogicalScreenDescriptor, Pixel,
+};
+use gif_me_hd::optimize::{optimize_frames, optimize_palettes};
+use gif_me_hd::render::{interlaced_rows, render, RenderOptions, RgbaFrame};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+// 64-bit FNV-1a, good enough to tell renderings apart and stable across platforms
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+// One line per rendered frame: "<file> <frame> <width>x<height> <delay> <hash>"
+fn render_hashes(name: &str, bytes: &[u8]) -> Vec<String> {
+    let gif_file = GifFile::new(bytes).unwrap_or_else(|err| panic!("{}: {}", name, err));
+    let frames = render(&gif_file, RenderOptions::default())
+        .unwrap_or_else(|err| panic!("{}: {}", name, err));
+    frames
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| {
+            format!(
+                "{} {} {}x{} {} {:016x}",
+                name,
+                i,
+                frame.width,
+                frame.height,
+                frame.delay_timer,
+                fnv1a(&frame.pixels)
+            )
+        })
+        .collect()
+}
+
+fn palette(len: usize, seed: u8) -> Vec<Pixel> {
+    (0..len)
+        .map(|i| Pixel {
+            red: (i as u8).wrapping_mul(37).wrapping_add(seed),
+            green: (i as u8).wrapping_mul(91),
+            blue: (i as u8).wrapping_mul(13).wrapping_add(seed / 2),
+        })
+        .collect()
+}
+
+fn gce(disposal_method: DisposalMethod, transparent: Option<u8>, delay_timer: u16) -> Extension {
+    Extension::GraphicsControlExtension {
+        reserved: 0,
+        disposal_method,
+        user_input_flag: false,
+        transparent_color_flag: transparent.is_some(),
+        delay_timer,
+        transparent_color_index: transparent.unwrap_or(0),
+    }
+}
+
+fn netscape_loop(count: u16) -> Extension {
+    let [low, high] = count.to_le_bytes();
+    Extension::Application {
+        identifier: *b"NETSCAPE",
+        authentication_code: *b"2.0",
+        data: vec![vec![0x01, low, high]],
+    }
+}
+
+// A frame filled by `pixel(x, y)`, stored interlaced if asked to
+fn frame(
+    (left, top, width, height): (u16, u16, u16, u16),
+    local_color_table: Option<Vec<Pixel>>,
+    extensions: Vec<Extension>,
+    interlace_flag: bool,
+    pixel: impl Fn(usize, usize) -> u8,
+) -> GifFrame {
+    let rows: Vec<usize> = if interlace_flag {
+        interlaced_rows(height as usize)
+    } else {
+        (0..height as usize).collect()
+    };
+    let frame_indices = rows
+        .into_iter()
+        .flat_map(|y| (0..width as usize).map(move |x| (x, y)))
+        .map(|(x, y)| pixel(x, y))
+        .collect();
+    GifFrame {
+        image_descriptor: ImageDescriptor {
+            left,
+            top,
+            width,
+            height,
+            local_color_table_flag: local_color_table.is_some(),
+            interlace_flag,
+            sort_flag: false,
+            reserved: 0,
+            local_color_table_size: local_color_table
+                .as_ref()
+                .map_or(0, |lct| lct.len().trailing_zeros() as u8 - 1),
+        },
+        local_color_table,
+        frame_indices,
+        extensions,
+    }
+}
+
+fn gif_file(
+    (width, height): (u16, u16),
+    global_color_table: Option<Vec<Pixel>>,
+    frames: Vec<GifFrame>,
+    trailing_extensions: Vec<Extension>,
+) -> GifFile {
+    GifFile {
+        header: GifHeader::GIF89a,
+        logical_screen_descriptor: LogicalScreenDescriptor {
+            canvas_width: width,
+            canvas_height: height,
+            global_color_table_flag: global_color_table.is_some(),
+            color_resolution: 7,
+            sort_flag: false,
+            global_color_table_size: global_color_table
+                .as_ref()
+                .map_or(0, |gct| gct.len().trailing_zeros() as u16 - 1),
+            background_color_index: 0,
+            pixel_aspect_ratio: 0,
+        },
+        global_color_table,
+        frames,
+        trailing_extensions,
+    }
+}
+
+// Four overlapping frames, the middle two using `disposal_method`
+fn disposal(disposal_method: DisposalMethod) -> GifFile {
+    gif_file(
+        (12, 12),
+        Some(palette(4, 0)),
+        vec![
+            frame(
+                (0, 0, 12, 12),
+                None,
+                vec![gce(DisposalMethod::DoNotDispose, None, 10)],
+                false,
+                |x, y| ((x / 3 + y / 3) % 2) as u8,
+            ),
+            frame(
+                (2, 2, 6, 6),
+                None,
+                vec![gce(disposal_method, Some(0), 10)],
+                false,
+                |x, y| if (x + y) % 3 == 0 { 0 } else { 2 },
+            ),
+            frame(
+                (5, 5, 6, 6),
+                None,
+                vec![gce(disposal_method, None, 10)],
+                false,
+                |x, _| 3 - (x % 2) as u8,
+            ),
+            frame(
+                (4, 0, 4, 12),
+                None,
+                vec![gce(DisposalMethod::NoDisposal, Some(1), 10)],
+                false,
+                |_, y| (y % 2) as u8,
+            ),
+        ],
+        Vec::new(),
+    )
+}
+
+// Deterministic noise, so that the LZW table fills up and gets cleared
+fn noise(seed: u32) -> impl Fn(usize, usize) -> u8 {
+    move |x, y| {
+        let mut state = seed ^ (x as u32).wrapping_mul(0x9E37_79B9) ^ (y as u32) << 16;
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        (state >> 24) as u8
+    }
+}
+
+fn golden_files() -> Vec<(&'static str, GifFile)> {
+    let mut files = vec![
+        ("disposal_none.gif", disposal(DisposalMethod::NoDisposal)),
+        (
+            "disposal_do_not_dispose.gif",
+            disposal(DisposalMethod::DoNotDispose),
+        ),
+        (
+            "disposal_restore_background.gif",
+            disposal(DisposalMethod::RestoreToBackground),
+        ),
+        (
+            "disposal_restore_previous.gif",
+            disposal(DisposalMethod::RestoreToPrevious),
+        ),
+        (
+            "disposal_undefined.gif",
+            disposal(DisposalMethod::Undefined(5)),
+        ),
+    ];
+
+    // 19 rows so that every interlace pass has a partial group
+    files.push((
+        "interlaced.gif",
+        gif_file(
+            (16, 19),
+            Some(palette(16, 7)),
+            vec![frame((0, 0, 16, 19), None, Vec::new(), true, |x, y| {
+                ((x + 2 * y) % 16) as u8
+            })],
+            Vec::new(),
+        ),
+    ));
+
+    files.push((
+        "local_color_tables.gif",
+        gif_file(
+            (8, 8),
+            Some(palette(2, 0)),
+            vec![
+                frame(
+                    (0, 0, 8, 8),
+                    Some(palette(8, 100)),
+                    vec![gce(DisposalMethod::NoDisposal, None, 5)],
+                    false,
+                    |x, y| ((x * y) % 8) as u8,
+                ),
+                // Back to the Global Color Table
+                frame(
+                    (2, 2, 4, 4),
+                    None,
+                    vec![gce(DisposalMethod::NoDisposal, None, 5)],
+                    false,
+                    |x, _| (x % 2) as u8,
+                ),
+                frame(
+                    (0, 4, 8, 4),
+                    Some(palette(256, 200)),
+                    vec![gce(DisposalMethod::NoDisposal, None, 5)],
+                    false,
+                    |x, y| (x * 31 + y * 7) as u8,
+                ),
+            ],
+            Vec::new(),
+        ),
+    ));
+
+    files.push((
+        "transparency.gif",
+        gif_file(
+            (10, 10),
+            Some(palette(4, 50)),
+            vec![
+                // Transparent holes straight onto the empty canvas
+                frame(
+                    (0, 0, 10, 10),
+                    None,
+                    vec![gce(DisposalMethod::DoNotDispose, Some(3), 10)],
+                    false,
+                    |x, y| ((x + y) % 4) as u8,
+                ),
+                frame(
+                    (0, 0, 10, 10),
+                    None,
+                    vec![gce(DisposalMethod::DoNotDispose, Some(0), 10)],
+                    false,
+                    |x, _| (x % 3) as u8,
+                ),
+            ],
+            Vec::new(),
+        ),
+    ));
+
+    files.push((
+        "clear_codes.gif",
+        gif_file(
+            (96, 96),
+            Some(palette(256, 9)),
+            vec![frame((0, 0, 96, 96), None, Vec::new(), false, noise(1))],
+            Vec::new(),
+        ),
+    ));
+
+    // One frame per minimum code size, each with a Local Color Table of 2^n colors
+    files.push((
+        "code_sizes.gif",
+        gif_file(
+            (24, 24),
+            None,
+            (1..=8)
+                .map(|bits| {
+                    let colors = 1usize << bits;
+                    let noise = noise(bits);
+                    frame(
+                        (bits as u16 * 2, bits as u16 * 2, 8, 8),
+                        Some(palette(colors, bits as u8 * 20)),
+                        vec![gce(DisposalMethod::NoDisposal, None, 2)],
+                        false,
+                        move |x, y| (noise(x, y) as usize % colors) as u8,
+                    )
+                })
+                .collect(),
+            Vec::new(),
+        ),
+    ));
+
+    files.push((
+        "comments_and_applications.gif",
+        gif_file(
+            (6, 4),
+            Some(palette(4, 30)),
+            vec![
+                frame(
+                    (0, 0, 6, 4),
+                    None,
+                    vec![
+                        netscape_loop(0),
+                        Extension::Comment {
+                            text: "golden comment".into(),
+                        },
+                        Extension::Application {
+                            identifier: *b"GIFMEHD0",
+                            authentication_code: *b"1.0",
+                            data: vec![(0..255).collect(), vec![255]],
+                        },
+                        gce(DisposalMethod::NoDisposal, None, 25),
+                    ],
+                    false,
+                    |x, y| ((x + y) % 4) as u8,
+                ),
+                frame(
+                    (1, 1, 4, 2),
+                    None,
+                    vec![
+                        Extension::PlainText {
+                            text_grid_left: 0,
+                            text_grid_top: 0,
+                            text_grid_width: 6,
+                            text_grid_height: 4,
+                            cell_width: 6,
+                            cell_height: 4,
+                            text_foreground_color_index: 1,
+                            text_background_color_index: 0,
+                            text: "x".into(),
+                        },
+                        gce(DisposalMethod::NoDisposal, None, 25),
+                    ],
+                    false,
+                    |_, _| 2,
+                ),
+            ],
+            vec![Extension::Comment {
+                text: "after the last image".into(),
+            }],
+        ),
+    ));
+
+    // Falls back to the default black, white, gray palette
+    files.push((
+        "no_color_table.gif",
+        gif_file(
+            (4, 4),
+            None,
+            vec![frame((0, 0, 4, 4), None, Vec::new(), false, |x, y| {
+                (x * 4 + y) as u8 * 16
+            })],
+            Vec::new(),
+        ),
+    ));
+
+    // Clipped to the canvas
+    files.push((
+        "frame_outside_canvas.gif",
+        gif_file(
+            (6, 6),
+            Some(palette(4, 80)),
+            vec![frame((3, 2, 6, 6), None, Vec::new(), false, |x, y| {
+                ((x + y) % 4) as u8
+            })],
+            Vec::new(),
+        ),
+    ));
+
+    let mut gif87a = gif_file(
+        (5, 3),
+        Some(palette(2, 60)),
+        vec![frame((0, 0, 5, 3), None, Vec::new(), false, |x, _| {
+            (x % 2) as u8
+        })],
+        Vec::new(),
+    );
+    gif87a.header = GifHeader::GIF87a;
+    files.push(("gif87a.gif", gif87a));
+    files
+}
+
+#[test]
+#[ignore]
+fn regenerate_golden_files() {
+    let dir = golden_dir();
+    fs::create_dir_all(&dir).unwrap();
+    let mut expected = Vec::new();
+    for (name, gif_file) in golden_files() {
+        let bytes = gif_file.to_bytes();
+        expected.extend(render_hashes(name, &bytes));
+        fs::write(dir.join(name), bytes).unwrap();
+    }
+    expected.push(String::new());
+    fs::write(dir.join("expected.txt"), expected.join("\n")).unwrap();
+}
+
+#[test]
+fn golden_renderings() {
+    let dir = golden_dir();
+    let expected = fs::read_to_string(dir.join("expected.txt")).unwrap();
+    let expected: Vec<&str> = expected.lines().collect();
+    let mut actual = Vec::new();
+    for (name, _) in golden_files() {
+        let bytes = fs::read(dir.join(name)).unwrap();
+        actual.extend(render_hashes(name, &bytes));
+    }
+    // Compared line by line so that a failure names the file and frame
+    for (actual, expected) in actual.iter().zip(&expected) {
+        assert_eq!(actual, expected);
+    }
+    assert_eq!(actual.len(), expected.len());
+}
+
+// Makes sure the corpus still exercises what the file names claim
+#[test]
+fn golden_corpus_coverage() {
+    let dir = golden_dir();
+    let read = |name: &str| fs::read(dir.join(name)).unwrap();
+
+    let stats = GifFile::stats(&read("clear_codes.gif"), &DecodeLimits::default()).unwrap();
+    assert!(stats[0].clear_codes > 1);
+    assert_eq!(stats[0].max_code_size, 12);
+
+    let code_sizes: Vec<u8> = compressed_frames(&read("code_sizes.gif"))
+        .unwrap()
+        .iter()
+        .map(|frame| frame.lzw_minimum_code_size)
+        .collect();
+    assert_eq!(code_sizes, vec![2, 2, 3, 4, 5, 6, 7, 8]);
+
+    let interlaced = GifFile::new(&read("interlaced.gif")).unwrap();
+    assert!(interlaced.frames[0].image_descriptor.interlace_flag);
+
+    let extensions = GifFile::new(&read("comments_and_applications.gif")).unwrap();
+    assert_eq!(extensions.frames[0].extensions.len(), 4);
+    assert!(matches!(
+        extensions.frames[1].extensions[0],
+        Extension::PlainText { .. }
+    ));
+    assert_eq!(extensions.trailing_extensions.len(), 1);
+
+    let stats = GifFile::stats(
+        &read("external/giflib_clear_codes.gif"),
+        &DecodeLimits::default(),
+    )
+    .unwrap();
+    assert!(stats.iter().all(|stats| stats.clear_codes > 1));
+    let interlaced = GifFile::new(&read("external/giflib_interlaced.gif")).unwrap();
+    assert!(interlaced.global_color_table.is_none());
+    assert!(interlaced.frames[0].image_descriptor.interlace_flag);
+    let animation = GifFile::new(&read("external/giflib_animation.gif")).unwrap();
+    let disposals: Vec<DisposalMethod> = animation
+        .frames
+        .iter()
+        .map(GifFrame::disposal_method)
+        .collect();
+    assert_eq!(
+        disposals,
+        vec![
+            DisposalMethod::DoNotDispose,
+            DisposalMethod::RestoreToBackground,
+            DisposalMethod::RestoreToPrevious,
+            DisposalMethod::NoDisposal,
+            DisposalMethod::DoNotDispose,
+        ]
+    );
+
+    assert_eq!(
+        &read("gif87a.gif")[..6],
+        b"GIF87a",
+        "the encoder should pick GIF87a when no GIF89a feature is used"
+    );
+    for (name, expected) in [
+        ("disposal_none.gif", DisposalMethod::NoDisposal),
+        ("disposal_do_not_dispose.gif", DisposalMethod::DoNotDispose),
+        (
+            "disposal_restore_background.gif",
+            DisposalMethod::RestoreToBackground,
+        ),
+        (
+            "disposal_restore_previous.gif",
+            DisposalMethod::RestoreToPrevious,
+        ),
+        ("disposal_undefined.gif", DisposalMethod::Undefined(5)),
+    ] {
+        let gif_file = GifFile::new(&read(name)).unwrap();
+        assert_eq!(gif_file.frames[1].disposal_method(), expected, "{}", name);
+    }
+}
+
+// Composites the frames decoded by the `gif` crate, independently from gif_me_hd
+fn reference_render(bytes: &[u8]) -> Vec<RgbaFrame> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(bytes).unwrap();
+    let (width, height) = (decoder.width() as usize, decoder.height() as usize);
+    let mut canvas = vec![0; width * height * 4];
+    let mut previous = None;
+    let mut pending_disposal = None;
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        let (left, top) = (frame.left as usize, frame.top as usize);
+        let (frame_width, frame_height) = (frame.width as usize, frame.height as usize);
+        let visible = |x: usize, y: usize| x < width && y < height;
+        match pending_disposal.take() {
+            Some((gif::DisposalMethod::Background, (l, t, w, h))) => {
+                for y in t..t + h {
+                    for x in (l..l + w).filter(|&x| visible(x, y)) {
+                        canvas[(y * width + x) * 4..][..4].fill(0);
+                    }
+                }
+            }
+            Some((gif::DisposalMethod::Previous, _)) => {
+                if let Some(previous) = previous.take() {
+                    canvas = previous;
+                }
+            }
+            _ => {}
+        }
+        if frame.dispose == gif::DisposalMethod::Previous {
+            previous = Some(canvas.clone());
+        }
+        for (i, pixel) in frame.buffer.chunks(4).enumerate() {
+            let (x, y) = (left + i % frame_width, top + i / frame_width);
+            if pixel[3] != 0 && visible(x, y) {
+                canvas[(y * width + x) * 4..][..4].copy_from_slice(pixel);
+            }
+        }
+        pending_disposal = Some((frame.dispose, (left, top, frame_width, frame_height)));
+        frames.push(RgbaFrame {
+            width,
+            height,
+            pixels: canvas.clone(),
+            delay_timer: frame.delay,
+        });
+    }
+    frames
+}
+
+#[test]
+fn independent_decoder() {
+    let external: Vec<PathBuf> = fs::read_dir(golden_dir().join("external"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "gif"))
+        .collect();
+    assert!(external.len() >= 4);
+    let own = golden_files()
+        .into_iter()
+        // The `gif` crate refuses frames without a color table
+        .filter(|(name, _)| *name != "no_color_table.gif")
+        .map(|(name, _)| golden_dir().join(name));
+    for path in external.into_iter().chain(own) {
+        let bytes = fs::read(&path).unwrap();
+        let gif_file = GifFile::new(&bytes).unwrap();
+        let rendered = render(&gif_file, RenderOptions::default()).unwrap();
+        let expected = reference_render(&bytes);
+        assert_eq!(rendered.len(), expected.len(), "{}", path.display());
+        for (i, (rendered, expected)) in rendered.iter().zip(&expected).enumerate() {
+            assert!(
+                rendered == expected,
+                "{} frame {} differs from the gif crate",
+                path.display(),
+                i
+            );
+        }
+    }
+}
+
+// Rendered canvases and how long they show, consecutive identical ones merged
+fn timeline(bytes: &[u8]) -> Vec<(Vec<u8>, u32)> {
+    let gif_file = GifFile::new(bytes).unwrap();
+    let mut timeline: Vec<(Vec<u8>, u32)> = Vec::new();
+    for frame in render(&gif_file, RenderOptions::default()).unwrap() {
+        // Frames without a delay are shown for a while by browsers, so they count
+        let timed = frame.delay_timer > 0;
+        match timeline.last_mut() {
+            Some((pixels, delay)) if *pixels == frame.pixels && *delay > 0 && timed => {
+                *delay += frame.delay_timer as u32
+            }
+            _ => timeline.push((frame.pixels, frame.delay_timer as u32)),
+        }
+    }
+    timeline
+}
+
+#[test]
+fn optimized_renderings() {
+    let dir = golden_dir();
+    for (name, _) in golden_files() {
+        let bytes = fs::read(dir.join(name)).unwrap();
+        let mut gif_file = GifFile::new(&bytes).unwrap();
+        optimize_palettes(&mut gif_file);
+        assert!(
+            timeline(&gif_file.to_bytes()) == timeline(&bytes),
+            "{} looks different after optimizing palettes",
+            name
+        );
+        optimize_frames(&mut gif_file).unwrap_or_else(|err| panic!("{}: {}", name, err));
+        optimize_palettes(&mut gif_file);
+        assert!(
+            timeline(&gif_file.to_bytes()) == timeline(&bytes),
+            "{} looks different after optimizing",
+            name
+        );
+    }
+}
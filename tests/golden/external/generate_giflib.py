@@ -0,0 +1,135 @@
+#!/usr/bin/env python3
+"""Writes the GIFs of this directory with giflib (libgif.so.7), an encoder
+independent from gif_me_hd. `tests/golden.rs` checks that gif_me_hd renders
+them like the `gif` crate decodes them.
+
+Run it from anywhere with python3, the files are written next to it.
+"""
+
+import ctypes
+import os
+
+gif = ctypes.CDLL("libgif.so.7")
+gif.EGifOpenFileName.restype = ctypes.c_void_p
+gif.EGifOpenFileName.argtypes = [ctypes.c_char_p, ctypes.c_bool, ctypes.POINTER(ctypes.c_int)]
+gif.GifMakeMapObject.restype = ctypes.c_void_p
+gif.GifMakeMapObject.argtypes = [ctypes.c_int, ctypes.c_char_p]
+gif.GifFreeMapObject.argtypes = [ctypes.c_void_p]
+gif.EGifSetGifVersion.argtypes = [ctypes.c_void_p, ctypes.c_bool]
+gif.EGifPutScreenDesc.argtypes = [ctypes.c_void_p] + [ctypes.c_int] * 4 + [ctypes.c_void_p]
+gif.EGifPutExtension.argtypes = [ctypes.c_void_p, ctypes.c_int, ctypes.c_int, ctypes.c_char_p]
+gif.EGifPutExtensionLeader.argtypes = [ctypes.c_void_p, ctypes.c_int]
+gif.EGifPutExtensionBlock.argtypes = [ctypes.c_void_p, ctypes.c_int, ctypes.c_char_p]
+gif.EGifPutExtensionTrailer.argtypes = [ctypes.c_void_p]
+gif.EGifPutImageDesc.argtypes = [ctypes.c_void_p] + [ctypes.c_int] * 4 + [ctypes.c_bool, ctypes.c_void_p]
+gif.EGifPutLine.argtypes = [ctypes.c_void_p, ctypes.c_char_p, ctypes.c_int]
+gif.EGifCloseFile.argtypes = [ctypes.c_void_p, ctypes.POINTER(ctypes.c_int)]
+
+DIR = os.path.dirname(os.path.abspath(__file__))
+
+
+def check(result):
+    if result != 1:
+        raise RuntimeError("giflib call failed")
+
+
+def palette(colors, seed):
+    return bytes(
+        channel & 0xFF
+        for i in range(colors)
+        for channel in (i * 37 + seed, i * 91 + seed // 3, i * 13 + seed * 5)
+    )
+
+
+def noise(seed):
+    def pixel(x, y):
+        state = (seed * 2654435761 ^ x * 40503 ^ y * 9973) & 0xFFFFFFFF
+        state ^= (state << 13) & 0xFFFFFFFF
+        state ^= state >> 17
+        state ^= (state << 5) & 0xFFFFFFFF
+        return state >> 24
+
+    return pixel
+
+
+def interlaced_rows(height):
+    return [y for start, step in ((0, 8), (4, 8), (2, 4), (1, 2)) for y in range(start, height, step)]
+
+
+class Writer:
+    def __init__(self, name, width, height, global_color_table=None, background=0):
+        error = ctypes.c_int(0)
+        self.handle = gif.EGifOpenFileName(os.path.join(DIR, name).encode(), False, ctypes.byref(error))
+        if not self.handle:
+            raise RuntimeError("cannot open {}: {}".format(name, error.value))
+        gif.EGifSetGifVersion(self.handle, True)
+        color_map = self.color_map(global_color_table)
+        check(gif.EGifPutScreenDesc(self.handle, width, height, 8, background, color_map))
+
+    @staticmethod
+    def color_map(color_table):
+        if color_table is None:
+            return None
+        return gif.GifMakeMapObject(len(color_table) // 3, color_table)
+
+    def graphics_control(self, disposal, delay, transparent=None):
+        packed = disposal << 2 | (transparent is not None)
+        block = bytes([packed, delay & 0xFF, delay >> 8, transparent or 0])
+        check(gif.EGifPutExtension(self.handle, 0xF9, len(block), block))
+
+    def netscape_loop(self, count):
+        check(gif.EGifPutExtensionLeader(self.handle, 0xFF))
+        check(gif.EGifPutExtensionBlock(self.handle, 11, b"NETSCAPE2.0"))
+        check(gif.EGifPutExtensionBlock(self.handle, 3, bytes([1, count & 0xFF, count >> 8])))
+        check(gif.EGifPutExtensionTrailer(self.handle))
+
+    def comment(self, text):
+        check(gif.EGifPutExtension(self.handle, 0xFE, len(text), text))
+
+    def image(self, rect, pixel, local_color_table=None, interlace=False):
+        left, top, width, height = rect
+        color_map = self.color_map(local_color_table)
+        check(gif.EGifPutImageDesc(self.handle, left, top, width, height, interlace, color_map))
+        # giflib writes lines in the order given, interlacing is up to the caller
+        rows = interlaced_rows(height) if interlace else range(height)
+        for y in rows:
+            line = bytes(pixel(x, y) for x in range(width))
+            check(gif.EGifPutLine(self.handle, line, width))
+
+    def close(self):
+        error = ctypes.c_int(0)
+        check(gif.EGifCloseFile(self.handle, ctypes.byref(error)))
+
+
+# Looping animation with every disposal method, transparency and sub-rectangles
+animation = Writer("giflib_animation.gif", 16, 12, palette(8, 11))
+animation.netscape_loop(0)
+animation.graphics_control(1, 10)
+animation.image((0, 0, 16, 12), lambda x, y: (x // 4 + y // 3) % 8)
+animation.graphics_control(2, 20, transparent=0)
+animation.image((3, 2, 8, 6), lambda x, y: 0 if (x + y) % 3 == 0 else 5)
+animation.graphics_control(3, 15, transparent=7)
+animation.comment(b"made by giflib")
+animation.image((6, 4, 9, 7), lambda x, y: (x * y) % 8, palette(8, 140))
+animation.graphics_control(0, 0)
+animation.image((0, 8, 5, 4), lambda x, y: (x + 2 * y) % 8)
+animation.graphics_control(1, 30, transparent=2)
+animation.image((1, 1, 14, 10), lambda x, y: (x ^ y) % 4)
+animation.close()
+
+# Interlaced with only a Local Color Table, 23 rows so every pass ends early
+interlaced = Writer("giflib_interlaced.gif", 20, 23)
+interlaced.image((0, 0, 20, 23), lambda x, y: (x + 3 * y) % 32, palette(32, 70), interlace=True)
+interlaced.close()
+
+# Smallest code size
+two_colors = Writer("giflib_two_colors.gif", 9, 7, palette(2, 200))
+two_colors.image((0, 0, 9, 7), lambda x, y: (x * x + y) % 2)
+two_colors.close()
+
+# Enough noise for the LZW table to fill up and be cleared several times
+clear_codes = Writer("giflib_clear_codes.gif", 128, 128, palette(256, 3))
+clear_codes.image((0, 0, 128, 128), noise(7))
+clear_codes.graphics_control(1, 5)
+clear_codes.image((32, 16, 64, 96), noise(8), palette(256, 99))
+clear_codes.close()